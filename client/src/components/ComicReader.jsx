@@ -9,17 +9,21 @@ import { Alert, AlertDescription } from "@/components/ui/alert";
 import { useTheme } from 'next-themes';
 import JSZip from 'jszip';
 import ComicLibrary from './ComicLibrary';
+import { authFetch, hasSession, login, logout } from '@/lib/session';
 
 const PasswordDialog = ({ onSubmit, onCancel, isOpen, authError }) => {
+  const [username, setUsername] = useState('');
   const [password, setPassword] = useState('');
   const { theme } = useTheme();
 
   const handleSubmit = () => {
-    onSubmit(password);
+    onSubmit(`${username.trim()}:${password}`);
+    setUsername('');
     setPassword('');
   };
 
   const handleCancel = () => {
+    setUsername('');
     setPassword('');
     onCancel();
   };
@@ -35,12 +39,19 @@ const PasswordDialog = ({ onSubmit, onCancel, isOpen, authError }) => {
             className={`${theme === 'dark' ? 'bg-[#1a2234] text-white border-[#3a4258]' : ''} z-[100]`}
         >
           <DialogHeader>
-            <DialogTitle>Server Password Required</DialogTitle>
+            <DialogTitle>Server Login Required</DialogTitle>
           </DialogHeader>
-          <div className="py-4">
+          <div className="py-4 space-y-2">
+            <Input
+                type="text"
+                placeholder="Username (leave empty for admin)"
+                value={username}
+                onChange={(e) => setUsername(e.target.value)}
+                className={theme === 'dark' ? 'bg-[#2a324a] border-[#3a4258] text-white' : ''}
+            />
             <Input
                 type="password"
-                placeholder="Enter server password"
+                placeholder="Password"
                 value={password}
                 onChange={(e) => setPassword(e.target.value)}
                 className={theme === 'dark' ? 'bg-[#2a324a] border-[#3a4258] text-white' : ''}
@@ -75,7 +86,6 @@ const ComicReader = () => {
   const [serverUrl, setServerUrl] = useState('');
   const [isServerDialogOpen, setIsServerDialogOpen] = useState(false);
   const [isLibraryOpen, setIsLibraryOpen] = useState(true);
-  const [serverPasswords, setServerPasswords] = useState({});
   const [authError, setAuthError] = useState('');
   const [isPasswordDialogOpen, setIsPasswordDialogOpen] = useState(false);
   const scrollContainerRef = useRef(null);
@@ -88,14 +98,14 @@ const ComicReader = () => {
     const initializeLibrary = async () => {
       try {
         const servers = JSON.parse(localStorage.getItem('comicReaderServers')) || [];
-        const savedPasswords = JSON.parse(localStorage.getItem('comicReaderPasswords')) || {};
+        // Older versions stored plain passwords; trade them for sessions once
+        const legacyPasswords = JSON.parse(localStorage.getItem('comicReaderPasswords')) || {};
+        localStorage.removeItem('comicReaderPasswords');
         setSavedServers(servers);
-        setServerPasswords(savedPasswords);
 
         for (const server of servers) {
           console.log('Loading comics from server:', server);
-          const password = savedPasswords[server];
-          await loadServerComics(server, password);
+          await loadServerComics(server, legacyPasswords[server]);
         }
       } catch (error) {
         console.error('Error initializing library:', error);
@@ -108,23 +118,17 @@ const ComicReader = () => {
 
   useEffect(() => {
     localStorage.setItem('comicReaderServers', JSON.stringify(savedServers));
-    localStorage.setItem('comicReaderPasswords', JSON.stringify(serverPasswords));
-  }, [savedServers, serverPasswords]);
+  }, [savedServers]);
 
-  const loadCover = async (comic, password) => {
+  const loadCover = async (comic) => {
     if (comic.type !== 'remote' || comic.cover) return;
 
     try {
-      const headers = {};
-      const authPassword = password || serverPasswords[comic.serverUrl];
-      if (authPassword) {
-        const base64Auth = btoa(`:${authPassword}`);
-        headers.Authorization = `Basic ${base64Auth}`;
-      }
-
       const comicId = comic.id.replace('remote-', '');
       const img = new Image();
-      const coverUrl = `${comic.serverUrl}/covers/${encodeURIComponent(comicId)}`;
+      // <img> cannot send credentials, so protected servers hand out a signed token
+      const tokenQuery = comic.access_token ? `?token=${encodeURIComponent(comic.access_token)}` : '';
+      const coverUrl = `${comic.serverUrl}/covers/${encodeURIComponent(comicId)}${tokenQuery}`;
 
       const loadPromise = new Promise((resolve, reject) => {
         img.onload = () => resolve(coverUrl);
@@ -142,10 +146,10 @@ const ComicReader = () => {
     }
   };
 
-  const loadCoverWithRetry = async (comic, password, retries = 3) => {
+  const loadCoverWithRetry = async (comic, retries = 3) => {
     for (let attempt = 0; attempt < retries; attempt++) {
       try {
-        await loadCover(comic, password);
+        await loadCover(comic);
         return;
       } catch (error) {
         if (attempt === retries - 1) {
@@ -159,7 +163,6 @@ const ComicReader = () => {
 
   useEffect(() => {
     const loadCovers = async () => {
-      const savedPasswords = JSON.parse(localStorage.getItem('comicReaderPasswords')) || {};
       const batchSize = 5;
       const comicsNeedingCovers = library.filter(comic =>
           comic.type === 'remote' && !comic.cover
@@ -168,9 +171,7 @@ const ComicReader = () => {
       for (let i = 0; i < comicsNeedingCovers.length; i += batchSize) {
         const batch = comicsNeedingCovers.slice(i, i + batchSize);
         await Promise.all(
-            batch.map(comic =>
-                loadCoverWithRetry(comic, savedPasswords[comic.serverUrl])
-            )
+            batch.map(comic => loadCoverWithRetry(comic))
         );
       }
     };
@@ -187,7 +188,7 @@ const ComicReader = () => {
     };
   }, [images]);
 
-  const loadServerComics = async (serverUrl, password = null) => {
+  const loadServerComics = async (serverUrl, credentials = null) => {
     setLoading(true);
     setError(null);
 
@@ -200,9 +201,16 @@ const ComicReader = () => {
       }
 
       const { requires_password } = await authCheckResponse.json();
-      console.log('Auth check:', { requires_password, password });
+      console.log('Auth check:', { requires_password });
 
-      if (requires_password && !password) {
+      if (requires_password && credentials) {
+        if (!await login(normalizedUrl, credentials)) {
+          setAuthError('Invalid username or password');
+          setPendingServerUrl(normalizedUrl);
+          setIsPasswordDialogOpen(true);
+          return false;
+        }
+      } else if (requires_password && !hasSession(normalizedUrl)) {
         setIsLibraryOpen(false);
         setPendingServerUrl(normalizedUrl);
         setIsPasswordDialogOpen(true);
@@ -210,17 +218,11 @@ const ComicReader = () => {
         return 'password-required';
       }
 
-      const headers = {};
-      if (password || serverPasswords[normalizedUrl]) {
-        const authPassword = password || serverPasswords[normalizedUrl];
-        const base64Auth = btoa(`:${authPassword}`);
-        headers.Authorization = `Basic ${base64Auth}`;
-      }
-
-      const response = await fetch(`${normalizedUrl}/comics`, { headers });
+      const response = await authFetch(normalizedUrl, '/comics');
 
       if (response.status === 401) {
-        setAuthError('Invalid password');
+        setAuthError('Your session has expired, please sign in again');
+        setPendingServerUrl(normalizedUrl);
         setIsPasswordDialogOpen(true);
         setLoading(false);
         return false;
@@ -233,7 +235,7 @@ const ComicReader = () => {
       const comics = await response.json();
       const serverComics = comics.map(comic => ({
         ...comic,
-        id: comic.file_name,
+        id: `remote-${comic.id}`,
         type: 'remote',
         serverUrl: normalizedUrl,
         cover: null,
@@ -247,13 +249,6 @@ const ComicReader = () => {
         return [...filteredLibrary, ...serverComics];
       });
 
-      if (password) {
-        setServerPasswords(prev => ({
-          ...prev,
-          [normalizedUrl]: password
-        }));
-      }
-
       return true;
     } catch (error) {
       console.error('Server connection error:', error);
@@ -283,11 +278,11 @@ const ComicReader = () => {
     }
   };
 
-  const handlePasswordSubmit = async (password) => {
+  const handlePasswordSubmit = async (credentials) => {
     setAuthError('');
 
     try {
-      const result = await loadServerComics(pendingServerUrl, password);
+      const result = await loadServerComics(pendingServerUrl, credentials);
 
       if (result === true) {
         setIsPasswordDialogOpen(false);
@@ -344,24 +339,8 @@ const ComicReader = () => {
     setError(null);
     try {
       if (comic.type === 'remote') {
-        const headers = {};
-        const savedPasswords = JSON.parse(localStorage.getItem('comicReaderPasswords')) || {};
-        const password = savedPasswords[comic.serverUrl] || serverPasswords[comic.serverUrl];
-
-        if (password) {
-          const base64Auth = btoa(`:${password}`);
-          headers.Authorization = `Basic ${base64Auth}`;
-          headers.Accept = 'application/zip';
-        }
-
-      const comicId = comic.id.replace('remote-', '');
-      const folderPath = comic.folder_path && comic.folder_path.length > 0 
-        ? comic.folder_path.join('/') + '/' 
-        : '';
-      const url = `${comic.serverUrl}/comics/${encodeURIComponent(folderPath + comicId)}`;
-      console.log('Fetching comic from:', url);
-
-        const response = await fetch(url, { headers });
+        const comicId = encodeURIComponent(comic.id.replace('remote-', ''));
+        const response = await authFetch(comic.serverUrl, `/comics/${comicId}/pages`);
 
         if (response.status === 401) {
           setPendingServerUrl(comic.serverUrl);
@@ -371,19 +350,12 @@ const ComicReader = () => {
 
         if (!response.ok) throw new Error('Failed to fetch comic');
 
-        const blob = await response.blob();
-        const zip = new JSZip();
-        const zipContent = await zip.loadAsync(blob);
-
-        const imageFiles = Object.entries(zipContent.files)
-            .filter(([name, entry]) => !entry.dir && name.match(/\.(jpg|jpeg|png|gif)$/i))
-            .sort(([a], [b]) => a.localeCompare(b, undefined, { numeric: true }));
-
-        const imageUrls = await Promise.all(
-            imageFiles.map(async ([_, entry]) => {
-              const blob = await entry.async('blob');
-              return URL.createObjectURL(blob);
-            })
+        // Pages are streamed one by one instead of downloading the archive,
+        // which also covers formats the browser cannot unpack
+        const pages = await response.json();
+        const tokenQuery = comic.access_token ? `?token=${encodeURIComponent(comic.access_token)}` : '';
+        const imageUrls = pages.map(page =>
+            `${comic.serverUrl}/comics/${comicId}/pages/${page.index}${tokenQuery}`
         );
 
         setImages(imageUrls);
@@ -506,11 +478,7 @@ const ComicReader = () => {
                                       size="sm"
                                       onClick={() => {
                                         setSavedServers(prev => prev.filter(s => s !== server));
-                                        setServerPasswords(prev => {
-                                          const newPasswords = { ...prev };
-                                          delete newPasswords[server];
-                                          return newPasswords;
-                                        });
+                                        logout(server);
                                         setLibrary(prev => prev.filter(comic =>
                                             comic.type !== 'remote' || comic.serverUrl !== server
                                         ));
@@ -610,6 +578,7 @@ const ComicReader = () => {
                                   <img
                                       src={imgSrc}
                                       alt={`Page ${index + 1}`}
+                                      loading="lazy"
                                       className="max-w-full h-auto transition-transform duration-200"
                                       style={{
                                         transform: `scale(${zoom})`,
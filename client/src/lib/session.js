@@ -0,0 +1,92 @@
+const SESSIONS_KEY = 'comicReaderSessions';
+
+// Refresh a little before the access token actually runs out
+const EXPIRY_MARGIN_MS = 30 * 1000;
+
+const loadSessions = () => JSON.parse(localStorage.getItem(SESSIONS_KEY)) || {};
+
+const storeSession = (serverUrl, session) => {
+  const sessions = loadSessions();
+  if (session) {
+    sessions[serverUrl] = session;
+  } else {
+    delete sessions[serverUrl];
+  }
+  localStorage.setItem(SESSIONS_KEY, JSON.stringify(sessions));
+};
+
+const toSession = (tokens) => ({
+  accessToken: tokens.access_token,
+  refreshToken: tokens.refresh_token,
+  expiresAt: Date.now() + tokens.expires_in * 1000,
+});
+
+export const hasSession = (serverUrl) => Boolean(loadSessions()[serverUrl]);
+
+// Credentials are `username:password`; a bare password signs in as the admin.
+export const login = async (serverUrl, credentials) => {
+  const separator = credentials.indexOf(':');
+  const username = separator >= 0 ? credentials.slice(0, separator) : '';
+  const password = separator >= 0 ? credentials.slice(separator + 1) : credentials;
+
+  const response = await fetch(`${serverUrl}/auth/login`, {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify({ username, password }),
+  });
+  if (response.status === 401) return false;
+  if (!response.ok) throw new Error('Login failed');
+
+  storeSession(serverUrl, toSession(await response.json()));
+  return true;
+};
+
+const refresh = async (serverUrl, session) => {
+  const response = await fetch(`${serverUrl}/auth/refresh`, {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify({ refresh_token: session.refreshToken }),
+  });
+  const refreshed = response.ok ? toSession(await response.json()) : null;
+  storeSession(serverUrl, refreshed);
+  return refreshed;
+};
+
+export const logout = async (serverUrl) => {
+  const session = loadSessions()[serverUrl];
+  storeSession(serverUrl, null);
+  if (!session) return;
+
+  try {
+    await fetch(`${serverUrl}/auth/logout`, {
+      method: 'POST',
+      headers: { Authorization: `Bearer ${session.accessToken}` },
+    });
+  } catch (error) {
+    console.error('Logout failed:', error);
+  }
+};
+
+// fetch() with the server's session attached, refreshing it once when the
+// access token has expired
+export const authFetch = async (serverUrl, path, options = {}) => {
+  let session = loadSessions()[serverUrl];
+  if (session && session.expiresAt - EXPIRY_MARGIN_MS <= Date.now()) {
+    session = await refresh(serverUrl, session);
+  }
+
+  const send = (current) => fetch(`${serverUrl}${path}`, {
+    ...options,
+    headers: {
+      ...options.headers,
+      ...(current ? { Authorization: `Bearer ${current.accessToken}` } : {}),
+    },
+  });
+
+  let response = await send(session);
+  if (response.status === 401 && session) {
+    session = await refresh(serverUrl, session);
+    if (session) response = await send(session);
+  }
+  return response;
+};
@@ -1,4 +1,9 @@
 pub struct AppConfig {
     pub comics_dir: String,
     pub server_password: Option<String>,
+    /// Account created from `server_password` when there are none yet.
+    pub admin_username: String,
+    pub cover_cache_dir: String,
+    pub database_path: String,
+    pub url_signing_key_file: String,
 }
\ No newline at end of file
@@ -6,9 +6,15 @@ mod routes;
 mod services;
 mod utils;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use crate::config::AppConfig;
+use crate::routes::komga::SeriesCache;
+use crate::services::auth::UrlSigner;
 use crate::services::comic_service::ComicService;
+use crate::services::database::Database;
+use crate::services::progress::ProgressService;
+use crate::services::users::UserService;
 use crate::utils::cors::CORS;
 
 #[launch]
@@ -16,31 +22,141 @@ async fn rocket() -> rocket::Rocket<rocket::Build> {
     let comics_dir = std::env::var("COMICS_DIR")
         .unwrap_or_else(|_| String::from("/comics"));
     let server_password = std::env::var("SERVER_PASSWORD").ok();
+    let admin_username = std::env::var("ADMIN_USERNAME")
+        .unwrap_or_else(|_| String::from("admin"));
+    let data_dir = std::env::var("DATA_DIR")
+        .unwrap_or_else(|_| String::from("/data"));
+    let cover_cache_dir = std::env::var("COVER_CACHE_DIR")
+        .unwrap_or_else(|_| format!("{}/covers", data_dir));
+    let database_path = std::env::var("DATABASE_PATH")
+        .unwrap_or_else(|_| format!("{}/library.db", data_dir));
+    let url_signing_key_file = std::env::var("URL_SIGNING_KEY_FILE")
+        .unwrap_or_else(|_| format!("{}/url_signing.key", data_dir));
 
     let config = AppConfig {
-        comics_dir: comics_dir.clone(),
+        comics_dir,
         server_password,
+        admin_username,
+        cover_cache_dir,
+        database_path,
+        url_signing_key_file,
     };
 
+    let database = Arc::new(
+        Database::open(Path::new(&config.database_path))
+            .expect("Failed to open library database")
+    );
+
+    let user_service = UserService::new(database.clone());
+    let progress_service = ProgressService::new(database.clone());
+    if let Some(password) = &config.server_password {
+        user_service.bootstrap_admin(&config.admin_username, password)
+            .expect("Failed to create admin user");
+    }
+
     // Initialize comic service
-    let comic_service = ComicService::new(PathBuf::from(comics_dir))
+    let comic_service = ComicService::new(
+        PathBuf::from(&config.comics_dir),
+        PathBuf::from(&config.cover_cache_dir),
+        database,
+    )
         .await
         .expect("Failed to initialize comic service");
 
+    let url_signer = UrlSigner::load_or_create(Path::new(&config.url_signing_key_file))
+        .expect("Failed to load URL signing key");
+
     rocket::build()
         .attach(CORS)
         .manage(config)
         .manage(comic_service)
+        .manage(user_service)
+        .manage(progress_service)
+        .manage(url_signer)
+        .manage(SeriesCache::default())
         .mount("/", routes![
     routes::auth::check_auth,
     routes::auth::auth_check_options,
+    routes::auth::login,
+    routes::auth::login_options,
+    routes::auth::refresh,
+    routes::auth::refresh_options,
+    routes::auth::logout,
+    routes::auth::logout_options,
+    routes::auth::list_sessions,
+    routes::auth::sessions_options,
+    routes::auth::revoke_session,
+    routes::auth::session_options,
     routes::comics::list_comics,
     routes::comics::comics_options,
     routes::comics::get_cover,
     routes::comics::cover_options,
     routes::comics::get_comic,
     routes::comics::comic_options,
+    routes::comics::list_pages,
+    routes::comics::pages_options,
+    routes::comics::get_page,
+    routes::comics::page_options,
     routes::comics::get_folder_structure,
     routes::comics::folders_options,
+    routes::komga::list_libraries,
+    routes::komga::get_library,
+    routes::komga::list_series,
+    routes::komga::latest_series,
+    routes::komga::get_series,
+    routes::komga::series_thumbnail,
+    routes::komga::list_series_books,
+    routes::komga::get_tachiyomi_progress,
+    routes::komga::update_tachiyomi_progress,
+    routes::komga::get_book,
+    routes::komga::book_thumbnail,
+    routes::komga::list_book_pages,
+    routes::komga::get_book_page,
+    routes::komga::update_book_progress,
+    routes::komga::delete_book_progress,
+    routes::komga::komga_options,
+    routes::kosync::healthcheck,
+    routes::kosync::create_user,
+    routes::kosync::authorize,
+    routes::kosync::push_progress,
+    routes::kosync::get_progress,
+    routes::kosync::kosync_options,
+    routes::opds::catalog,
+    routes::opds::catalog_options,
+    routes::opds::folder_feed,
+    routes::opds::folder_comics_feed,
+    routes::opds::all_comics_feed,
+    routes::opds::search_feed,
+    routes::opds::opensearch,
+    routes::opds::opds_options,
+    routes::opds2::catalog,
+    routes::opds2::folder_feed,
+    routes::opds2::series_feed,
+    routes::opds2::publications,
+    routes::progress::list_progress,
+    routes::progress::progress_list_options,
+    routes::progress::get_progress,
+    routes::progress::save_progress,
+    routes::progress::delete_progress,
+    routes::progress::progress_options,
+    routes::progress::mark_folder_read,
+    routes::progress::mark_folder_unread,
+    routes::progress::folder_read_options,
+    routes::progress::mark_series_read,
+    routes::progress::mark_series_unread,
+    routes::progress::series_read_options,
+    routes::users::list_users,
+    routes::users::create_user,
+    routes::users::users_options,
+    routes::users::current_user,
+    routes::users::current_user_options,
+    routes::users::update_user,
+    routes::users::delete_user,
+    routes::users::user_options,
+    routes::users::change_password,
+    routes::users::password_options,
 ])
+        .register("/opds", catchers![routes::opds::unauthorized])
+        .register("/kosync", catchers![routes::kosync::unauthorized])
+        .register("/komga", catchers![routes::komga::unauthorized])
 }
\ No newline at end of file
@@ -1,5 +1,8 @@
 use serde::Serialize;
-use std::path::PathBuf;
+use std::path::Path;
+
+use crate::models::metadata::{ComicMetadata, PageMetadata};
+use crate::models::progress::{ReadCounts, ReadProgress};
 
 #[derive(Debug, Clone, Serialize)]
 pub struct Comic {
@@ -9,11 +12,36 @@ pub struct Comic {
     pub path: String,
     pub folder_path: Vec<String>,
     pub series: Option<String>,
+    pub format: String,
+    pub metadata: Option<ComicMetadata>,
+    /// Readable pages in the archive; 0 until first counted for comics
+    /// indexed before counts were kept.
+    pub page_count: usize,
+    /// Signed token for embedding this comic's cover, pages and download
+    /// in URLs, present when the server is password protected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<String>,
+    /// The requesting user's progress, when they have opened this comic.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_progress: Option<ReadProgress>,
+}
+
+/// A single readable image inside a comic archive.
+#[derive(Debug, Clone, Serialize)]
+pub struct Page {
+    pub index: usize,
+    pub name: String,
+    pub size: u64,
+    pub mime: String,
+    /// ComicInfo page type such as `FrontCover` or `Story`, if tagged.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_type: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub struct CoverImage {
     pub data: Vec<u8>,
+    pub mime: String,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -22,51 +50,80 @@ pub struct Folder {
     pub path: Vec<String>,
     pub comics: Vec<Comic>,
     pub subfolders: Vec<Folder>,
+    /// The requesting user's counts for every comic in and below this
+    /// folder.
+    pub read_counts: ReadCounts,
 }
 
 impl Comic {
-    pub fn from_path(base_dir: &PathBuf, full_path: &PathBuf) -> Option<Self> {
+    pub fn from_path(base_dir: &Path, full_path: &Path, format: &str) -> Option<Self> {
 
         let file_name = full_path.file_name()?.to_string_lossy().into_owned();
-        let name = full_path.file_stem()?.to_string_lossy().into_owned();
 
         // Calculate relative path from base_dir
         let relative_path = full_path.strip_prefix(base_dir).ok()?;
-        let parent_path = relative_path.parent();
-
-
-        // Create folder path and series name
-        let (folder_path, series) = if let Some(parent) = parent_path {
-            if parent.as_os_str().is_empty() {
-                (vec![], None)
-            } else {
-                let folder_path: Vec<String> = parent
-                    .components()
-                    .map(|c| c.as_os_str().to_string_lossy().into_owned())
-                    .collect();
-                let series = folder_path.last().cloned();
-                (folder_path, series)
-            }
-        } else {
-            (vec![], None)
-        };
+        let folder_path: Vec<String> = relative_path.parent()
+            .map(|parent| parent
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect())
+            .unwrap_or_default();
 
+        Some(Comic::new(file_name, folder_path, format))
+    }
 
-        // Create the encoded path
-        let encoded_path = format!("/comics/{}", urlencoding::encode(&file_name));
+    /// Builds a comic from its location, deriving the display name from the
+    /// file stem and the series from the parent folder.
+    pub fn new(file_name: String, folder_path: Vec<String>, format: &str) -> Self {
+        let name = Path::new(&file_name)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| file_name.clone());
+        let series = folder_path.last().cloned();
 
-        let comic = Comic {
-            id: file_name.clone(),
+        // The library assigns the id and download path once the comic is indexed
+        Comic {
+            id: String::new(),
             name,
             file_name,
-            path: encoded_path,
+            path: String::new(),
             folder_path,
             series,
-        };
+            format: format.to_string(),
+            metadata: None,
+            page_count: 0,
+            access_token: None,
+            read_progress: None,
+        }
+    }
+
+    pub fn set_id(&mut self, id: String) {
+        self.path = format!("/comics/{}", urlencoding::encode(&id));
+        self.id = id;
+    }
+
+    /// Path of the comic file relative to the library root, `/` separated.
+    pub fn relative_path(&self) -> String {
+        self.folder_path.iter()
+            .chain(std::iter::once(&self.file_name))
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("/")
+    }
 
-        Some(comic)
+    /// Attaches archive metadata, letting its series override the one
+    /// guessed from the parent folder.
+    pub fn with_metadata(mut self, metadata: Option<ComicMetadata>) -> Self {
+        if let Some(series) = metadata.as_ref().and_then(|m| m.series.clone()) {
+            self.series = Some(series);
+        }
+        self.metadata = metadata;
+        self
     }
 
+    pub fn page_metadata(&self) -> &[PageMetadata] {
+        self.metadata.as_ref().map(|m| m.pages.as_slice()).unwrap_or_default()
+    }
 
     pub fn matches_search(&self, query: &str) -> bool {
         let query = query.to_lowercase();
@@ -83,9 +140,125 @@ impl Comic {
             }
         }
 
+        // Check if query matches the embedded title or credits
+        if let Some(metadata) = &self.metadata {
+            let matches_title = metadata.title.as_ref()
+                .is_some_and(|title| title.to_lowercase().contains(&query));
+            let matches_writer = metadata.writers.iter()
+                .any(|writer| writer.to_lowercase().contains(&query));
+            if matches_title || matches_writer {
+                return true;
+            }
+        }
+
         // Check if query matches any folder in path
         self.folder_path.iter().any(|folder|
             folder.to_lowercase().contains(&query)
         )
     }
-}
\ No newline at end of file
+}
+
+impl Folder {
+    pub fn root() -> Self {
+        Folder {
+            name: "root".to_string(),
+            path: vec![],
+            comics: vec![],
+            subfolders: vec![],
+            read_counts: ReadCounts::default(),
+        }
+    }
+
+    /// Builds the folder tree holding the given comics. Only folders that
+    /// contain comics, directly or below, appear in the tree.
+    pub fn from_comics<'a>(comics: impl IntoIterator<Item = &'a Comic>) -> Self {
+        let mut root = Folder::root();
+        for comic in comics {
+            root.insert(comic, 0);
+        }
+        root.sort();
+        root
+    }
+
+    /// Adds a comic, creating its folders as needed.
+    pub fn add_comic(&mut self, comic: &Comic) {
+        self.insert(comic, 0);
+        self.sort();
+    }
+
+    /// Removes a comic, pruning folders it leaves empty.
+    pub fn remove_comic(&mut self, comic: &Comic) {
+        self.remove(comic, 0);
+    }
+
+    fn insert(&mut self, comic: &Comic, depth: usize) {
+        let Some(name) = comic.folder_path.get(depth) else {
+            self.comics.push(comic.clone());
+            return;
+        };
+
+        let index = match self.subfolders.iter().position(|folder| &folder.name == name) {
+            Some(index) => index,
+            None => {
+                self.subfolders.push(Folder {
+                    name: name.clone(),
+                    path: comic.folder_path[..depth].to_vec(),
+                    comics: vec![],
+                    subfolders: vec![],
+                    read_counts: ReadCounts::default(),
+                });
+                self.subfolders.len() - 1
+            }
+        };
+        self.subfolders[index].insert(comic, depth + 1);
+    }
+
+    fn remove(&mut self, comic: &Comic, depth: usize) {
+        let Some(name) = comic.folder_path.get(depth) else {
+            self.comics.retain(|existing| existing.file_name != comic.file_name);
+            return;
+        };
+
+        if let Some(index) = self.subfolders.iter().position(|folder| &folder.name == name) {
+            let folder = &mut self.subfolders[index];
+            folder.remove(comic, depth + 1);
+            if folder.comics.is_empty() && folder.subfolders.is_empty() {
+                self.subfolders.remove(index);
+            }
+        }
+    }
+
+    fn sort(&mut self) {
+        self.comics.sort_by(|a, b| natord::compare_ignore_case(&a.file_name, &b.file_name));
+        self.subfolders.sort_by(|a, b| natord::compare_ignore_case(&a.name, &b.name));
+        for folder in &mut self.subfolders {
+            folder.sort();
+        }
+    }
+
+    /// The folder at `path`, given as folder names below this one.
+    pub fn find(&self, path: &[String]) -> Option<&Folder> {
+        match path.split_first() {
+            None => Some(self),
+            Some((name, rest)) => self.subfolders.iter()
+                .find(|folder| &folder.name == name)?
+                .find(rest),
+        }
+    }
+
+    /// Splits a `/`-separated folder path as used in query strings.
+    pub fn parse_path(path: &str) -> Vec<String> {
+        path.split('/')
+            .filter(|part| !part.is_empty())
+            .map(String::from)
+            .collect()
+    }
+
+    /// Visits this folder and every folder below it, depth first.
+    pub fn walk<'a>(&'a self, visit: &mut impl FnMut(&'a Folder)) {
+        visit(self);
+        for folder in &self.subfolders {
+            folder.walk(visit);
+        }
+    }
+}
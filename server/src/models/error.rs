@@ -7,7 +7,36 @@ pub enum ComicError {
     InvalidPath,
     ComicNotFound,
     NoCoverFound,
-    ZipError(zip::result::ZipError),
+    PageNotFound,
+    EntryNotFound,
+    UnsupportedFormat,
+    InvalidMetadata(String),
+    InvalidThumbnailSize,
+    ImageError(image::ImageError),
+    DatabaseError(String),
+    UserNotFound,
+    UserExists,
+    InvalidUser(String),
+    LastAdmin,
+    SessionNotFound,
+    RarError(unrar::error::UnrarError),
+    ArchiveError(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl ComicError {
+    /// Wraps a format-specific archive library error. RAR errors keep
+    /// their own variant through `From`.
+    pub fn archive<E: std::error::Error + Send + Sync + 'static>(error: E) -> Self {
+        ComicError::ArchiveError(Box::new(error))
+    }
+
+    pub fn image(error: image::ImageError) -> Self {
+        ComicError::ImageError(error)
+    }
+
+    pub fn database(error: rusqlite::Error) -> Self {
+        ComicError::DatabaseError(error.to_string())
+    }
 }
 
 impl fmt::Display for ComicError {
@@ -17,7 +46,20 @@ impl fmt::Display for ComicError {
             ComicError::InvalidPath => write!(f, "Invalid path"),
             ComicError::ComicNotFound => write!(f, "Comic not found"),
             ComicError::NoCoverFound => write!(f, "No cover found in comic"),
-            ComicError::ZipError(e) => write!(f, "Zip error: {}", e),
+            ComicError::PageNotFound => write!(f, "Page not found in comic"),
+            ComicError::EntryNotFound => write!(f, "Entry not found in archive"),
+            ComicError::UnsupportedFormat => write!(f, "Unsupported archive format"),
+            ComicError::InvalidMetadata(e) => write!(f, "Invalid metadata: {}", e),
+            ComicError::InvalidThumbnailSize => write!(f, "Invalid thumbnail size"),
+            ComicError::ImageError(e) => write!(f, "Image error: {}", e),
+            ComicError::DatabaseError(e) => write!(f, "Database error: {}", e),
+            ComicError::UserNotFound => write!(f, "User not found"),
+            ComicError::UserExists => write!(f, "A user with that name already exists"),
+            ComicError::InvalidUser(e) => write!(f, "Invalid user: {}", e),
+            ComicError::LastAdmin => write!(f, "At least one active admin must remain"),
+            ComicError::SessionNotFound => write!(f, "Session not found"),
+            ComicError::RarError(e) => write!(f, "Rar error: {}", e),
+            ComicError::ArchiveError(e) => write!(f, "Archive error: {}", e),
         }
     }
 }
@@ -30,14 +72,14 @@ impl From<io::Error> for ComicError {
     }
 }
 
-impl From<zip::result::ZipError> for ComicError {
-    fn from(error: zip::result::ZipError) -> Self {
-        ComicError::ZipError(error)
+impl From<unrar::error::UnrarError> for ComicError {
+    fn from(error: unrar::error::UnrarError) -> Self {
+        ComicError::RarError(error)
     }
 }
 
 impl From<notify::Error> for ComicError {
     fn from(error: notify::Error) -> Self {
-        ComicError::IoError(io::Error::new(io::ErrorKind::Other, error))
+        ComicError::IoError(io::Error::other(error))
     }
 }
\ No newline at end of file
@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+
+/// Where a metadata field was read from, in descending precedence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetadataSource {
+    /// `ComicInfo.xml` (ComicRack / Anansi schema)
+    ComicInfo,
+    /// CoMet XML file
+    CoMet,
+    /// ComicBookInfo JSON stored in the ZIP comment
+    ComicBookInfo,
+}
+
+/// Per-page information from ComicInfo's `<Pages>` element.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageMetadata {
+    /// Index of the image among the archive's ordered pages.
+    pub image: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_type: Option<String>,
+}
+
+/// Descriptive metadata embedded in a comic archive.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ComicMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub series: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub number: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volume: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub year: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub month: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub day: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub writers: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pencillers: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub inkers: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub colorists: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub letterers: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cover_artists: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub editors: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub translators: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub publisher: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub imprint: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub genres: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub characters: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub story_arc: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_count: Option<u32>,
+    /// ISO 639 language code.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manga: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub right_to_left: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub age_rating: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub web: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pages: Vec<PageMetadata>,
+    /// The source each populated field above was taken from.
+    pub sources: BTreeMap<String, MetadataSource>,
+}
+
+impl ComicMetadata {
+    /// Release date as `YYYY`, `YYYY-MM` or `YYYY-MM-DD`, as precise as the
+    /// metadata allows.
+    pub fn release_date(&self) -> Option<String> {
+        let year = self.year?;
+        Some(match (self.month, self.day) {
+            (Some(month), Some(day)) => format!("{:04}-{:02}-{:02}", year, month, day),
+            (Some(month), None) => format!("{:04}-{:02}", year, month),
+            _ => format!("{:04}", year),
+        })
+    }
+}
@@ -1,2 +1,5 @@
 pub mod comic;
-pub mod error;
\ No newline at end of file
+pub mod error;
+pub mod metadata;
+pub mod progress;
+pub mod user;
\ No newline at end of file
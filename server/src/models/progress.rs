@@ -0,0 +1,81 @@
+use std::ops::AddAssign;
+
+use serde::Serialize;
+
+/// Where a user is in a comic. Concurrent updates from several devices are
+/// settled by `last_read_at`: the latest one wins.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadProgress {
+    pub comic_id: String,
+    /// Zero-based index of the current page.
+    pub page: usize,
+    pub completed: bool,
+    /// Unix time in seconds of the update, as reported by the reading device.
+    pub last_read_at: i64,
+    /// Name of the KOReader device that made the update, if one did.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<String>,
+}
+
+/// How many comics in a folder or series a user has finished, started or
+/// not opened yet.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ReadCounts {
+    pub read: usize,
+    pub in_progress: usize,
+    pub unread: usize,
+}
+
+impl ReadCounts {
+    /// Counts one comic given the user's progress in it, if any.
+    pub fn add(&mut self, progress: Option<&ReadProgress>) {
+        match ReadStatus::of(progress) {
+            ReadStatus::Read => self.read += 1,
+            ReadStatus::InProgress => self.in_progress += 1,
+            ReadStatus::Unread => self.unread += 1,
+        }
+    }
+}
+
+impl AddAssign for ReadCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.read += other.read;
+        self.in_progress += other.in_progress;
+        self.unread += other.unread;
+    }
+}
+
+/// Whether a user has finished, started or not yet opened a comic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadStatus {
+    Unread,
+    InProgress,
+    Read,
+}
+
+impl ReadStatus {
+    pub const ALL: [ReadStatus; 3] = [ReadStatus::Unread, ReadStatus::InProgress, ReadStatus::Read];
+
+    pub fn of(progress: Option<&ReadProgress>) -> Self {
+        match progress {
+            Some(progress) if progress.completed => ReadStatus::Read,
+            Some(_) => ReadStatus::InProgress,
+            None => ReadStatus::Unread,
+        }
+    }
+
+    /// Name used in query strings.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReadStatus::Unread => "unread",
+            ReadStatus::InProgress => "in-progress",
+            ReadStatus::Read => "read",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        ReadStatus::ALL.into_iter().find(|status| status.as_str() == value)
+    }
+}
@@ -0,0 +1,43 @@
+use serde::Serialize;
+
+/// A server account. The password hash stays in the database and is never
+/// part of this struct.
+#[derive(Debug, Clone, Serialize)]
+pub struct User {
+    pub id: i64,
+    pub username: String,
+    pub is_admin: bool,
+    pub disabled: bool,
+    pub created_at: i64,
+}
+
+impl User {
+    pub fn is_active_admin(&self) -> bool {
+        self.is_admin && !self.disabled
+    }
+}
+
+/// A signed-in client. Tokens themselves are only ever stored hashed.
+#[derive(Debug, Clone, Serialize)]
+pub struct Session {
+    pub id: String,
+    pub user_id: i64,
+    pub created_at: i64,
+    /// When the refresh token, and with it the session, runs out.
+    pub expires_at: i64,
+    pub last_used_at: i64,
+    pub user_agent: Option<String>,
+}
+
+/// Issued on login and on every refresh; the previous pair stops working.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionTokens {
+    pub session_id: String,
+    pub access_token: String,
+    pub token_type: &'static str,
+    /// Seconds until `access_token` expires.
+    pub expires_in: i64,
+    pub refresh_token: String,
+    pub refresh_expires_in: i64,
+    pub user: User,
+}
@@ -1,21 +1,192 @@
 use rocket::http::Status;
-use rocket::State;
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Request, State};
 use rocket::serde::json::Json;
+use serde::{Deserialize, Serialize};
+
 use crate::config::AppConfig;
+use crate::models::error::ComicError;
+use crate::models::user::{Session, SessionTokens};
+use crate::services::auth::AuthGuard;
+use crate::services::database::run_blocking;
+use crate::services::users::UserService;
 
 #[derive(serde::Serialize)]
 pub struct AuthCheck {
     requires_password: bool,
 }
 
+#[derive(Deserialize)]
+pub struct LoginRequest {
+    #[serde(default)]
+    username: String,
+    password: String,
+}
+
+#[derive(Deserialize)]
+pub struct RefreshRequest {
+    refresh_token: String,
+}
+
+#[derive(Serialize)]
+pub struct SessionInfo {
+    #[serde(flatten)]
+    session: Session,
+    /// True for the session making the request.
+    current: bool,
+}
+
+/// The client's `User-Agent`, recorded so users can tell sessions apart.
+pub struct UserAgent(Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for UserAgent {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(UserAgent(request.headers().get_one("User-Agent").map(str::to_string)))
+    }
+}
+
 #[get("/auth/check")]
-pub fn check_auth(config: &State<AppConfig>) -> Json<AuthCheck> {
-    Json(AuthCheck {
-        requires_password: config.server_password.is_some()
+pub async fn check_auth(users: &State<UserService>) -> Result<Json<AuthCheck>, Status> {
+    let requires_password = run_blocking(users.inner(), |users| users.auth_required()).await
+        .map_err(|_| Status::InternalServerError)?;
+    Ok(Json(AuthCheck { requires_password }))
+}
+
+/// Exchanges a username and password for session tokens. As with Basic
+/// auth, an empty username means the bootstrapped admin.
+#[post("/auth/login", data = "<login>")]
+pub async fn login(
+    config: &State<AppConfig>,
+    users: &State<UserService>,
+    user_agent: UserAgent,
+    login: Json<LoginRequest>
+) -> Result<Json<SessionTokens>, Status> {
+    let LoginRequest { username, password } = login.into_inner();
+    let username = if username.is_empty() {
+        config.admin_username.clone()
+    } else {
+        username
+    };
+
+    let tokens = run_blocking(users.inner(), move |users| {
+        users.login(&username, &password, user_agent.0.as_deref())
     })
+        .await
+        .map_err(session_error_status)?;
+
+    tokens.map(Json).ok_or(Status::Unauthorized)
+}
+
+#[post("/auth/refresh", data = "<refresh>")]
+pub async fn refresh(
+    users: &State<UserService>,
+    refresh: Json<RefreshRequest>
+) -> Result<Json<SessionTokens>, Status> {
+    let refresh_token = refresh.into_inner().refresh_token;
+    run_blocking(users.inner(), move |users| users.refresh(&refresh_token))
+        .await
+        .map_err(session_error_status)?
+        .map(Json)
+        .ok_or(Status::Unauthorized)
+}
+
+/// Ends the session the request was made with.
+#[post("/auth/logout")]
+pub async fn logout(
+    auth: AuthGuard,
+    users: &State<UserService>
+) -> Result<Status, Status> {
+    if let Some(session_id) = auth.session_id {
+        run_blocking(users.inner(), move |users| users.revoke_session(&session_id))
+            .await
+            .map_err(session_error_status)?;
+    }
+    Ok(Status::NoContent)
+}
+
+#[get("/auth/sessions")]
+pub async fn list_sessions(
+    auth: AuthGuard,
+    users: &State<UserService>
+) -> Result<Json<Vec<SessionInfo>>, Status> {
+    let Some(user) = &auth.user else {
+        return Ok(Json(vec![]));
+    };
+
+    let user_id = user.id;
+    let sessions = run_blocking(users.inner(), move |users| users.sessions(user_id))
+        .await
+        .map_err(session_error_status)?;
+    Ok(Json(sessions.into_iter()
+        .map(|session| SessionInfo {
+            current: auth.session_id.as_deref() == Some(session.id.as_str()),
+            session,
+        })
+        .collect()))
+}
+
+/// Revokes one of the caller's sessions; admins may revoke anyone's.
+#[delete("/auth/sessions/<id>")]
+pub async fn revoke_session(
+    auth: AuthGuard,
+    users: &State<UserService>,
+    id: &str
+) -> Result<Status, Status> {
+    let id = id.to_string();
+    let session = run_blocking(users.inner(), move |users| users.session(&id))
+        .await
+        .map_err(session_error_status)?;
+    if let Some(user) = &auth.user {
+        if !user.is_admin && user.id != session.user_id {
+            return Err(Status::NotFound);
+        }
+    }
+
+    run_blocking(users.inner(), move |users| users.revoke_session(&session.id))
+        .await
+        .map_err(session_error_status)?;
+    Ok(Status::NoContent)
+}
+
+fn session_error_status(error: ComicError) -> Status {
+    match error {
+        ComicError::SessionNotFound => Status::NotFound,
+        e => {
+            println!("Error managing sessions: {:?}", e);
+            Status::InternalServerError
+        }
+    }
 }
 
 #[options("/auth/check")]
 pub fn auth_check_options() -> Status {
     Status::NoContent
-}
\ No newline at end of file
+}
+
+#[options("/auth/login")]
+pub fn login_options() -> Status {
+    Status::NoContent
+}
+
+#[options("/auth/refresh")]
+pub fn refresh_options() -> Status {
+    Status::NoContent
+}
+
+#[options("/auth/logout")]
+pub fn logout_options() -> Status {
+    Status::NoContent
+}
+
+#[options("/auth/sessions")]
+pub fn sessions_options() -> Status {
+    Status::NoContent
+}
+
+#[options("/auth/sessions/<_id>")]
+pub fn session_options(_id: &str) -> Status {
+    Status::NoContent
+}
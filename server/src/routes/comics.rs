@@ -1,47 +1,136 @@
+use std::collections::HashMap;
+
 use rocket::State;
 use rocket::http::{Status, ContentType};
 use rocket::serde::json::Json;
 
-use crate::models::comic::{Comic, Folder};
+use crate::models::comic::{Comic, Folder, Page};
 use crate::models::error::ComicError;
+use crate::models::progress::{ReadCounts, ReadProgress};
 use crate::services::comic_service::ComicService;
-use crate::services::auth::AuthGuard;
-use crate::utils::response::BinaryResponse;
+use crate::services::auth::{AuthGuard, ComicAccess, UrlSigner};
+use crate::services::database::run_blocking;
+use crate::services::progress::ProgressService;
+use crate::services::thumbnails::ThumbnailSize;
+use crate::services::users::UserService;
+use crate::utils::response::{BinaryResponse, Body};
 
 #[get("/comics?<search>")]
 pub async fn list_comics(
-    _auth: AuthGuard,
+    auth: AuthGuard,
+    signer: &State<UrlSigner>,
+    users: &State<UserService>,
     comic_service: &State<ComicService>,
+    progress_service: &State<ProgressService>,
     search: Option<String>,
-) -> Json<Vec<Comic>> {
-    let comics = match search {
+) -> Result<Json<Vec<Comic>>, Status> {
+    let mut comics = match search {
         Some(query) => comic_service.search_comics(&query).await,
         None => comic_service.get_all_comics().await,
     };
-    Json(comics)
+    let listing = Listing::for_user(&auth, signer, users, progress_service).await?;
+    listing.annotate(&mut comics);
+    Ok(Json(comics))
 }
 
 #[get("/folders")]
 pub async fn get_folder_structure(
-    _auth: AuthGuard,
-    comic_service: &State<ComicService>
-) -> Json<Folder> {
-    Json(comic_service.get_folder_structure().await)
+    auth: AuthGuard,
+    signer: &State<UrlSigner>,
+    users: &State<UserService>,
+    comic_service: &State<ComicService>,
+    progress_service: &State<ProgressService>
+) -> Result<Json<Folder>, Status> {
+    let mut folder = comic_service.get_folder_structure().await;
+    let listing = Listing::for_user(&auth, signer, users, progress_service).await?;
+    listing.annotate_folder(&mut folder);
+    Ok(Json(folder))
+}
+
+/// The per-user fields filled into listed comics.
+struct Listing<'a> {
+    /// Set when auth is enforced, so clients can load covers through
+    /// `<img>` tags.
+    tokens: Option<TokenIssuer<'a>>,
+    progress: HashMap<String, ReadProgress>,
+}
+
+/// Signs URL tokens for one user at their current token generation.
+struct TokenIssuer<'a> {
+    signer: &'a UrlSigner,
+    user_id: i64,
+    generation: i64,
+}
+
+impl<'a> Listing<'a> {
+    async fn for_user(
+        auth: &AuthGuard,
+        signer: &'a UrlSigner,
+        users: &UserService,
+        progress_service: &ProgressService,
+    ) -> Result<Self, Status> {
+        let user_id = auth.user_id();
+        let progress = run_blocking(progress_service, move |progress| progress.by_comic(user_id))
+            .await
+            .map_err(|e| {
+                println!("Error loading reading progress: {:?}", e);
+                Status::InternalServerError
+            })?;
+
+        let tokens = match &auth.user {
+            Some(user) => run_blocking(users, move |users| users.token_generation(user_id))
+                .await
+                .map_err(|e| {
+                    println!("Error loading token generation: {:?}", e);
+                    Status::InternalServerError
+                })?
+                .map(|generation| TokenIssuer { signer, user_id: user.id, generation }),
+            None => None,
+        };
+        Ok(Listing { tokens, progress })
+    }
+
+    fn annotate(&self, comics: &mut [Comic]) {
+        for comic in comics {
+            comic.access_token = self.tokens.as_ref()
+                .map(|tokens| tokens.signer.sign(&comic.id, tokens.user_id, tokens.generation));
+            comic.read_progress = self.progress.get(&comic.id).cloned();
+        }
+    }
+
+    /// Annotates a folder's comics and fills in its read counts, which
+    /// it returns for the parent's.
+    fn annotate_folder(&self, folder: &mut Folder) -> ReadCounts {
+        self.annotate(&mut folder.comics);
+
+        let mut counts = ReadCounts::default();
+        for comic in &folder.comics {
+            counts.add(comic.read_progress.as_ref());
+        }
+        for subfolder in &mut folder.subfolders {
+            counts += self.annotate_folder(subfolder);
+        }
+        folder.read_counts = counts;
+        counts
+    }
 }
 
 #[get("/comics/<id>")]
 pub async fn get_comic(
+    access: ComicAccess<'_>,
     comic_service: &State<ComicService>,
     id: String
 ) -> Result<BinaryResponse, Status> {
+    access.check(&id)?;
     println!("Requested comic ID: {}", id);
 
-    let data = comic_service.get_comic_data(&id)
+    let file = comic_service.get_comic_file(&id)
         .await
         .map_err(|e| {
             println!("Error getting comic data: {:?}", e);
             match e {
                 ComicError::ComicNotFound => Status::NotFound,
+                ComicError::InvalidPath => Status::Forbidden,
                 _ => Status::InternalServerError,
             }
         })?;
@@ -50,27 +139,109 @@ pub async fn get_comic(
         .await
         .ok_or(Status::NotFound)?;
 
+    let content_type = comic_service.archives().content_type(&comic.format);
+
+    let body = Body::from_file(file)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
     Ok(BinaryResponse {
-        data,
-        content_type: ContentType::ZIP,
+        body,
+        content_type,
         filename: Some(comic.file_name),
     })
 }
 
-#[get("/covers/<id>")]
-pub async fn get_cover(
+#[get("/comics/<id>/pages")]
+pub async fn list_pages(
+    access: ComicAccess<'_>,
     comic_service: &State<ComicService>,
     id: String
+) -> Result<Json<Vec<Page>>, Status> {
+    access.check(&id)?;
+    comic_service.get_pages(&id)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            println!("Error listing pages: {:?}", e);
+            match e {
+                ComicError::ComicNotFound => Status::NotFound,
+                ComicError::InvalidPath => Status::Forbidden,
+                _ => Status::InternalServerError,
+            }
+        })
+}
+
+/// Serves a page image. With `width`, the page is scaled down to fit and
+/// re-encoded as JPEG, as OPDS page streaming clients ask for.
+#[get("/comics/<id>/pages/<index>?<width>")]
+pub async fn get_page(
+    access: ComicAccess<'_>,
+    comic_service: &State<ComicService>,
+    id: String,
+    index: usize,
+    width: Option<u32>
+) -> Result<BinaryResponse, Status> {
+    access.check(&id)?;
+    let page_error = |e: ComicError| {
+        println!("Error getting page {} of {}: {:?}", index, id, e);
+        match e {
+            ComicError::ComicNotFound | ComicError::PageNotFound => Status::NotFound,
+            ComicError::InvalidPath => Status::Forbidden,
+            _ => Status::InternalServerError,
+        }
+    };
+    let (mime, data) = match width.map(ThumbnailSize::fit_width) {
+        Some(size) => {
+            let image = comic_service.get_scaled_page(&id, index, size).await.map_err(page_error)?;
+            (image.mime, image.data)
+        }
+        None => {
+            let (page, data) = comic_service.get_page(&id, index).await.map_err(page_error)?;
+            (page.mime, data)
+        }
+    };
+
+    Ok(BinaryResponse {
+        body: data.into(),
+        content_type: ContentType::parse_flexible(&mime).unwrap_or(ContentType::Binary),
+        filename: None,
+    })
+}
+
+#[get("/covers/<id>?<size>&<width>&<height>")]
+pub async fn get_cover(
+    access: ComicAccess<'_>,
+    comic_service: &State<ComicService>,
+    id: String,
+    size: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>
 ) -> Result<BinaryResponse, Status> {
+    access.check(&id)?;
     println!("Requested cover ID: {}", id);
 
-    let cover = comic_service.get_cover(&id)
-        .await
-        .ok_or(Status::NotFound)?;
+    let thumbnail_size = ThumbnailSize::from_query(size.as_deref(), width, height)
+        .map_err(|_| Status::BadRequest)?;
+
+    let cover = match thumbnail_size {
+        Some(thumbnail_size) => comic_service.get_thumbnail(&id, thumbnail_size)
+            .await
+            .map_err(|e| {
+                println!("Error rendering thumbnail: {:?}", e);
+                match e {
+                    ComicError::ComicNotFound | ComicError::NoCoverFound => Status::NotFound,
+                    _ => Status::InternalServerError,
+                }
+            })?,
+        None => comic_service.get_cover(&id)
+            .await
+            .ok_or(Status::NotFound)?,
+    };
 
     Ok(BinaryResponse {
-        data: cover.data,
-        content_type: ContentType::JPEG,
+        body: cover.data.into(),
+        content_type: ContentType::parse_flexible(&cover.mime).unwrap_or(ContentType::JPEG),
         filename: None,
     })
 }
@@ -90,7 +261,336 @@ pub fn comic_options(_id: String) -> Status {
     Status::NoContent
 }
 
+#[options("/comics/<_id>/pages")]
+pub fn pages_options(_id: String) -> Status {
+    Status::NoContent
+}
+
+#[options("/comics/<_id>/pages/<_index>")]
+pub fn page_options(_id: String, _index: usize) -> Status {
+    Status::NoContent
+}
+
 #[options("/folders")]
 pub fn folders_options() -> Status {
     Status::NoContent
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::{Cursor, Write};
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use rocket::fairing::AdHoc;
+    use rocket::http::Header;
+    use rocket::local::blocking::Client;
+    use rocket::serde::json::Value;
+    use crate::config::AppConfig;
+    use crate::services::database::Database;
+
+    /// A running server over a library with two comics, `Batman/001.cbz`
+    /// and `Batman %41.cbz`, plus a symlink to a third comic outside the
+    /// library that no request may reach.
+    struct Fixture {
+        dir: PathBuf,
+        client: Client,
+        users: UserService,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir()
+                .join(format!("comic-routes-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(dir.join("library/Batman")).unwrap();
+            fs::create_dir_all(dir.join("data")).unwrap();
+            write_comic(&dir.join("library/Batman/001.cbz"), "page.png");
+            write_comic(&dir.join("library/Batman %41.cbz"), "page.png");
+            write_comic(&dir.join("secret.cbz"), "secret.png");
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(dir.join("secret.cbz"), dir.join("library/escape.cbz")).unwrap();
+
+            let data = dir.join("data");
+            let config = AppConfig {
+                comics_dir: dir.join("library").to_string_lossy().into_owned(),
+                server_password: None,
+                admin_username: String::from("admin"),
+                cover_cache_dir: data.join("covers").to_string_lossy().into_owned(),
+                database_path: data.join("library.db").to_string_lossy().into_owned(),
+                url_signing_key_file: data.join("url_signing.key").to_string_lossy().into_owned(),
+            };
+            let database = Arc::new(Database::open(Path::new(&config.database_path)).unwrap());
+            let url_signer = UrlSigner::load_or_create(Path::new(&config.url_signing_key_file)).unwrap();
+            let users = UserService::new(database.clone());
+
+            // The service spawns its watcher, so it has to start on the
+            // client's runtime
+            let managed_users = users.clone();
+            let library = AdHoc::try_on_ignite("Library", move |rocket| async move {
+                let comic_service = ComicService::new(
+                    PathBuf::from(&config.comics_dir),
+                    PathBuf::from(&config.cover_cache_dir),
+                    database.clone(),
+                ).await.unwrap();
+                Ok(rocket
+                    .manage(config)
+                    .manage(comic_service)
+                    .manage(managed_users)
+                    .manage(ProgressService::new(database)))
+            });
+
+            let rocket = rocket::build()
+                .attach(library)
+                .manage(url_signer)
+                .mount("/", routes![list_comics, get_comic, list_pages, get_page, get_cover])
+                .mount("/", routes![
+                    crate::routes::auth::login,
+                    crate::routes::auth::refresh,
+                    crate::routes::auth::logout,
+                    crate::routes::users::create_user,
+                ]);
+            Fixture { dir, client: Client::tracked(rocket).unwrap(), users }
+        }
+
+        fn comic(&self, file_name: &str, authorization: Option<&str>) -> Value {
+            let mut request = self.client.get("/comics");
+            if let Some(authorization) = authorization {
+                request = request.header(Header::new("Authorization", authorization.to_string()));
+            }
+            let comics: Vec<Value> = request.dispatch().into_json().unwrap();
+            comics.into_iter()
+                .find(|comic| comic["file_name"] == file_name)
+                .unwrap()
+        }
+
+        fn comic_id(&self, file_name: &str) -> String {
+            self.comic(file_name, None)["id"].as_str().unwrap().to_string()
+        }
+
+        /// Status of listing the library with a bearer token.
+        fn bearer_status(&self, access_token: &str) -> Status {
+            self.client.get("/comics")
+                .header(Header::new("Authorization", format!("Bearer {}", access_token)))
+                .dispatch()
+                .status()
+        }
+
+        /// Posts JSON to an auth endpoint, returning the tokens it issued.
+        fn tokens(&self, uri: &str, body: String) -> Option<Value> {
+            let response = self.client.post(uri).header(ContentType::JSON).body(body).dispatch();
+            if response.status() != Status::Ok {
+                return None;
+            }
+            response.into_json()
+        }
+
+        fn login(&self, username: &str, password: &str) -> Value {
+            let body = rocket::serde::json::json!({ "username": username, "password": password });
+            self.tokens("/auth/login", body.to_string()).unwrap()
+        }
+
+        fn refresh(&self, refresh_token: &Value) -> Option<Value> {
+            let body = rocket::serde::json::json!({ "refresh_token": refresh_token });
+            self.tokens("/auth/refresh", body.to_string())
+        }
+
+        fn status(&self, uri: &str) -> Status {
+            self.client.get(uri).dispatch().status()
+        }
+
+        fn assert_rejected(&self, uri: &str) {
+            let status = self.client.get(uri).dispatch().status();
+            assert!(status == Status::NotFound || status == Status::Forbidden,
+                    "{} answered {}", uri, status);
+        }
+
+        fn assert_id_rejected(&self, id: &str) {
+            self.assert_rejected(&format!("/comics/{}", id));
+            self.assert_rejected(&format!("/comics/{}/pages", id));
+            self.assert_rejected(&format!("/comics/{}/pages/0", id));
+            self.assert_rejected(&format!("/covers/{}", id));
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    /// Writes a one-page CBZ whose page is a 1x1 PNG named `page_name`.
+    fn write_comic(path: &Path, page_name: &str) {
+        let mut png = Vec::new();
+        image::RgbImage::new(1, 1)
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+
+        let mut zip = zip::ZipWriter::new(fs::File::create(path).unwrap());
+        zip.start_file(page_name, zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(&png).unwrap();
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn serves_comics_inside_the_library() {
+        let fixture = Fixture::new("inside");
+        let id = fixture.comic_id("001.cbz");
+
+        assert_eq!(fixture.client.get(format!("/comics/{}", id)).dispatch().status(), Status::Ok);
+        assert_eq!(fixture.client.get(format!("/covers/{}", id)).dispatch().status(), Status::Ok);
+        let pages: Vec<Value> = fixture.client.get(format!("/comics/{}/pages", id))
+            .dispatch()
+            .into_json()
+            .unwrap();
+        assert_eq!(pages.len(), 1);
+    }
+
+    #[test]
+    fn resolves_legacy_names_containing_percent_signs() {
+        let fixture = Fixture::new("percent");
+        let response = fixture.client.get("/comics/Batman%20%2541.cbz/pages").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn rejects_traversal_ids() {
+        let fixture = Fixture::new("traversal");
+        for id in [
+            "..",
+            "%2e%2e",
+            "..%2Fsecret.cbz",
+            "%2e%2e%2Fsecret.cbz",
+            "%2E%2E%2fsecret.cbz",
+            "Batman%2F..%2F..%2Fsecret.cbz",
+            "%252e%252e%252fsecret.cbz",
+            "secret.cbz",
+        ] {
+            fixture.assert_id_rejected(id);
+        }
+    }
+
+    #[test]
+    fn signed_urls_are_bound_to_their_comic_and_user() {
+        let fixture = Fixture::new("signed");
+        let other = fixture.comic_id("Batman %41.cbz");
+        fixture.users.create("admin", "correct horse", true).unwrap();
+        let user = fixture.users.create("reader", "correct horse", false).unwrap();
+        let comic = fixture.comic("001.cbz", Some("Basic cmVhZGVyOmNvcnJlY3QgaG9yc2U="));
+        let (id, token) = (comic["id"].as_str().unwrap(), comic["access_token"].as_str().unwrap());
+
+        assert_eq!(fixture.status(&format!("/comics/{}/pages?token={}", id, token)), Status::Ok);
+        assert_eq!(fixture.status(&format!("/comics/{}/pages", id)), Status::Unauthorized);
+        assert_eq!(fixture.status(&format!("/comics/{}/pages?token={}", other, token)), Status::Unauthorized);
+
+        // Signing the user out everywhere revokes their tokens
+        fixture.users.set_password(user.id, "battery staple").unwrap();
+        assert_eq!(fixture.status(&format!("/comics/{}/pages?token={}", id, token)), Status::Unauthorized);
+    }
+
+    #[test]
+    fn the_first_account_is_always_an_admin() {
+        let fixture = Fixture::new("bootstrap");
+        let create = |remote: &str, authorization: Option<&str>, body: &str| {
+            let mut request = fixture.client.post("/users")
+                .remote(remote.parse().unwrap())
+                .header(ContentType::JSON)
+                .body(body);
+            if let Some(authorization) = authorization {
+                request = request.header(Header::new("Authorization", authorization.to_string()));
+            }
+            let response = request.dispatch();
+            (response.status(), response.into_json::<Value>())
+        };
+        let first = r#"{"username": "first", "password": "correct horse"}"#;
+
+        // Without accounts only the server's own machine may create one
+        assert_eq!(create("203.0.113.5:4000", None, first).0, Status::Forbidden);
+
+        let (status, user) = create("127.0.0.1:4000", None, first);
+        assert_eq!(status, Status::Created);
+        assert_eq!(user.unwrap()["is_admin"], true);
+
+        // From then on creating accounts takes an admin's credentials
+        let second = r#"{"username": "second", "password": "correct horse"}"#;
+        assert_eq!(create("127.0.0.1:4000", None, second).0, Status::Unauthorized);
+        let (status, user) = create("127.0.0.1:4000", Some("Basic Zmlyc3Q6Y29ycmVjdCBob3JzZQ=="), second);
+        assert_eq!(status, Status::Created);
+        assert_eq!(user.unwrap()["is_admin"], false);
+    }
+
+    #[test]
+    fn refreshing_rotates_both_tokens() {
+        let fixture = Fixture::new("refresh");
+        fixture.users.create("admin", "correct horse", true).unwrap();
+        let first = fixture.login("admin", "correct horse");
+        let access = first["access_token"].as_str().unwrap();
+        assert_eq!(fixture.bearer_status(access), Status::Ok);
+
+        let second = fixture.refresh(&first["refresh_token"]).unwrap();
+        assert_eq!(second["session_id"], first["session_id"]);
+        assert_eq!(fixture.bearer_status(second["access_token"].as_str().unwrap()), Status::Ok);
+
+        // The previous pair is spent
+        assert!(fixture.refresh(&first["refresh_token"]).is_none());
+        assert_eq!(fixture.bearer_status(access), Status::Unauthorized);
+        assert!(fixture.refresh(&second["refresh_token"]).is_some());
+    }
+
+    #[test]
+    fn logging_out_revokes_the_session() {
+        let fixture = Fixture::new("logout");
+        fixture.users.create("admin", "correct horse", true).unwrap();
+        let tokens = fixture.login("admin", "correct horse");
+        let other = fixture.login("admin", "correct horse");
+        let access = tokens["access_token"].as_str().unwrap();
+
+        let response = fixture.client.post("/auth/logout")
+            .header(Header::new("Authorization", format!("Bearer {}", access)))
+            .dispatch();
+        assert_eq!(response.status(), Status::NoContent);
+
+        assert_eq!(fixture.bearer_status(access), Status::Unauthorized);
+        assert!(fixture.refresh(&tokens["refresh_token"]).is_none());
+        // Only that session ends
+        assert_eq!(fixture.bearer_status(other["access_token"].as_str().unwrap()), Status::Ok);
+    }
+
+    #[test]
+    fn expired_access_tokens_are_rejected_until_refreshed() {
+        let fixture = Fixture::new("expired");
+        fixture.users.create("admin", "correct horse", true).unwrap();
+        let tokens = fixture.login("admin", "correct horse");
+        let access = tokens["access_token"].as_str().unwrap();
+
+        let database = rusqlite::Connection::open(fixture.dir.join("data/library.db")).unwrap();
+        database.execute("UPDATE sessions SET access_expires_at = 0", []).unwrap();
+        assert_eq!(fixture.bearer_status(access), Status::Unauthorized);
+
+        let refreshed = fixture.refresh(&tokens["refresh_token"]).unwrap();
+        assert_eq!(fixture.bearer_status(refreshed["access_token"].as_str().unwrap()), Status::Ok);
+
+        // Past the refresh token's lifetime the session is over
+        database.execute("UPDATE sessions SET access_expires_at = 0, expires_at = 0", []).unwrap();
+        assert_eq!(fixture.bearer_status(refreshed["access_token"].as_str().unwrap()), Status::Unauthorized);
+        assert!(fixture.refresh(&refreshed["refresh_token"]).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlink_escapes() {
+        let fixture = Fixture::new("symlink");
+        // Links out of the library are never indexed
+        fixture.assert_id_rejected("escape.cbz");
+
+        // Nor followed when a comic is swapped for one after the scan
+        let id = fixture.comic_id("001.cbz");
+        let comic = fixture.dir.join("library/Batman/001.cbz");
+        fs::remove_file(&comic).unwrap();
+        std::os::unix::fs::symlink(fixture.dir.join("secret.cbz"), &comic).unwrap();
+
+        fixture.assert_rejected(&format!("/comics/{}", id));
+        fixture.assert_rejected(&format!("/comics/{}/pages/0", id));
+    }
+}
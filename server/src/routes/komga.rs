@@ -0,0 +1,841 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use rocket::{Request, State};
+use rocket::http::{ContentType, Header, Status};
+use rocket::serde::json::Json;
+use serde::Deserialize;
+
+use crate::models::comic::{Comic, Folder};
+use crate::models::error::ComicError;
+use crate::models::metadata::ComicMetadata;
+use crate::models::progress::{ReadCounts, ReadProgress, ReadStatus};
+use crate::services::auth::AuthGuard;
+use crate::services::comic_service::{ComicService, FileStats};
+use crate::services::database::{run_blocking, unix_time};
+use crate::services::komga::{
+    self, AuthorDto, BookDto, BookMetadataAggregationDto, BookMetadataDto, ErrorDto, LibraryDto, MediaDto,
+    PageDto, PageWrapper, ReadProgressDto, SeriesDto, SeriesMetadataDto, TachiyomiReadProgressDto,
+    DEFAULT_PAGE_SIZE,
+};
+use crate::services::opds::format_timestamp;
+use crate::services::progress::ProgressService;
+use crate::services::thumbnails::ThumbnailSize;
+use crate::utils::response::BinaryResponse;
+
+/// The whole library appears to Komga clients as a single library.
+const LIBRARY_ID: &str = "comics";
+const LIBRARY_NAME: &str = "Comics";
+
+/// Covers are served at Komga's thumbnail width.
+const THUMBNAIL_SIZE: ThumbnailSize = ThumbnailSize { width: Some(300), height: None };
+
+/// Query parameters shared by Komga's listings. Ones we have no use for,
+/// such as `deleted` or `media_status`, are ignored.
+#[derive(FromForm, Default)]
+pub struct ListQuery {
+    search: Option<String>,
+    library_id: Vec<String>,
+    read_status: Vec<String>,
+    /// `field,direction`, such as `metadata.titleSort,asc`.
+    sort: Vec<String>,
+    /// Zero-based.
+    page: Option<usize>,
+    size: Option<usize>,
+    unpaged: Option<bool>,
+}
+
+impl ListQuery {
+    fn paginate<T>(&self, items: Vec<T>) -> PageWrapper<T> {
+        let size = match self.unpaged {
+            Some(true) => None,
+            _ => Some(self.size.unwrap_or(DEFAULT_PAGE_SIZE)),
+        };
+        PageWrapper::paginate(items, self.page.unwrap_or(0), size)
+    }
+
+    /// Komga's `UNREAD`, `IN_PROGRESS` and `READ`.
+    fn read_statuses(&self) -> Result<Vec<ReadStatus>, Status> {
+        self.read_status.iter()
+            .map(|status| {
+                ReadStatus::parse(&status.to_lowercase().replace('_', "-")).ok_or(Status::BadRequest)
+            })
+            .collect()
+    }
+
+    fn in_library(&self) -> bool {
+        self.library_id.is_empty() || self.library_id.iter().any(|id| id == LIBRARY_ID)
+    }
+
+    /// Whether the first sort is by date rather than title or number, and
+    /// whether it is descending.
+    fn sort_order(&self) -> (bool, bool) {
+        let Some(sort) = self.sort.first() else {
+            return (false, false);
+        };
+        let (field, direction) = sort.split_once(',').unwrap_or((sort, "asc"));
+        let by_date = matches!(field, "created" | "createdDate" | "lastModified" | "lastModifiedDate");
+        (by_date, direction.eq_ignore_ascii_case("desc"))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ReadProgressUpdate {
+    /// One-based page.
+    page: Option<usize>,
+    completed: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TachiyomiReadProgressUpdate {
+    last_book_number_sort_read: f32,
+}
+
+/// A folder holding comics, which Komga clients see as a series.
+struct Series {
+    id: String,
+    title: String,
+    /// Folder names from the library root.
+    path: Vec<String>,
+    /// In reading order.
+    books: Vec<Comic>,
+}
+
+/// Every series with its books' file stats. Built from the folder tree
+/// and shared by all users until the library changes.
+struct SeriesIndex {
+    /// Sorted by title.
+    series: Vec<Series>,
+    stats: HashMap<String, FileStats>,
+}
+
+impl SeriesIndex {
+    async fn build(comic_service: &ComicService) -> Self {
+        let root = comic_service.get_folder_structure().await;
+        let mut series = Vec::new();
+        root.walk(&mut |folder: &Folder| {
+            let Some(first) = folder.comics.first() else {
+                return;
+            };
+            let path = first.folder_path.clone();
+            series.push(Series {
+                id: komga::series_id(&path),
+                title: path.last().cloned().unwrap_or_else(|| LIBRARY_NAME.to_string()),
+                path,
+                books: folder.comics.clone(),
+            });
+        });
+        series.sort_by(|a, b| natord::compare_ignore_case(&a.title, &b.title));
+
+        SeriesIndex {
+            series,
+            stats: comic_service.file_stats().await,
+        }
+    }
+}
+
+/// The last `SeriesIndex` built, with the library generation it reflects.
+#[derive(Default)]
+pub struct SeriesCache {
+    cached: Mutex<Option<(u64, Arc<SeriesIndex>)>>,
+}
+
+impl SeriesCache {
+    async fn get(&self, comic_service: &ComicService) -> Arc<SeriesIndex> {
+        // Read before building, so an index that raced a change is
+        // rebuilt on the next request rather than kept
+        let generation = comic_service.generation();
+        if let Some((built, index)) = &*self.cached.lock().unwrap_or_else(|e| e.into_inner()) {
+            if *built == generation {
+                return index.clone();
+            }
+        }
+
+        let index = Arc::new(SeriesIndex::build(comic_service).await);
+        *self.cached.lock().unwrap_or_else(|e| e.into_inner()) = Some((generation, index.clone()));
+        index
+    }
+}
+
+/// The library as seen by one user.
+struct Library<'a> {
+    comic_service: &'a ComicService,
+    index: Arc<SeriesIndex>,
+    progress: HashMap<String, ReadProgress>,
+}
+
+impl<'a> Library<'a> {
+    async fn load(
+        auth: &AuthGuard,
+        comic_service: &'a ComicService,
+        series_cache: &SeriesCache,
+        progress_service: &ProgressService,
+    ) -> Result<Self, Status> {
+        let user_id = auth.user_id();
+        let progress = run_blocking(progress_service, move |progress| progress.by_comic(user_id))
+            .await
+            .map_err(|e| {
+                println!("Error loading reading progress: {:?}", e);
+                Status::InternalServerError
+            })?;
+
+        Ok(Library {
+            comic_service,
+            index: series_cache.get(comic_service).await,
+            progress,
+        })
+    }
+
+    fn find_series(&self, id: &str) -> Result<&Series, Status> {
+        self.index.series.iter().find(|series| series.id == id).ok_or(Status::NotFound)
+    }
+
+    /// The series holding a book and the book's index in it.
+    fn find_book(&self, id: &str) -> Result<(&Series, usize), Status> {
+        self.index.series.iter()
+            .find_map(|series| {
+                series.books.iter()
+                    .position(|book| book.id == id)
+                    .map(|index| (series, index))
+            })
+            .ok_or(Status::NotFound)
+    }
+
+    fn counts(&self, series: &Series) -> ReadCounts {
+        let mut counts = ReadCounts::default();
+        for book in &series.books {
+            counts.add(self.progress.get(&book.id));
+        }
+        counts
+    }
+
+    fn series_status(&self, series: &Series) -> ReadStatus {
+        let counts = self.counts(series);
+        if counts.read == series.books.len() {
+            ReadStatus::Read
+        } else if counts.unread == series.books.len() {
+            ReadStatus::Unread
+        } else {
+            ReadStatus::InProgress
+        }
+    }
+
+    fn modified(&self, book: &Comic) -> i64 {
+        self.index.stats.get(&book.id).map_or(0, |stats| stats.modified)
+    }
+
+    /// Latest change to any of a series' books.
+    fn series_modified(&self, series: &Series) -> i64 {
+        series.books.iter().map(|book| self.modified(book)).max().unwrap_or_default()
+    }
+
+    fn series_dto(&self, series: &Series) -> SeriesDto {
+        let counts = self.counts(series);
+        let modified = format_timestamp(self.series_modified(series));
+        let books_metadata: Vec<&ComicMetadata> = series.books.iter()
+            .filter_map(|book| book.metadata.as_ref())
+            .collect();
+        let first = |field: fn(&ComicMetadata) -> Option<&String>| books_metadata.iter()
+            .find_map(|metadata| field(metadata))
+            .cloned()
+            .unwrap_or_default();
+
+        let mut genres: Vec<String> = books_metadata.iter()
+            .flat_map(|metadata| metadata.genres.iter().cloned())
+            .collect();
+        genres.sort();
+        genres.dedup();
+        let mut tags: Vec<String> = books_metadata.iter()
+            .flat_map(|metadata| metadata.tags.iter().cloned())
+            .collect();
+        tags.sort();
+        tags.dedup();
+        let mut authors: Vec<AuthorDto> = Vec::new();
+        for author in books_metadata.iter().flat_map(|metadata| authors_of(metadata)) {
+            if !authors.contains(&author) {
+                authors.push(author);
+            }
+        }
+
+        let total_book_count = books_metadata.iter().find_map(|metadata| metadata.count);
+        let right_to_left = books_metadata.iter()
+            .any(|metadata| metadata.right_to_left == Some(true));
+        let summary_book = series.books.iter()
+            .position(|book| book.metadata.as_ref().is_some_and(|m| m.summary.is_some()));
+
+        SeriesDto {
+            id: series.id.clone(),
+            library_id: LIBRARY_ID,
+            name: series.title.clone(),
+            url: series.path.join("/"),
+            created: modified.clone(),
+            last_modified: modified.clone(),
+            file_last_modified: modified.clone(),
+            books_count: series.books.len(),
+            books_read_count: counts.read,
+            books_unread_count: counts.unread,
+            books_in_progress_count: counts.in_progress,
+            metadata: SeriesMetadataDto {
+                status: match total_book_count {
+                    Some(count) if series.books.len() >= count as usize => "ENDED",
+                    _ => "ONGOING",
+                },
+                title: series.title.clone(),
+                title_sort: series.title.clone(),
+                reading_direction: if right_to_left { "RIGHT_TO_LEFT" } else { "LEFT_TO_RIGHT" },
+                publisher: first(|metadata| metadata.publisher.as_ref()),
+                language: first(|metadata| metadata.language.as_ref()),
+                genres,
+                tags: tags.clone(),
+                total_book_count,
+                created: modified.clone(),
+                last_modified: modified.clone(),
+                ..SeriesMetadataDto::default()
+            },
+            books_metadata: BookMetadataAggregationDto {
+                authors,
+                tags,
+                release_date: books_metadata.iter().filter_map(|metadata| release_date(metadata)).min(),
+                summary: first(|metadata| metadata.summary.as_ref()),
+                summary_number: summary_book
+                    .map(|index| book_number(&series.books[index], index))
+                    .unwrap_or_default(),
+                created: modified.clone(),
+                last_modified: modified,
+            },
+            deleted: false,
+            oneshot: false,
+        }
+    }
+
+    fn book_dto(&self, series: &Series, index: usize) -> BookDto {
+        let book = &series.books[index];
+        let stats = self.index.stats.get(&book.id).copied().unwrap_or_default();
+        let modified = format_timestamp(stats.modified);
+        let media_type = self.comic_service.archives().content_type(&book.format);
+
+        let mut metadata = BookMetadataDto {
+            title: book.name.clone(),
+            number: book_number(book, index),
+            number_sort: number_sort(book, index),
+            created: modified.clone(),
+            last_modified: modified.clone(),
+            ..BookMetadataDto::default()
+        };
+        if let Some(comic_metadata) = &book.metadata {
+            if let Some(title) = &comic_metadata.title {
+                metadata.title = title.clone();
+            }
+            metadata.summary = comic_metadata.summary.clone().unwrap_or_default();
+            metadata.release_date = release_date(comic_metadata);
+            metadata.authors = authors_of(comic_metadata);
+            metadata.tags = comic_metadata.tags.clone();
+        }
+
+        BookDto {
+            id: book.id.clone(),
+            series_id: series.id.clone(),
+            series_title: series.title.clone(),
+            library_id: LIBRARY_ID,
+            name: book.name.clone(),
+            url: book.relative_path(),
+            number: index + 1,
+            created: modified.clone(),
+            last_modified: modified.clone(),
+            file_last_modified: modified,
+            size_bytes: stats.size,
+            size: format_size(stats.size),
+            media: MediaDto {
+                // Not counted until the book's pages are first listed
+                status: if book.page_count > 0 { "READY" } else { "UNKNOWN" },
+                media_type: media_type.to_string(),
+                media_profile: "DIVINA",
+                pages_count: book.page_count,
+                comment: "",
+                epub_divina_compatible: false,
+                epub_is_kepub: false,
+            },
+            metadata,
+            read_progress: self.progress.get(&book.id).map(read_progress_dto),
+            deleted: false,
+            oneshot: false,
+        }
+    }
+}
+
+/// Komga's answer to a missing or wrong login: Spring's error body and a
+/// Basic challenge.
+#[derive(Responder)]
+#[response(status = 401)]
+pub struct Challenge {
+    body: Json<ErrorDto>,
+    authenticate: Header<'static>,
+}
+
+#[catch(401)]
+pub fn unauthorized(request: &Request<'_>) -> Challenge {
+    Challenge {
+        body: Json(ErrorDto {
+            timestamp: format_timestamp(unix_time()),
+            status: Status::Unauthorized.code,
+            error: "Unauthorized",
+            path: request.uri().path().to_string(),
+        }),
+        authenticate: Header::new("WWW-Authenticate", "Basic realm=\"Comic Reader\", charset=\"UTF-8\""),
+    }
+}
+
+#[get("/komga/api/v1/libraries")]
+pub fn list_libraries(_auth: AuthGuard) -> Json<Vec<LibraryDto>> {
+    Json(vec![library_dto()])
+}
+
+#[get("/komga/api/v1/libraries/<id>")]
+pub fn get_library(_auth: AuthGuard, id: String) -> Result<Json<LibraryDto>, Status> {
+    if id != LIBRARY_ID {
+        return Err(Status::NotFound);
+    }
+    Ok(Json(library_dto()))
+}
+
+#[get("/komga/api/v1/series?<query..>")]
+pub async fn list_series(
+    auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    series_cache: &State<SeriesCache>,
+    progress_service: &State<ProgressService>,
+    query: ListQuery
+) -> Result<Json<PageWrapper<SeriesDto>>, Status> {
+    let library = Library::load(&auth, comic_service, series_cache, progress_service).await?;
+    Ok(Json(series_page(&library, &query)?))
+}
+
+/// Series with the most recently changed books first.
+#[get("/komga/api/v1/series/latest?<query..>")]
+pub async fn latest_series(
+    auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    series_cache: &State<SeriesCache>,
+    progress_service: &State<ProgressService>,
+    mut query: ListQuery
+) -> Result<Json<PageWrapper<SeriesDto>>, Status> {
+    let library = Library::load(&auth, comic_service, series_cache, progress_service).await?;
+    query.sort = vec!["lastModified,desc".to_string()];
+    Ok(Json(series_page(&library, &query)?))
+}
+
+#[get("/komga/api/v1/series/<id>", rank = 2)]
+pub async fn get_series(
+    auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    series_cache: &State<SeriesCache>,
+    progress_service: &State<ProgressService>,
+    id: String
+) -> Result<Json<SeriesDto>, Status> {
+    let library = Library::load(&auth, comic_service, series_cache, progress_service).await?;
+    let series = library.find_series(&id)?;
+    Ok(Json(library.series_dto(series)))
+}
+
+#[get("/komga/api/v1/series/<id>/thumbnail")]
+pub async fn series_thumbnail(
+    _auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    id: String
+) -> Result<BinaryResponse, Status> {
+    let root = comic_service.get_folder_structure().await;
+    let mut cover_id = None;
+    root.walk(&mut |folder| {
+        if let Some(first) = folder.comics.first() {
+            if cover_id.is_none() && komga::series_id(&first.folder_path) == id {
+                cover_id = Some(first.id.clone());
+            }
+        }
+    });
+    thumbnail(comic_service, &cover_id.ok_or(Status::NotFound)?).await
+}
+
+#[get("/komga/api/v1/series/<id>/books?<query..>")]
+pub async fn list_series_books(
+    auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    series_cache: &State<SeriesCache>,
+    progress_service: &State<ProgressService>,
+    id: String,
+    query: ListQuery
+) -> Result<Json<PageWrapper<BookDto>>, Status> {
+    let library = Library::load(&auth, comic_service, series_cache, progress_service).await?;
+    let series = library.find_series(&id)?;
+    let statuses = query.read_statuses()?;
+
+    let mut indices: Vec<usize> = (0..series.books.len())
+        .filter(|&index| {
+            statuses.is_empty()
+                || statuses.contains(&ReadStatus::of(library.progress.get(&series.books[index].id)))
+        })
+        .collect();
+    let (by_date, descending) = query.sort_order();
+    if by_date {
+        indices.sort_by_key(|&index| library.modified(&series.books[index]));
+    } else {
+        indices.sort_by(|&a, &b| {
+            number_sort(&series.books[a], a).total_cmp(&number_sort(&series.books[b], b))
+        });
+    }
+    if descending {
+        indices.reverse();
+    }
+
+    let books = indices.into_iter().map(|index| library.book_dto(series, index)).collect();
+    Ok(Json(query.paginate(books)))
+}
+
+/// Series progress for Mihon's Komga tracker.
+#[get("/komga/api/v2/series/<id>/read-progress/tachiyomi")]
+pub async fn get_tachiyomi_progress(
+    auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    series_cache: &State<SeriesCache>,
+    progress_service: &State<ProgressService>,
+    id: String
+) -> Result<Json<TachiyomiReadProgressDto>, Status> {
+    let library = Library::load(&auth, comic_service, series_cache, progress_service).await?;
+    let series = library.find_series(&id)?;
+    let counts = library.counts(series);
+
+    let mut numbers: Vec<(f32, bool)> = series.books.iter()
+        .enumerate()
+        .map(|(index, book)| {
+            let read = ReadStatus::of(library.progress.get(&book.id)) == ReadStatus::Read;
+            (number_sort(book, index), read)
+        })
+        .collect();
+    numbers.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let last_read_continuous = numbers.iter()
+        .take_while(|(_, read)| *read)
+        .last()
+        .map_or(0.0, |(number, _)| *number);
+
+    Ok(Json(TachiyomiReadProgressDto {
+        books_count: series.books.len(),
+        books_read_count: counts.read,
+        books_unread_count: counts.unread,
+        books_in_progress_count: counts.in_progress,
+        last_read_continuous_number_sort: last_read_continuous,
+        max_number_sort: numbers.last().map_or(0.0, |(number, _)| *number),
+    }))
+}
+
+/// Marks every book up to and including a number read, as Mihon's tracker
+/// does when chapters are read on the device.
+#[put("/komga/api/v2/series/<id>/read-progress/tachiyomi", data = "<update>")]
+pub async fn update_tachiyomi_progress(
+    auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    series_cache: &State<SeriesCache>,
+    progress_service: &State<ProgressService>,
+    id: String,
+    update: Json<TachiyomiReadProgressUpdate>
+) -> Result<Status, Status> {
+    let library = Library::load(&auth, comic_service, series_cache, progress_service).await?;
+    let series = library.find_series(&id)?;
+    let comic_ids: Vec<String> = series.books.iter()
+        .enumerate()
+        .filter(|(index, book)| number_sort(book, *index) <= update.last_book_number_sort_read)
+        .map(|(_, book)| book.id.clone())
+        .collect();
+
+    let user_id = auth.user_id();
+    run_blocking(progress_service.inner(), move |progress| progress.mark(user_id, &comic_ids, true))
+        .await
+        .map_err(|e| {
+            println!("Error marking series read: {:?}", e);
+            Status::InternalServerError
+        })?;
+    Ok(Status::NoContent)
+}
+
+#[get("/komga/api/v1/books/<id>")]
+pub async fn get_book(
+    auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    series_cache: &State<SeriesCache>,
+    progress_service: &State<ProgressService>,
+    id: String
+) -> Result<Json<BookDto>, Status> {
+    let library = Library::load(&auth, comic_service, series_cache, progress_service).await?;
+    let (series, index) = library.find_book(&id)?;
+    Ok(Json(library.book_dto(series, index)))
+}
+
+#[get("/komga/api/v1/books/<id>/thumbnail")]
+pub async fn book_thumbnail(
+    _auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    id: String
+) -> Result<BinaryResponse, Status> {
+    thumbnail(comic_service, &id).await
+}
+
+#[get("/komga/api/v1/books/<id>/pages")]
+pub async fn list_book_pages(
+    _auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    id: String
+) -> Result<Json<Vec<PageDto>>, Status> {
+    let pages = comic_service.get_pages(&id).await.map_err(|e| {
+        println!("Error listing pages: {:?}", e);
+        status_for(e)
+    })?;
+
+    Ok(Json(pages.into_iter()
+        .map(|page| PageDto {
+            number: page.index + 1,
+            file_name: page.name,
+            media_type: page.mime,
+            size_bytes: page.size,
+        })
+        .collect()))
+}
+
+/// A page image by its one-based number. Pages are served as stored, so
+/// Komga's `convert` parameter is ignored.
+#[get("/komga/api/v1/books/<id>/pages/<number>")]
+pub async fn get_book_page(
+    _auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    id: String,
+    number: usize
+) -> Result<BinaryResponse, Status> {
+    let index = number.checked_sub(1).ok_or(Status::NotFound)?;
+    let (page, data) = comic_service.get_page(&id, index).await.map_err(|e| {
+        println!("Error getting page {} of {}: {:?}", number, id, e);
+        status_for(e)
+    })?;
+
+    Ok(BinaryResponse {
+        body: data.into(),
+        content_type: ContentType::parse_flexible(&page.mime).unwrap_or(ContentType::Binary),
+        filename: None,
+    })
+}
+
+#[patch("/komga/api/v1/books/<id>/read-progress", data = "<update>")]
+pub async fn update_book_progress(
+    auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    progress_service: &State<ProgressService>,
+    id: String,
+    update: Json<ReadProgressUpdate>
+) -> Result<Status, Status> {
+    let comic = comic_service.get_comic(&id).await.ok_or(Status::NotFound)?;
+    let progress = book_progress(comic, &update).ok_or(Status::BadRequest)?;
+    let user_id = auth.user_id();
+    run_blocking(progress_service.inner(), move |service| service.save(user_id, &progress))
+        .await
+        .map_err(|e| {
+            println!("Error saving reading progress: {:?}", e);
+            Status::InternalServerError
+        })?;
+    Ok(Status::NoContent)
+}
+
+/// The progress a Komga update describes, or `None` when it names no
+/// position. Pages past the end land on the last page, unless the comic's
+/// pages are not counted yet.
+fn book_progress(comic: Comic, update: &ReadProgressUpdate) -> Option<ReadProgress> {
+    let last_page = comic.page_count.saturating_sub(1);
+    let completed = update.completed.unwrap_or(false);
+    let page = match update.page {
+        Some(page) => page.saturating_sub(1),
+        None if completed => last_page,
+        None => return None,
+    };
+    let page = match comic.page_count {
+        0 => page,
+        page_count => page.min(page_count - 1),
+    };
+
+    Some(ReadProgress {
+        comic_id: comic.id,
+        page,
+        completed: completed || (comic.page_count > 0 && page == last_page),
+        last_read_at: unix_time(),
+        device: None,
+        device_id: None,
+    })
+}
+
+#[delete("/komga/api/v1/books/<id>/read-progress")]
+pub async fn delete_book_progress(
+    auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    progress_service: &State<ProgressService>,
+    id: String
+) -> Result<Status, Status> {
+    let comic = comic_service.get_comic(&id).await.ok_or(Status::NotFound)?;
+    let user_id = auth.user_id();
+    run_blocking(progress_service.inner(), move |progress| progress.clear(user_id, &comic.id))
+        .await
+        .map_err(|e| {
+            println!("Error clearing reading progress: {:?}", e);
+            Status::InternalServerError
+        })?;
+    Ok(Status::NoContent)
+}
+
+#[options("/komga/<_path..>")]
+pub fn komga_options(_path: std::path::PathBuf) -> Status {
+    Status::NoContent
+}
+
+fn library_dto() -> LibraryDto {
+    LibraryDto { id: LIBRARY_ID, name: LIBRARY_NAME, root: "/".to_string(), unavailable: false }
+}
+
+fn series_page(library: &Library, query: &ListQuery) -> Result<PageWrapper<SeriesDto>, Status> {
+    let statuses = query.read_statuses()?;
+    let mut matching: Vec<&Series> = library.index.series.iter()
+        .filter(|_| query.in_library())
+        .filter(|series| query.search.as_deref().is_none_or(|search| {
+            series.title.to_lowercase().contains(&search.to_lowercase())
+        }))
+        .filter(|series| statuses.is_empty() || statuses.contains(&library.series_status(series)))
+        .collect();
+
+    let (by_date, descending) = query.sort_order();
+    if by_date {
+        matching.sort_by_key(|series| library.series_modified(series));
+    }
+    if descending {
+        matching.reverse();
+    }
+
+    let series = matching.into_iter().map(|series| library.series_dto(series)).collect();
+    Ok(query.paginate(series))
+}
+
+async fn thumbnail(comic_service: &ComicService, id: &str) -> Result<BinaryResponse, Status> {
+    let cover = comic_service.get_thumbnail(id, THUMBNAIL_SIZE).await.map_err(|e| {
+        println!("Error rendering thumbnail: {:?}", e);
+        match e {
+            ComicError::ComicNotFound | ComicError::NoCoverFound => Status::NotFound,
+            _ => Status::InternalServerError,
+        }
+    })?;
+
+    Ok(BinaryResponse {
+        body: cover.data.into(),
+        content_type: ContentType::parse_flexible(&cover.mime).unwrap_or(ContentType::JPEG),
+        filename: None,
+    })
+}
+
+fn status_for(error: ComicError) -> Status {
+    match error {
+        ComicError::ComicNotFound | ComicError::PageNotFound => Status::NotFound,
+        ComicError::InvalidPath => Status::Forbidden,
+        _ => Status::InternalServerError,
+    }
+}
+
+fn read_progress_dto(progress: &ReadProgress) -> ReadProgressDto {
+    let read_date = format_timestamp(progress.last_read_at);
+    ReadProgressDto {
+        page: progress.page + 1,
+        completed: progress.completed,
+        read_date: read_date.clone(),
+        created: read_date.clone(),
+        last_modified: read_date,
+        device_id: progress.device_id.clone().unwrap_or_default(),
+        device_name: progress.device.clone().unwrap_or_default(),
+    }
+}
+
+/// The issue number from the metadata, or the position in the series.
+fn book_number(book: &Comic, index: usize) -> String {
+    book.metadata.as_ref()
+        .and_then(|metadata| metadata.number.clone())
+        .unwrap_or_else(|| (index + 1).to_string())
+}
+
+fn number_sort(book: &Comic, index: usize) -> f32 {
+    book.metadata.as_ref()
+        .and_then(|metadata| metadata.number.as_deref())
+        .and_then(|number| number.trim().parse().ok())
+        .unwrap_or((index + 1) as f32)
+}
+
+/// Komga dates are full `YYYY-MM-DD`; missing parts fall on the first.
+fn release_date(metadata: &ComicMetadata) -> Option<String> {
+    let year = metadata.year?;
+    Some(format!("{:04}-{:02}-{:02}", year, metadata.month.unwrap_or(1), metadata.day.unwrap_or(1)))
+}
+
+fn authors_of(metadata: &ComicMetadata) -> Vec<AuthorDto> {
+    let roles = [
+        (&metadata.writers, "writer"),
+        (&metadata.pencillers, "penciller"),
+        (&metadata.inkers, "inker"),
+        (&metadata.colorists, "colorist"),
+        (&metadata.letterers, "letterer"),
+        (&metadata.cover_artists, "cover"),
+        (&metadata.editors, "editor"),
+        (&metadata.translators, "translator"),
+    ];
+    roles.into_iter()
+        .flat_map(|(names, role)| names.iter().map(move |name| AuthorDto { name: name.clone(), role }))
+        .collect()
+}
+
+/// Human-readable size as Komga shows it, such as `12.3 MiB`.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comic(page_count: usize) -> Comic {
+        let mut comic = Comic::new(String::from("issue.cbz"), vec![], "cbz");
+        comic.page_count = page_count;
+        comic
+    }
+
+    fn update(page: Option<usize>, completed: Option<bool>) -> ReadProgressUpdate {
+        ReadProgressUpdate { page, completed }
+    }
+
+    fn position(progress: Option<ReadProgress>) -> Option<(usize, bool)> {
+        progress.map(|progress| (progress.page, progress.completed))
+    }
+
+    #[test]
+    fn converts_one_based_pages_within_the_book() {
+        assert_eq!(position(book_progress(comic(20), &update(Some(1), None))), Some((0, false)));
+        assert_eq!(position(book_progress(comic(20), &update(Some(20), None))), Some((19, true)));
+        assert_eq!(position(book_progress(comic(20), &update(Some(99), Some(false)))), Some((19, true)));
+        assert_eq!(position(book_progress(comic(20), &update(None, Some(true)))), Some((19, true)));
+        assert_eq!(position(book_progress(comic(20), &update(None, Some(false)))), None);
+    }
+
+    #[test]
+    fn keeps_pages_of_uncounted_books() {
+        assert_eq!(position(book_progress(comic(0), &update(Some(12), None))), Some((11, false)));
+        assert_eq!(position(book_progress(comic(0), &update(Some(12), Some(true)))), Some((11, true)));
+        assert_eq!(position(book_progress(comic(0), &update(None, Some(true)))), Some((0, true)));
+        assert_eq!(position(book_progress(comic(0), &update(None, None))), None);
+    }
+}
@@ -0,0 +1,247 @@
+use rocket::State;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use serde::{Deserialize, Serialize};
+
+use crate::models::comic::Comic;
+use crate::models::progress::ReadProgress;
+use crate::services::auth::KosyncAuth;
+use crate::services::comic_service::ComicService;
+use crate::services::database::{run_blocking, unix_time};
+use crate::services::progress::ProgressService;
+use crate::services::users::UserService;
+
+/// Device reported to KOReader for progress saved by the web reader.
+const WEB_DEVICE: &str = "Comic Reader";
+const WEB_DEVICE_ID: &str = "comic-reader-web";
+
+/// Error body in the format KOReader's sync plugin understands.
+#[derive(Serialize)]
+pub struct KosyncError {
+    code: u32,
+    message: &'static str,
+}
+
+type KosyncResult<T> = Result<Json<T>, (Status, Json<KosyncError>)>;
+
+fn kosync_error(status: Status, code: u32, message: &'static str) -> (Status, Json<KosyncError>) {
+    (status, Json(KosyncError { code, message }))
+}
+
+fn server_error<E: std::fmt::Debug>(error: E) -> (Status, Json<KosyncError>) {
+    println!("Error handling kosync request: {:?}", error);
+    kosync_error(Status::InternalServerError, 2000, "Unknown server error.")
+}
+
+#[catch(401)]
+pub fn unauthorized() -> Json<KosyncError> {
+    Json(KosyncError { code: 2001, message: "Unauthorized" })
+}
+
+#[derive(Deserialize)]
+pub struct Registration {
+    username: String,
+}
+
+#[derive(Serialize)]
+pub struct Registered {
+    username: String,
+}
+
+#[derive(Serialize)]
+pub struct Authorized {
+    authorized: &'static str,
+}
+
+#[derive(Serialize)]
+pub struct Health {
+    state: &'static str,
+}
+
+#[derive(Deserialize)]
+pub struct ProgressPush {
+    document: String,
+    /// Current page, one-based, for page-based documents such as comics.
+    progress: String,
+    percentage: f64,
+    device: String,
+    device_id: String,
+}
+
+#[derive(Serialize)]
+pub struct ProgressPushed {
+    document: String,
+    timestamp: i64,
+}
+
+/// A stored position. Empty when there is none, as KOReader expects.
+#[derive(Serialize, Default)]
+pub struct ProgressRecord {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    document: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    progress: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    percentage: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<i64>,
+}
+
+#[get("/kosync/healthcheck")]
+pub fn healthcheck() -> Json<Health> {
+    Json(Health { state: "OK" })
+}
+
+/// Accounts are managed by admins, so registration only succeeds on a
+/// server without accounts, where any credentials are accepted anyway.
+#[post("/kosync/users/create", data = "<registration>")]
+pub async fn create_user(
+    users: &State<UserService>,
+    registration: Json<Registration>
+) -> Result<(Status, Json<Registered>), (Status, Json<KosyncError>)> {
+    let username = registration.into_inner().username;
+    if !run_blocking(users.inner(), |users| users.auth_required()).await.map_err(server_error)? {
+        return Ok((Status::Created, Json(Registered { username })));
+    }
+
+    let exists = run_blocking(users.inner(), move |users| users.exists(&username)).await;
+    if exists.map_err(server_error)? {
+        return Err(kosync_error(Status::PaymentRequired, 2002, "Username is already registered."));
+    }
+    Err(kosync_error(Status::Forbidden, 2005, "User registration is disabled."))
+}
+
+#[get("/kosync/users/auth")]
+pub fn authorize(_auth: KosyncAuth) -> Json<Authorized> {
+    Json(Authorized { authorized: "OK" })
+}
+
+#[put("/kosync/syncs/progress", data = "<push>")]
+pub async fn push_progress(
+    auth: KosyncAuth,
+    comic_service: &State<ComicService>,
+    progress_service: &State<ProgressService>,
+    push: Json<ProgressPush>
+) -> KosyncResult<ProgressPushed> {
+    let push = push.into_inner();
+    let comic = comic_service.find_by_document(&push.document)
+        .await
+        .ok_or_else(|| kosync_error(Status::NotFound, 2004, "Document is not in the library."))?;
+
+    let progress = ReadProgress {
+        comic_id: comic.id.clone(),
+        page: page_index(&comic, &push),
+        completed: push.percentage >= 1.0,
+        last_read_at: unix_time(),
+        device: Some(push.device),
+        device_id: Some(push.device_id),
+    };
+    let user_id = auth.user_id();
+    let stored = run_blocking(progress_service.inner(), move |service| service.save(user_id, &progress))
+        .await
+        .map_err(server_error)?;
+
+    Ok(Json(ProgressPushed { document: push.document, timestamp: stored.last_read_at }))
+}
+
+#[get("/kosync/syncs/progress/<document>")]
+pub async fn get_progress(
+    auth: KosyncAuth,
+    comic_service: &State<ComicService>,
+    progress_service: &State<ProgressService>,
+    document: String
+) -> KosyncResult<ProgressRecord> {
+    let Some(comic) = comic_service.find_by_document(&document).await else {
+        return Ok(Json(ProgressRecord::default()));
+    };
+    let (user_id, comic_id) = (auth.user_id(), comic.id.clone());
+    let progress = run_blocking(progress_service.inner(), move |progress| progress.get(user_id, &comic_id))
+        .await
+        .map_err(server_error)?;
+    let Some(progress) = progress else {
+        return Ok(Json(ProgressRecord::default()));
+    };
+
+    let page = progress.page + 1;
+    let percentage = if progress.completed {
+        1.0
+    } else if comic.page_count == 0 {
+        0.0
+    } else {
+        (page as f64 / comic.page_count as f64).min(1.0)
+    };
+
+    Ok(Json(ProgressRecord {
+        document: Some(document),
+        progress: Some(page.to_string()),
+        percentage: Some(percentage),
+        device: Some(progress.device.unwrap_or_else(|| WEB_DEVICE.to_string())),
+        device_id: Some(progress.device_id.unwrap_or_else(|| WEB_DEVICE_ID.to_string())),
+        timestamp: Some(progress.last_read_at),
+    }))
+}
+
+/// Zero-based page from KOReader's one-based page number, falling back to
+/// the percentage when the position is not a page. Pages of a comic not
+/// counted yet are kept as sent.
+fn page_index(comic: &Comic, push: &ProgressPush) -> usize {
+    let page = match push.progress.trim().parse::<usize>() {
+        Ok(page) => page.saturating_sub(1),
+        Err(_) => (push.percentage.clamp(0.0, 1.0) * comic.page_count as f64) as usize,
+    };
+    match comic.page_count {
+        0 => page,
+        page_count => page.min(page_count - 1),
+    }
+}
+
+#[options("/kosync/<_path..>")]
+pub fn kosync_options(_path: std::path::PathBuf) -> Status {
+    Status::NoContent
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comic(page_count: usize) -> Comic {
+        let mut comic = Comic::new(String::from("issue.cbz"), vec![], "cbz");
+        comic.page_count = page_count;
+        comic
+    }
+
+    fn push(progress: &str, percentage: f64) -> ProgressPush {
+        ProgressPush {
+            document: String::from("0123456789abcdef0123456789abcdef"),
+            progress: progress.to_string(),
+            percentage,
+            device: String::from("KOReader"),
+            device_id: String::from("device"),
+        }
+    }
+
+    #[test]
+    fn converts_one_based_pages_within_the_comic() {
+        assert_eq!(page_index(&comic(20), &push("1", 0.0)), 0);
+        assert_eq!(page_index(&comic(20), &push(" 7 ", 0.3)), 6);
+        assert_eq!(page_index(&comic(20), &push("0", 0.0)), 0);
+        assert_eq!(page_index(&comic(20), &push("99", 1.0)), 19);
+    }
+
+    #[test]
+    fn falls_back_to_the_percentage() {
+        assert_eq!(page_index(&comic(20), &push("/body/DocFragment[3]", 0.5)), 10);
+        assert_eq!(page_index(&comic(20), &push("", 1.0)), 19);
+        assert_eq!(page_index(&comic(20), &push("", -0.5)), 0);
+    }
+
+    #[test]
+    fn keeps_pages_of_uncounted_comics() {
+        assert_eq!(page_index(&comic(0), &push("12", 0.5)), 11);
+        assert_eq!(page_index(&comic(0), &push("/body/DocFragment[3]", 0.5)), 0);
+    }
+}
@@ -1,2 +1,8 @@
 pub mod auth;
-pub mod comics;
\ No newline at end of file
+pub mod comics;
+pub mod komga;
+pub mod kosync;
+pub mod opds;
+pub mod opds2;
+pub mod progress;
+pub mod users;
\ No newline at end of file
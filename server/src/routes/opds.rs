@@ -0,0 +1,352 @@
+use std::collections::HashMap;
+
+use rocket::State;
+use rocket::http::{ContentType, Header, Status};
+
+use crate::models::comic::{Comic, Folder};
+use crate::models::progress::ReadProgress;
+use crate::services::auth::AuthGuard;
+use crate::services::catalog::{sort_by_name, PAGE_SIZE, REL_ACQUISITION};
+use crate::services::comic_service::ComicService;
+use crate::services::database::{run_blocking, unix_time};
+use crate::services::opds::{
+    self, Entry, Feed, Link, Pagination, ACQUISITION_TYPE, NAVIGATION_TYPE, OPENSEARCH_TYPE,
+    REL_IMAGE, REL_PSE_STREAM, REL_SUBSECTION, REL_THUMBNAIL,
+};
+use crate::services::progress::ProgressService;
+
+type OpdsResponse = (ContentType, String);
+
+/// Basic auth challenge, so OPDS readers know to ask for credentials.
+#[derive(Responder)]
+#[response(status = 401)]
+pub struct Challenge {
+    body: &'static str,
+    authenticate: Header<'static>,
+}
+
+#[catch(401)]
+pub fn unauthorized() -> Challenge {
+    Challenge {
+        body: "Authentication required",
+        authenticate: Header::new("WWW-Authenticate", "Basic realm=\"Comic Reader\", charset=\"UTF-8\""),
+    }
+}
+
+/// The catalog root: the folder tree, every comic, and search.
+#[get("/opds")]
+pub async fn catalog(_auth: AuthGuard) -> OpdsResponse {
+    let now = unix_time();
+    let mut feed = Feed::new("urn:comic-reader:catalog", "Comic Reader", now);
+    feed.links = catalog_links("/opds", NAVIGATION_TYPE);
+    feed.entries = vec![
+        navigation_entry("urn:comic-reader:folders", "Library", "Browse by folder", now,
+            Link::new(REL_SUBSECTION, "/opds/folders", NAVIGATION_TYPE)),
+        navigation_entry("urn:comic-reader:comics", "All comics", "Every comic by name", now,
+            Link::new(REL_SUBSECTION, "/opds/comics", ACQUISITION_TYPE)),
+    ];
+    atom(feed, "navigation")
+}
+
+/// Navigation feed for a folder: its subfolders, then its own comics.
+#[get("/opds/folders?<path>")]
+pub async fn folder_feed(
+    _auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    path: Option<String>
+) -> Result<OpdsResponse, Status> {
+    let folder_path = Folder::parse_path(path.as_deref().unwrap_or_default());
+    let root = comic_service.get_folder_structure().await;
+    let folder = root.find(&folder_path).ok_or(Status::NotFound)?;
+
+    let now = unix_time();
+    let mut feed = Feed::new(folder_id(&folder_path), folder_title(folder, &folder_path), now);
+    feed.links = catalog_links(&folder_href("/opds/folders", &folder_path), NAVIGATION_TYPE);
+
+    for subfolder in &folder.subfolders {
+        let mut subfolder_path = folder_path.clone();
+        subfolder_path.push(subfolder.name.clone());
+
+        // Folders holding only comics link straight to them
+        let link = if subfolder.subfolders.is_empty() {
+            Link::new(REL_SUBSECTION, folder_href("/opds/folders/comics", &subfolder_path), ACQUISITION_TYPE)
+        } else {
+            Link::new(REL_SUBSECTION, folder_href("/opds/folders", &subfolder_path), NAVIGATION_TYPE)
+        };
+        feed.entries.push(navigation_entry(
+            folder_id(&subfolder_path),
+            &subfolder.name,
+            &comics_label(comic_count(subfolder)),
+            now,
+            link,
+        ));
+    }
+
+    if !folder.comics.is_empty() {
+        feed.entries.push(navigation_entry(
+            format!("{}:comics", folder_id(&folder_path)),
+            &format!("Comics in {}", folder_title(folder, &folder_path)),
+            &comics_label(folder.comics.len()),
+            now,
+            Link::new(REL_SUBSECTION, folder_href("/opds/folders/comics", &folder_path), ACQUISITION_TYPE),
+        ));
+    }
+
+    Ok(atom(feed, "navigation"))
+}
+
+/// Acquisition feed for the comics directly inside a folder.
+#[get("/opds/folders/comics?<path>&<page>")]
+pub async fn folder_comics_feed(
+    auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    progress_service: &State<ProgressService>,
+    path: Option<String>,
+    page: Option<usize>
+) -> Result<OpdsResponse, Status> {
+    let folder_path = Folder::parse_path(path.as_deref().unwrap_or_default());
+    let root = comic_service.get_folder_structure().await;
+    let folder = root.find(&folder_path).ok_or(Status::NotFound)?;
+
+    let feed = acquisition_feed(
+        comic_service,
+        &user_progress(&auth, progress_service).await?,
+        format!("{}:comics", folder_id(&folder_path)),
+        folder_title(folder, &folder_path),
+        &folder_href("/opds/folders/comics", &folder_path),
+        folder.comics.clone(),
+        page,
+    )?;
+    Ok(atom(feed, "acquisition"))
+}
+
+#[get("/opds/comics?<page>")]
+pub async fn all_comics_feed(
+    auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    progress_service: &State<ProgressService>,
+    page: Option<usize>
+) -> Result<OpdsResponse, Status> {
+    let mut comics = comic_service.get_all_comics().await;
+    sort_by_name(&mut comics);
+
+    let feed = acquisition_feed(
+        comic_service,
+        &user_progress(&auth, progress_service).await?,
+        "urn:comic-reader:comics".to_string(),
+        "All comics".to_string(),
+        "/opds/comics",
+        comics,
+        page,
+    )?;
+    Ok(atom(feed, "acquisition"))
+}
+
+#[get("/opds/search?<q>&<page>")]
+pub async fn search_feed(
+    auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    progress_service: &State<ProgressService>,
+    q: String,
+    page: Option<usize>
+) -> Result<OpdsResponse, Status> {
+    let mut comics = comic_service.search_comics(&q).await;
+    sort_by_name(&mut comics);
+
+    let feed = acquisition_feed(
+        comic_service,
+        &user_progress(&auth, progress_service).await?,
+        format!("urn:comic-reader:search:{}", q),
+        format!("Search: {}", q),
+        &format!("/opds/search?q={}", urlencoding::encode(&q)),
+        comics,
+        page,
+    )?;
+    Ok(atom(feed, "acquisition"))
+}
+
+#[get("/opds/opensearch.xml")]
+pub async fn opensearch(_auth: AuthGuard) -> OpdsResponse {
+    let content_type = ContentType::new("application", "opensearchdescription+xml");
+    (content_type, opds::opensearch_description("/opds/search?q={searchTerms}"))
+}
+
+/// Builds one page of an acquisition feed. Pages are numbered from 1.
+fn acquisition_feed(
+    comic_service: &ComicService,
+    progress: &HashMap<String, ReadProgress>,
+    id: String,
+    title: String,
+    href: &str,
+    comics: Vec<Comic>,
+    page: Option<usize>,
+) -> Result<Feed, Status> {
+    let total = comics.len();
+    let last_page = total.div_ceil(PAGE_SIZE).max(1);
+    let page = page.unwrap_or(1);
+    if page == 0 || page > last_page {
+        return Err(Status::NotFound);
+    }
+
+    let now = unix_time();
+    let mut feed = Feed::new(id, title, now);
+    feed.links = catalog_links(&page_href(href, page), ACQUISITION_TYPE);
+    feed.links.push(Link::new("first", page_href(href, 1), ACQUISITION_TYPE));
+    if page > 1 {
+        feed.links.push(Link::new("previous", page_href(href, page - 1), ACQUISITION_TYPE));
+    }
+    if page < last_page {
+        feed.links.push(Link::new("next", page_href(href, page + 1), ACQUISITION_TYPE));
+    }
+    feed.links.push(Link::new("last", page_href(href, last_page), ACQUISITION_TYPE));
+
+    let start = (page - 1) * PAGE_SIZE;
+    feed.pagination = Some(Pagination {
+        total,
+        per_page: PAGE_SIZE,
+        start_index: start + 1,
+    });
+    feed.entries = comics.iter()
+        .skip(start)
+        .take(PAGE_SIZE)
+        .map(|comic| comic_entry(comic_service, comic, progress.get(&comic.id), now))
+        .collect();
+    Ok(feed)
+}
+
+fn comic_entry(
+    comic_service: &ComicService,
+    comic: &Comic,
+    progress: Option<&ReadProgress>,
+    updated: i64,
+) -> Entry {
+    let content_type = comic_service.archives().content_type(&comic.format);
+
+    let mut entry = Entry {
+        id: format!("urn:comic-reader:comic:{}", comic.id),
+        title: comic.name.clone(),
+        updated,
+        links: vec![
+            // Covers are re-encoded as JPEG whenever a size is asked for
+            Link::new(REL_IMAGE, format!("/covers/{}?size=large", comic.id), "image/jpeg"),
+            Link::new(REL_THUMBNAIL, format!("/covers/{}?size=medium", comic.id), "image/jpeg"),
+            Link::new(REL_ACQUISITION, format!("/comics/{}", comic.id), content_type.to_string())
+                .titled(comic.file_name.clone()),
+        ],
+        ..Entry::default()
+    };
+
+    // OPDS-PSE: readers fill in the zero-based page and their screen width
+    if comic.page_count > 0 {
+        let mut stream = Link::new(
+            REL_PSE_STREAM,
+            format!("/comics/{}/pages/{{pageNumber}}?width={{maxWidth}}", comic.id),
+            "image/jpeg",
+        ).with_attribute("pse:count", comic.page_count.to_string());
+        if let Some(progress) = progress {
+            stream = stream
+                .with_attribute("pse:lastRead", progress.page.to_string())
+                .with_attribute("pse:lastReadDate", opds::format_timestamp(progress.last_read_at));
+        }
+        entry.links.push(stream);
+    }
+
+    if let Some(metadata) = &comic.metadata {
+        if let Some(title) = &metadata.title {
+            entry.title = title.clone();
+        }
+        entry.authors = metadata.writers.clone();
+        entry.summary = metadata.summary.clone();
+        entry.publisher = metadata.publisher.clone();
+        entry.language = metadata.language.clone();
+        entry.issued = metadata.release_date();
+    }
+    entry
+}
+
+async fn user_progress(
+    auth: &AuthGuard,
+    progress_service: &ProgressService,
+) -> Result<HashMap<String, ReadProgress>, Status> {
+    let user_id = auth.user_id();
+    run_blocking(progress_service, move |progress| progress.by_comic(user_id))
+        .await
+        .map_err(|e| {
+            println!("Error loading reading progress: {:?}", e);
+            Status::InternalServerError
+        })
+}
+
+fn navigation_entry(id: impl Into<String>, title: &str, content: &str, updated: i64, link: Link) -> Entry {
+    Entry {
+        id: id.into(),
+        title: title.to_string(),
+        updated,
+        content: Some(content.to_string()),
+        links: vec![link],
+        ..Entry::default()
+    }
+}
+
+/// Links every feed carries: itself, the catalog root and search.
+fn catalog_links(self_href: &str, kind: &str) -> Vec<Link> {
+    vec![
+        Link::new("self", self_href, kind),
+        Link::new("start", "/opds", NAVIGATION_TYPE),
+        Link::new("search", "/opds/opensearch.xml", OPENSEARCH_TYPE),
+    ]
+}
+
+fn atom(feed: Feed, kind: &'static str) -> OpdsResponse {
+    let content_type = ContentType::new("application", "atom+xml")
+        .with_params([("profile", "opds-catalog"), ("kind", kind)]);
+    (content_type, feed.to_xml())
+}
+
+fn folder_href(base: &str, folder_path: &[String]) -> String {
+    if folder_path.is_empty() {
+        base.to_string()
+    } else {
+        format!("{}?path={}", base, urlencoding::encode(&folder_path.join("/")))
+    }
+}
+
+fn page_href(href: &str, page: usize) -> String {
+    let separator = if href.contains('?') { '&' } else { '?' };
+    format!("{}{}page={}", href, separator, page)
+}
+
+fn folder_id(folder_path: &[String]) -> String {
+    format!("urn:comic-reader:folder:{}", urlencoding::encode(&folder_path.join("/")))
+}
+
+fn folder_title(folder: &Folder, folder_path: &[String]) -> String {
+    if folder_path.is_empty() {
+        "Library".to_string()
+    } else {
+        folder.name.clone()
+    }
+}
+
+fn comic_count(folder: &Folder) -> usize {
+    let mut count = 0;
+    folder.walk(&mut |folder| count += folder.comics.len());
+    count
+}
+
+fn comics_label(count: usize) -> String {
+    match count {
+        1 => "1 comic".to_string(),
+        count => format!("{} comics", count),
+    }
+}
+
+#[options("/opds")]
+pub fn catalog_options() -> Status {
+    Status::NoContent
+}
+
+#[options("/opds/<_path..>")]
+pub fn opds_options(_path: std::path::PathBuf) -> Status {
+    Status::NoContent
+}
@@ -0,0 +1,481 @@
+use std::collections::{BTreeMap, HashMap};
+
+use rocket::State;
+use rocket::http::{ContentType, Status};
+use rocket::serde::json::Json;
+
+use crate::models::comic::{Comic, Folder};
+use crate::models::progress::ReadStatus;
+use crate::services::auth::AuthGuard;
+use crate::services::catalog::{series_names, sort_by_name, PAGE_SIZE, REL_ACQUISITION};
+use crate::services::comic_service::ComicService;
+use crate::services::database::run_blocking;
+use crate::services::opds2::{
+    BelongsTo, FacetGroup, Feed, Group, Image, Link, Metadata, Publication, PublicationMetadata,
+    SeriesMembership, FEED_TYPE,
+};
+use crate::services::progress::ProgressService;
+
+/// Publications previewed in each folder or series group.
+const GROUP_SIZE: usize = 10;
+/// Series groups per page of the series feed.
+const SERIES_PER_PAGE: usize = 20;
+
+const PUBLICATIONS_PATH: &str = "/opds/v2/publications.json";
+
+type Opds2Response = (ContentType, Json<Feed>);
+
+/// Narrows the publications feed. `query`, `folder` and `series` pick the
+/// comics; `read`, `publisher` and `year` are facets offered within them.
+#[derive(FromForm, Clone, Default)]
+pub struct PublicationFilter {
+    query: Option<String>,
+    folder: Option<String>,
+    series: Option<String>,
+    read: Option<String>,
+    publisher: Option<String>,
+    year: Option<i32>,
+    page: Option<usize>,
+}
+
+impl PublicationFilter {
+    fn for_series(series: &str) -> Self {
+        PublicationFilter { series: Some(series.to_string()), ..PublicationFilter::default() }
+    }
+
+    fn href(&self) -> String {
+        let mut params = Vec::new();
+        let mut push = |name: &str, value: Option<String>| {
+            if let Some(value) = value {
+                params.push(format!("{}={}", name, urlencoding::encode(&value)));
+            }
+        };
+        push("query", self.query.clone());
+        push("folder", self.folder.clone());
+        push("series", self.series.clone());
+        push("read", self.read.clone());
+        push("publisher", self.publisher.clone());
+        push("year", self.year.map(|year| year.to_string()));
+        push("page", self.page.map(|page| page.to_string()));
+
+        if params.is_empty() {
+            PUBLICATIONS_PATH.to_string()
+        } else {
+            format!("{}?{}", PUBLICATIONS_PATH, params.join("&"))
+        }
+    }
+
+    /// The same filter on its first page, with one facet changed.
+    fn with_facet(&self, facet: Facet, value: Option<String>) -> Self {
+        let mut filter = PublicationFilter { page: None, ..self.clone() };
+        match facet {
+            Facet::Read => filter.read = value,
+            Facet::Publisher => filter.publisher = value,
+            Facet::Year => filter.year = value.and_then(|year| year.parse().ok()),
+        }
+        filter
+    }
+
+    fn in_scope(&self, comic: &Comic) -> bool {
+        let query = self.query.as_deref().is_none_or(|query| comic.matches_search(query));
+        let folder = self.folder.as_deref()
+            .is_none_or(|folder| comic.folder_path.starts_with(&Folder::parse_path(folder)));
+        let series = self.series.as_deref().is_none_or(|series| {
+            comic.series.as_deref().is_some_and(|s| s.eq_ignore_ascii_case(series))
+        });
+        query && folder && series
+    }
+
+    /// Checks the active facets, skipping `except` so a facet group can
+    /// count its own options.
+    fn matches_facets(&self, comic: &Comic, status: ReadStatus, except: Option<Facet>) -> bool {
+        let metadata = comic.metadata.as_ref();
+        let read = except == Some(Facet::Read)
+            || self.read.as_deref().is_none_or(|read| read == status.as_str());
+        let publisher = except == Some(Facet::Publisher)
+            || self.publisher.as_deref().is_none_or(|publisher| {
+                metadata.and_then(|m| m.publisher.as_deref()) == Some(publisher)
+            });
+        let year = except == Some(Facet::Year)
+            || self.year.is_none_or(|year| metadata.and_then(|m| m.year) == Some(year));
+        read && publisher && year
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Facet {
+    Read,
+    Publisher,
+    Year,
+}
+
+/// The catalog root, with a preview group per top-level folder.
+#[get("/opds/v2/catalog.json")]
+pub async fn catalog(
+    _auth: AuthGuard,
+    comic_service: &State<ComicService>
+) -> Opds2Response {
+    let root = comic_service.get_folder_structure().await;
+    let comics = comic_service.get_all_comics().await;
+    let series_count = series_names(&comics).len();
+
+    let mut feed = Feed {
+        metadata: Metadata::titled("Comic Reader"),
+        links: feed_links("/opds/v2/catalog.json"),
+        ..Feed::default()
+    };
+    feed.navigation = vec![
+        Link::new("/opds/v2/folders.json", FEED_TYPE).titled("Library").count(comics.len()),
+        Link::new(PUBLICATIONS_PATH, FEED_TYPE).titled("All comics").count(comics.len()),
+        Link::new("/opds/v2/series.json", FEED_TYPE).titled("Series").count(series_count),
+    ];
+
+    for folder in &root.subfolders {
+        let mut folder_comics = Vec::new();
+        folder.walk(&mut |folder| folder_comics.extend(folder.comics.iter().cloned()));
+        sort_by_name(&mut folder_comics);
+
+        feed.groups.push(Group {
+            metadata: Metadata {
+                number_of_items: Some(folder_comics.len()),
+                ..Metadata::titled(folder.name.clone())
+            },
+            links: vec![
+                Link::new(folder_href(std::slice::from_ref(&folder.name), None), FEED_TYPE).rel("self"),
+            ],
+            publications: folder_comics.iter()
+                .take(GROUP_SIZE)
+                .map(|comic| publication(comic_service, comic))
+                .collect(),
+            ..Group::default()
+        });
+    }
+
+    json_feed(feed)
+}
+
+/// A folder: its subfolders as navigation and its own comics as
+/// publications.
+#[get("/opds/v2/folders.json?<path>&<page>")]
+pub async fn folder_feed(
+    _auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    path: Option<String>,
+    page: Option<usize>
+) -> Result<Opds2Response, Status> {
+    let folder_path = Folder::parse_path(path.as_deref().unwrap_or_default());
+    let root = comic_service.get_folder_structure().await;
+    let folder = root.find(&folder_path).ok_or(Status::NotFound)?;
+
+    let page = page.unwrap_or(1);
+    let (start, last_page) = page_bounds(folder.comics.len(), PAGE_SIZE, page)?;
+    let title = if folder_path.is_empty() { "Library".to_string() } else { folder.name.clone() };
+
+    let mut feed = Feed {
+        metadata: Metadata {
+            number_of_items: Some(folder.comics.len()),
+            items_per_page: Some(PAGE_SIZE),
+            current_page: Some(page),
+            ..Metadata::titled(title)
+        },
+        links: feed_links(&folder_href(&folder_path, Some(page))),
+        ..Feed::default()
+    };
+    feed.links.extend(page_links(page, last_page, |page| folder_href(&folder_path, Some(page))));
+
+    for subfolder in &folder.subfolders {
+        let mut subfolder_path = folder_path.clone();
+        subfolder_path.push(subfolder.name.clone());
+
+        let mut count = 0;
+        subfolder.walk(&mut |folder| count += folder.comics.len());
+        feed.navigation.push(
+            Link::new(folder_href(&subfolder_path, None), FEED_TYPE)
+                .titled(subfolder.name.clone())
+                .count(count)
+        );
+    }
+
+    feed.publications = folder.comics.iter()
+        .skip(start)
+        .take(PAGE_SIZE)
+        .map(|comic| publication(comic_service, comic))
+        .collect();
+    Ok(json_feed(feed))
+}
+
+/// A preview group per series.
+#[get("/opds/v2/series.json?<page>")]
+pub async fn series_feed(
+    _auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    page: Option<usize>
+) -> Result<Opds2Response, Status> {
+    let mut comics = comic_service.get_all_comics().await;
+    sort_by_name(&mut comics);
+    let series = series_names(&comics);
+
+    let page = page.unwrap_or(1);
+    let (start, last_page) = page_bounds(series.len(), SERIES_PER_PAGE, page)?;
+    let href = |page: usize| format!("/opds/v2/series.json?page={}", page);
+
+    let mut feed = Feed {
+        metadata: Metadata {
+            number_of_items: Some(series.len()),
+            items_per_page: Some(SERIES_PER_PAGE),
+            current_page: Some(page),
+            ..Metadata::titled("Series")
+        },
+        links: feed_links(&href(page)),
+        ..Feed::default()
+    };
+    feed.links.extend(page_links(page, last_page, href));
+
+    for name in series.iter().skip(start).take(SERIES_PER_PAGE) {
+        let filter = PublicationFilter::for_series(name);
+        let members: Vec<&Comic> = comics.iter().filter(|comic| filter.in_scope(comic)).collect();
+
+        feed.groups.push(Group {
+            metadata: Metadata {
+                number_of_items: Some(members.len()),
+                ..Metadata::titled(name.clone())
+            },
+            links: vec![Link::new(filter.href(), FEED_TYPE).rel("self")],
+            publications: members.iter()
+                .take(GROUP_SIZE)
+                .map(|comic| publication(comic_service, comic))
+                .collect(),
+            ..Group::default()
+        });
+    }
+
+    Ok(json_feed(feed))
+}
+
+/// Publications matching a filter, with read status, publisher and year
+/// facets. This is also the search endpoint.
+#[get("/opds/v2/publications.json?<filter..>")]
+pub async fn publications(
+    auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    progress_service: &State<ProgressService>,
+    filter: PublicationFilter
+) -> Result<Opds2Response, Status> {
+    if filter.read.as_deref().is_some_and(|read| ReadStatus::parse(read).is_none()) {
+        return Err(Status::BadRequest);
+    }
+
+    let user_id = auth.user_id();
+    let progress = run_blocking(progress_service.inner(), move |progress| progress.by_comic(user_id))
+        .await
+        .map_err(|e| {
+            println!("Error loading reading progress: {:?}", e);
+            Status::InternalServerError
+        })?;
+
+    let mut scoped = comic_service.get_all_comics().await;
+    scoped.retain(|comic| filter.in_scope(comic));
+    sort_by_name(&mut scoped);
+    let scoped: Vec<(&Comic, ReadStatus)> = scoped.iter()
+        .map(|comic| (comic, ReadStatus::of(progress.get(&comic.id))))
+        .collect();
+
+    let matching: Vec<&Comic> = scoped.iter()
+        .filter(|(comic, status)| filter.matches_facets(comic, *status, None))
+        .map(|(comic, _)| *comic)
+        .collect();
+
+    let page = filter.page.unwrap_or(1);
+    let (start, last_page) = page_bounds(matching.len(), PAGE_SIZE, page)?;
+    let href = |page: usize| PublicationFilter { page: Some(page), ..filter.clone() }.href();
+
+    let title = match (&filter.query, &filter.series) {
+        (Some(query), _) => format!("Search: {}", query),
+        (None, Some(series)) => series.clone(),
+        (None, None) => "All comics".to_string(),
+    };
+    let mut feed = Feed {
+        metadata: Metadata {
+            number_of_items: Some(matching.len()),
+            items_per_page: Some(PAGE_SIZE),
+            current_page: Some(page),
+            ..Metadata::titled(title)
+        },
+        links: feed_links(&href(page)),
+        facets: facet_groups(&filter, &scoped),
+        ..Feed::default()
+    };
+    feed.links.extend(page_links(page, last_page, href));
+
+    feed.publications = matching.iter()
+        .skip(start)
+        .take(PAGE_SIZE)
+        .map(|comic| publication(comic_service, comic))
+        .collect();
+    Ok(json_feed(feed))
+}
+
+fn facet_groups(filter: &PublicationFilter, scoped: &[(&Comic, ReadStatus)]) -> Vec<FacetGroup> {
+    let candidates = |except: Facet| scoped.iter()
+        .filter(move |(comic, status)| filter.matches_facets(comic, *status, Some(except)));
+
+    let mut read_counts: HashMap<&'static str, usize> = HashMap::new();
+    for (_, status) in candidates(Facet::Read) {
+        *read_counts.entry(status.as_str()).or_default() += 1;
+    }
+    let read_options = ReadStatus::ALL.iter()
+        .map(|status| {
+            let label = match status {
+                ReadStatus::Unread => "Unread",
+                ReadStatus::InProgress => "In progress",
+                ReadStatus::Read => "Read",
+            };
+            let count = read_counts.get(status.as_str()).copied().unwrap_or(0);
+            (status.as_str().to_string(), label.to_string(), count)
+        })
+        .collect();
+
+    let mut publishers: BTreeMap<String, usize> = BTreeMap::new();
+    for (comic, _) in candidates(Facet::Publisher) {
+        if let Some(publisher) = comic.metadata.as_ref().and_then(|m| m.publisher.clone()) {
+            *publishers.entry(publisher).or_default() += 1;
+        }
+    }
+    let publisher_options = publishers.into_iter()
+        .map(|(publisher, count)| (publisher.clone(), publisher, count))
+        .collect();
+
+    let mut years: BTreeMap<i32, usize> = BTreeMap::new();
+    for (comic, _) in candidates(Facet::Year) {
+        if let Some(year) = comic.metadata.as_ref().and_then(|m| m.year) {
+            *years.entry(year).or_default() += 1;
+        }
+    }
+    let year_options = years.into_iter()
+        .rev()
+        .map(|(year, count)| (year.to_string(), year.to_string(), count))
+        .collect();
+
+    vec![
+        facet_group(filter, Facet::Read, "Read status", filter.read.clone(), read_options),
+        facet_group(filter, Facet::Publisher, "Publisher", filter.publisher.clone(), publisher_options),
+        facet_group(filter, Facet::Year, "Year", filter.year.map(|year| year.to_string()), year_options),
+    ]
+}
+
+/// A facet group with an "All" link followed by `(value, title, count)`
+/// options. The active option is marked `self`.
+fn facet_group(
+    filter: &PublicationFilter,
+    facet: Facet,
+    title: &str,
+    active: Option<String>,
+    options: Vec<(String, String, usize)>,
+) -> FacetGroup {
+    let mut all = Link::new(filter.with_facet(facet, None).href(), FEED_TYPE).titled("All");
+    if active.is_none() {
+        all = all.rel("self");
+    }
+
+    let mut links = vec![all];
+    for (value, option_title, count) in options {
+        let mut link = Link::new(filter.with_facet(facet, Some(value.clone())).href(), FEED_TYPE)
+            .titled(option_title)
+            .count(count);
+        if active.as_deref() == Some(value.as_str()) {
+            link = link.rel("self");
+        }
+        links.push(link);
+    }
+
+    FacetGroup { metadata: Metadata::titled(title), links }
+}
+
+fn publication(comic_service: &ComicService, comic: &Comic) -> Publication {
+    let content_type = comic_service.archives().content_type(&comic.format);
+
+    let mut metadata = PublicationMetadata {
+        kind: "http://schema.org/ComicIssue",
+        identifier: format!("urn:comic-reader:comic:{}", comic.id),
+        title: comic.name.clone(),
+        number_of_pages: (comic.page_count > 0).then_some(comic.page_count),
+        ..PublicationMetadata::default()
+    };
+    let mut position = None;
+    if let Some(comic_metadata) = &comic.metadata {
+        if let Some(title) = &comic_metadata.title {
+            metadata.title = title.clone();
+        }
+        metadata.author = comic_metadata.writers.clone();
+        metadata.publisher = comic_metadata.publisher.clone();
+        metadata.published = comic_metadata.release_date();
+        metadata.language = comic_metadata.language.clone();
+        metadata.description = comic_metadata.summary.clone();
+        position = comic_metadata.number.as_deref().and_then(|number| number.parse().ok());
+    }
+    metadata.belongs_to = comic.series.clone().map(|name| BelongsTo {
+        series: vec![SeriesMembership { name, position }],
+    });
+
+    Publication {
+        metadata,
+        links: vec![
+            Link::new(format!("/comics/{}", comic.id), content_type.to_string()).rel(REL_ACQUISITION),
+        ],
+        // Covers are re-encoded as JPEG whenever a size is asked for
+        images: vec![
+            Image { href: format!("/covers/{}?size=large", comic.id), kind: "image/jpeg", width: None },
+            Image { href: format!("/covers/{}?size=medium", comic.id), kind: "image/jpeg", width: Some(300) },
+        ],
+    }
+}
+
+/// Links every feed carries: itself, the catalog root and search.
+fn feed_links(self_href: &str) -> Vec<Link> {
+    vec![
+        Link::new(self_href, FEED_TYPE).rel("self"),
+        Link::new("/opds/v2/catalog.json", FEED_TYPE).rel("start"),
+        Link::new(format!("{}{{?query}}", PUBLICATIONS_PATH), FEED_TYPE).rel("search").templated(),
+    ]
+}
+
+/// Offset of the first item on a one-based `page`, and the last page.
+fn page_bounds(total: usize, per_page: usize, page: usize) -> Result<(usize, usize), Status> {
+    let last_page = total.div_ceil(per_page).max(1);
+    if page == 0 || page > last_page {
+        return Err(Status::NotFound);
+    }
+    Ok(((page - 1) * per_page, last_page))
+}
+
+fn page_links(page: usize, last_page: usize, href: impl Fn(usize) -> String) -> Vec<Link> {
+    let mut links = vec![Link::new(href(1), FEED_TYPE).rel("first")];
+    if page > 1 {
+        links.push(Link::new(href(page - 1), FEED_TYPE).rel("previous"));
+    }
+    if page < last_page {
+        links.push(Link::new(href(page + 1), FEED_TYPE).rel("next"));
+    }
+    links.push(Link::new(href(last_page), FEED_TYPE).rel("last"));
+    links
+}
+
+fn folder_href(folder_path: &[String], page: Option<usize>) -> String {
+    let mut params = Vec::new();
+    if !folder_path.is_empty() {
+        params.push(format!("path={}", urlencoding::encode(&folder_path.join("/"))));
+    }
+    if let Some(page) = page {
+        params.push(format!("page={}", page));
+    }
+
+    if params.is_empty() {
+        "/opds/v2/folders.json".to_string()
+    } else {
+        format!("/opds/v2/folders.json?{}", params.join("&"))
+    }
+}
+
+fn json_feed(feed: Feed) -> Opds2Response {
+    (ContentType::new("application", "opds+json"), Json(feed))
+}
@@ -0,0 +1,203 @@
+use rocket::State;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use serde::Deserialize;
+
+use crate::models::error::ComicError;
+use crate::models::comic::{Comic, Folder};
+use crate::models::progress::{ReadCounts, ReadProgress};
+use crate::services::auth::AuthGuard;
+use crate::services::comic_service::ComicService;
+use crate::services::database::{run_blocking, unix_time};
+use crate::services::progress::ProgressService;
+
+#[derive(Deserialize)]
+pub struct ProgressUpdate {
+    page: usize,
+    #[serde(default)]
+    completed: bool,
+    /// When the device read the page; defaults to now. Offline devices
+    /// should send the original time so newer reads elsewhere win.
+    last_read_at: Option<i64>,
+}
+
+#[get("/progress")]
+pub async fn list_progress(
+    auth: AuthGuard,
+    progress_service: &State<ProgressService>
+) -> Result<Json<Vec<ReadProgress>>, Status> {
+    let user_id = auth.user_id();
+    run_blocking(progress_service.inner(), move |progress| progress.all(user_id))
+        .await
+        .map(Json)
+        .map_err(progress_error_status)
+}
+
+#[get("/comics/<id>/progress")]
+pub async fn get_progress(
+    auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    progress_service: &State<ProgressService>,
+    id: String
+) -> Result<Json<ReadProgress>, Status> {
+    let (user_id, comic_id) = (auth.user_id(), canonical_id(comic_service, &id).await?);
+    run_blocking(progress_service.inner(), move |progress| progress.get(user_id, &comic_id))
+        .await
+        .map_err(progress_error_status)?
+        .map(Json)
+        .ok_or(Status::NotFound)
+}
+
+/// Returns the progress stored after the update, which is the previous one
+/// when the update turned out to be older. Pages past the end land on the
+/// last page, and times in the future count as now, so a device with a
+/// fast clock cannot pin its progress above every later read.
+#[put("/comics/<id>/progress", data = "<update>")]
+pub async fn save_progress(
+    auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    progress_service: &State<ProgressService>,
+    id: String,
+    update: Json<ProgressUpdate>
+) -> Result<Json<ReadProgress>, Status> {
+    let comic = comic_service.get_comic(&id)
+        .await
+        .ok_or(Status::NotFound)?;
+    let now = unix_time();
+    let progress = ReadProgress {
+        page: match comic.page_count {
+            // Not counted yet
+            0 => update.page,
+            page_count => update.page.min(page_count - 1),
+        },
+        comic_id: comic.id,
+        completed: update.completed,
+        last_read_at: update.last_read_at.map_or(now, |last_read_at| last_read_at.min(now)),
+        device: None,
+        device_id: None,
+    };
+
+    let user_id = auth.user_id();
+    run_blocking(progress_service.inner(), move |service| service.save(user_id, &progress))
+        .await
+        .map(Json)
+        .map_err(progress_error_status)
+}
+
+#[delete("/comics/<id>/progress")]
+pub async fn delete_progress(
+    auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    progress_service: &State<ProgressService>,
+    id: String
+) -> Result<Status, Status> {
+    let (user_id, comic_id) = (auth.user_id(), canonical_id(comic_service, &id).await?);
+    run_blocking(progress_service.inner(), move |progress| progress.clear(user_id, &comic_id))
+        .await
+        .map_err(progress_error_status)?;
+    Ok(Status::NoContent)
+}
+
+/// Marks every comic in a folder, subfolders included, as read. `path` is
+/// the folder's `/`-separated path; leaving it out marks the whole library.
+#[put("/folders/read?<path>")]
+pub async fn mark_folder_read(
+    auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    progress_service: &State<ProgressService>,
+    path: Option<String>
+) -> Result<Json<ReadCounts>, Status> {
+    let folder_path = Folder::parse_path(path.as_deref().unwrap_or_default());
+    let comics = comic_service.comics_in_folder(&folder_path).await;
+    mark_comics(&auth, progress_service, &comics, true).await
+}
+
+#[delete("/folders/read?<path>")]
+pub async fn mark_folder_unread(
+    auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    progress_service: &State<ProgressService>,
+    path: Option<String>
+) -> Result<Json<ReadCounts>, Status> {
+    let folder_path = Folder::parse_path(path.as_deref().unwrap_or_default());
+    let comics = comic_service.comics_in_folder(&folder_path).await;
+    mark_comics(&auth, progress_service, &comics, false).await
+}
+
+#[put("/series/<name>/read")]
+pub async fn mark_series_read(
+    auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    progress_service: &State<ProgressService>,
+    name: String
+) -> Result<Json<ReadCounts>, Status> {
+    let comics = comic_service.comics_in_series(&name).await;
+    mark_comics(&auth, progress_service, &comics, true).await
+}
+
+#[delete("/series/<name>/read")]
+pub async fn mark_series_unread(
+    auth: AuthGuard,
+    comic_service: &State<ComicService>,
+    progress_service: &State<ProgressService>,
+    name: String
+) -> Result<Json<ReadCounts>, Status> {
+    let comics = comic_service.comics_in_series(&name).await;
+    mark_comics(&auth, progress_service, &comics, false).await
+}
+
+/// Marks the comics and returns their counts afterwards.
+async fn mark_comics(
+    auth: &AuthGuard,
+    progress_service: &ProgressService,
+    comics: &[Comic],
+    read: bool,
+) -> Result<Json<ReadCounts>, Status> {
+    if comics.is_empty() {
+        return Err(Status::NotFound);
+    }
+
+    let user_id = auth.user_id();
+    let comic_ids: Vec<String> = comics.iter().map(|comic| comic.id.clone()).collect();
+    run_blocking(progress_service, move |progress| {
+        progress.mark(user_id, &comic_ids, read)?;
+        progress.counts(user_id, &comic_ids)
+    })
+        .await
+        .map(Json)
+        .map_err(progress_error_status)
+}
+
+/// Progress is keyed by the stable id, so legacy path lookups still land
+/// on the same record.
+async fn canonical_id(comic_service: &ComicService, id: &str) -> Result<String, Status> {
+    comic_service.get_comic(id)
+        .await
+        .map(|comic| comic.id)
+        .ok_or(Status::NotFound)
+}
+
+fn progress_error_status(error: ComicError) -> Status {
+    println!("Error syncing reading progress: {:?}", error);
+    Status::InternalServerError
+}
+
+#[options("/progress")]
+pub fn progress_list_options() -> Status {
+    Status::NoContent
+}
+
+#[options("/comics/<_id>/progress")]
+pub fn progress_options(_id: String) -> Status {
+    Status::NoContent
+}
+
+#[options("/folders/read")]
+pub fn folder_read_options() -> Status {
+    Status::NoContent
+}
+
+#[options("/series/<_name>/read")]
+pub fn series_read_options(_name: String) -> Status {
+    Status::NoContent
+}
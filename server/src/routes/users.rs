@@ -0,0 +1,153 @@
+use rocket::State;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use serde::Deserialize;
+
+use crate::models::error::ComicError;
+use crate::models::user::User;
+use crate::services::auth::{AdminGuard, AuthGuard};
+use crate::services::database;
+use crate::services::users::UserService;
+
+#[derive(Deserialize)]
+pub struct NewUser {
+    username: String,
+    password: String,
+    #[serde(default)]
+    is_admin: bool,
+}
+
+#[derive(Deserialize)]
+pub struct UserUpdate {
+    is_admin: Option<bool>,
+    disabled: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct PasswordChange {
+    password: String,
+    /// Required unless an admin is changing the password.
+    #[serde(default)]
+    current_password: Option<String>,
+}
+
+#[get("/users")]
+pub async fn list_users(
+    _admin: AdminGuard,
+    users: &State<UserService>
+) -> Result<Json<Vec<User>>, Status> {
+    run_blocking(users, |users| users.list()).await.map(Json)
+}
+
+#[post("/users", data = "<new_user>")]
+pub async fn create_user(
+    _admin: AdminGuard,
+    users: &State<UserService>,
+    new_user: Json<NewUser>
+) -> Result<(Status, Json<User>), Status> {
+    let new_user = new_user.into_inner();
+    let user = run_blocking(users, move |users| {
+        users.create(&new_user.username, &new_user.password, new_user.is_admin)
+    }).await?;
+
+    println!("Created user '{}'", user.username);
+    Ok((Status::Created, Json(user)))
+}
+
+#[get("/users/me")]
+pub async fn current_user(auth: AuthGuard) -> Result<Json<User>, Status> {
+    auth.user.map(Json).ok_or(Status::NotFound)
+}
+
+#[patch("/users/<id>", data = "<update>")]
+pub async fn update_user(
+    _admin: AdminGuard,
+    users: &State<UserService>,
+    id: i64,
+    update: Json<UserUpdate>
+) -> Result<Json<User>, Status> {
+    let UserUpdate { is_admin, disabled } = update.into_inner();
+    run_blocking(users, move |users| users.update(id, is_admin, disabled)).await.map(Json)
+}
+
+/// Admins may change anyone's password, other users only their own and
+/// only by confirming the current one, so a stolen session cannot lock
+/// the owner out.
+#[put("/users/<id>/password", data = "<change>")]
+pub async fn change_password(
+    auth: AuthGuard,
+    users: &State<UserService>,
+    id: i64,
+    change: Json<PasswordChange>
+) -> Result<Status, Status> {
+    let change = change.into_inner();
+    if let Some(user) = auth.user.filter(|user| !user.is_admin) {
+        if user.id != id {
+            return Err(Status::Forbidden);
+        }
+
+        let current_password = change.current_password.unwrap_or_default();
+        let verified = run_blocking(users, move |users| {
+            users.authenticate(&user.username, &current_password)
+        }).await?;
+        if verified.is_none() {
+            return Err(Status::Forbidden);
+        }
+    }
+
+    let password = change.password;
+    run_blocking(users, move |users| users.set_password(id, &password)).await?;
+    Ok(Status::NoContent)
+}
+
+#[delete("/users/<id>")]
+pub async fn delete_user(
+    _admin: AdminGuard,
+    users: &State<UserService>,
+    id: i64
+) -> Result<Status, Status> {
+    run_blocking(users, move |users| users.delete(id)).await?;
+    Ok(Status::NoContent)
+}
+
+/// Database calls and password hashing both block, so they run off the
+/// executor.
+async fn run_blocking<T, F>(users: &UserService, f: F) -> Result<T, Status>
+where
+    F: FnOnce(&UserService) -> Result<T, ComicError> + Send + 'static,
+    T: Send + 'static,
+{
+    database::run_blocking(users, f).await.map_err(user_error_status)
+}
+
+fn user_error_status(error: ComicError) -> Status {
+    match error {
+        ComicError::UserNotFound => Status::NotFound,
+        ComicError::UserExists | ComicError::LastAdmin => Status::Conflict,
+        ComicError::InvalidUser(_) => Status::UnprocessableEntity,
+        e => {
+            println!("Error managing users: {:?}", e);
+            Status::InternalServerError
+        }
+    }
+}
+
+#[options("/users")]
+pub fn users_options() -> Status {
+    Status::NoContent
+}
+
+#[options("/users/me")]
+pub fn current_user_options() -> Status {
+    Status::NoContent
+}
+
+#[options("/users/<_id>")]
+pub fn user_options(_id: i64) -> Status {
+    Status::NoContent
+}
+
+#[options("/users/<_id>/password")]
+pub fn password_options(_id: i64) -> Status {
+    Status::NoContent
+}
@@ -0,0 +1,65 @@
+use std::fs::File;
+use std::path::Path;
+use rocket::http::ContentType;
+use sevenz_rust::{Password, SevenZReader};
+
+use super::{read_entry_data, ArchiveBackend, ArchiveEntry, ArchiveReader};
+use crate::models::error::ComicError;
+
+pub struct SevenZBackend;
+
+impl ArchiveBackend for SevenZBackend {
+    fn format(&self) -> &'static str {
+        "cb7"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["cb7", "7z"]
+    }
+
+    fn content_type(&self) -> ContentType {
+        ContentType::new("application", "x-cb7")
+    }
+
+    fn matches_magic(&self, header: &[u8]) -> bool {
+        header.starts_with(b"7z\xbc\xaf\x27\x1c")
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn ArchiveReader>, ComicError> {
+        let archive = SevenZReader::open(path, Password::empty()).map_err(ComicError::archive)?;
+        Ok(Box::new(SevenZArchiveReader { archive }))
+    }
+}
+
+struct SevenZArchiveReader {
+    archive: SevenZReader<File>,
+}
+
+impl ArchiveReader for SevenZArchiveReader {
+    fn entries(&mut self) -> Result<Vec<ArchiveEntry>, ComicError> {
+        Ok(self.archive.archive().files.iter()
+            .map(|entry| ArchiveEntry {
+                name: entry.name().to_string(),
+                size: entry.size(),
+                is_dir: entry.is_directory(),
+            })
+            .collect())
+    }
+
+    fn read_entry(&mut self, name: &str) -> Result<Vec<u8>, ComicError> {
+        let mut found = None;
+
+        self.archive.for_each_entries(|entry, reader| {
+            if entry.name() != name {
+                // Entries must still be drained so the solid stream stays in sync
+                std::io::copy(reader, &mut std::io::sink())?;
+                return Ok(true);
+            }
+
+            found = Some(read_entry_data(reader, entry.size())?);
+            Ok(false)
+        }).map_err(ComicError::archive)?;
+
+        found.ok_or(ComicError::EntryNotFound)
+    }
+}
@@ -0,0 +1,186 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use rocket::http::ContentType;
+use unrar::Archive;
+
+use super::{entry_too_large, read_entry_data, ArchiveBackend, ArchiveEntry, ArchiveReader, MAX_ENTRY_SIZE};
+use crate::models::error::ComicError;
+
+pub struct RarBackend;
+
+impl ArchiveBackend for RarBackend {
+    fn format(&self) -> &'static str {
+        "cbr"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["cbr", "rar"]
+    }
+
+    fn content_type(&self) -> ContentType {
+        ContentType::new("application", "vnd.comicbook-rar")
+    }
+
+    fn matches_magic(&self, header: &[u8]) -> bool {
+        // RAR4 ends the marker with 0x00, RAR5 with 0x01 0x00
+        header.starts_with(b"Rar!\x1a\x07")
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn ArchiveReader>, ComicError> {
+        // unrar's is_archive only looks at the extension and rejects .cbr,
+        // so open the archive to check it really is one
+        Archive::new(path).open_for_listing()?;
+        Ok(Box::new(RarReader { path: path.to_path_buf() }))
+    }
+}
+
+/// unrar only works on paths and forward-only cursors, so every operation
+/// reopens the archive and walks its headers.
+struct RarReader {
+    path: PathBuf,
+}
+
+impl ArchiveReader for RarReader {
+    fn entries(&mut self) -> Result<Vec<ArchiveEntry>, ComicError> {
+        Archive::new(&self.path)
+            .open_for_listing()?
+            .map(|header| {
+                let header = header?;
+                Ok(ArchiveEntry {
+                    name: header.filename.to_string_lossy().into_owned(),
+                    size: header.unpacked_size,
+                    is_dir: header.is_directory(),
+                })
+            })
+            .collect()
+    }
+
+    fn read_entry(&mut self, name: &str) -> Result<Vec<u8>, ComicError> {
+        let mut archive = Archive::new(&self.path).open_for_processing()?;
+
+        while let Some(header) = archive.read_header()? {
+            if header.entry().filename.to_string_lossy() == name {
+                let declared_size = header.entry().unpacked_size;
+                if declared_size > MAX_ENTRY_SIZE {
+                    return Err(entry_too_large().into());
+                }
+
+                // unrar's in-memory read has no limit, so extract to disk and
+                // read back only as many bytes as we are willing to hold
+                let scratch = ScratchFile::new();
+                header.extract_to(&scratch.0)?;
+                let mut file = File::open(&scratch.0)?;
+                return Ok(read_entry_data(&mut file, declared_size)?);
+            }
+            archive = header.skip()?;
+        }
+
+        Err(ComicError::EntryNotFound)
+    }
+}
+
+/// Temporary extraction target, removed again when dropped.
+struct ScratchFile(PathBuf);
+
+impl ScratchFile {
+    fn new() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        ScratchFile(std::env::temp_dir().join(format!("comic-reader-rar-{}-{}", std::process::id(), n)))
+    }
+}
+
+impl Drop for ScratchFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Appends a RAR 1.5-4.x block, filling in its 16-bit header CRC.
+    fn push_block(archive: &mut Vec<u8>, block_type: u8, flags: u16, body: &[u8]) {
+        let mut header = vec![block_type];
+        header.extend_from_slice(&flags.to_le_bytes());
+        header.extend_from_slice(&(7 + body.len() as u16).to_le_bytes());
+        header.extend_from_slice(body);
+
+        let mut crc = flate2::Crc::new();
+        crc.update(&header);
+        archive.extend_from_slice(&(crc.sum() as u16).to_le_bytes());
+        archive.extend_from_slice(&header);
+    }
+
+    /// Builds a RAR4 archive holding a single stored (uncompressed) entry.
+    fn stored_rar(path: &Path, name: &str, data: &[u8]) {
+        let mut archive = b"Rar!\x1a\x07\x00".to_vec();
+        push_block(&mut archive, 0x73, 0, &[0; 6]);
+
+        let mut crc = flate2::Crc::new();
+        crc.update(data);
+        let mut file = Vec::new();
+        file.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        file.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        file.push(0); // host OS: MS-DOS
+        file.extend_from_slice(&crc.sum().to_le_bytes());
+        file.extend_from_slice(&0x0021_0000u32.to_le_bytes()); // 1980-01-01 DOS time
+        file.push(20); // needs unrar 2.0
+        file.push(0x30); // stored
+        file.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        file.extend_from_slice(&0x20u32.to_le_bytes()); // archive attribute
+        file.extend_from_slice(name.as_bytes());
+        push_block(&mut archive, 0x74, 0x8000, &file);
+        archive.extend_from_slice(data);
+
+        push_block(&mut archive, 0x7b, 0x4000, &[]);
+        fs::write(path, archive).unwrap();
+    }
+
+    struct TempRar(PathBuf);
+
+    impl TempRar {
+        fn new(name: &str, entry: &str, data: &[u8]) -> Self {
+            let path = std::env::temp_dir().join(format!("cbr-{}-{}.cbr", name, std::process::id()));
+            stored_rar(&path, entry, data);
+            TempRar(path)
+        }
+
+        fn reader(&self) -> Box<dyn ArchiveReader> {
+            RarBackend.open(&self.0).unwrap()
+        }
+    }
+
+    impl Drop for TempRar {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn reads_stored_entries() {
+        let rar = TempRar::new("read", "page1.png", b"not really a png");
+        let mut reader = rar.reader();
+
+        let entries = reader.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "page1.png");
+        assert_eq!(entries[0].size, 16);
+        assert_eq!(reader.read_entry("page1.png").unwrap(), b"not really a png");
+        assert!(matches!(reader.read_entry("page2.png"), Err(ComicError::EntryNotFound)));
+    }
+
+    #[test]
+    fn refuses_oversized_entries() {
+        let data = vec![0; MAX_ENTRY_SIZE as usize + 1];
+        let rar = TempRar::new("oversized", "huge.png", &data);
+        let mut reader = rar.reader();
+
+        match reader.read_entry("huge.png") {
+            Err(ComicError::IoError(error)) => assert_eq!(error.kind(), std::io::ErrorKind::InvalidData),
+            other => panic!("expected an oversized entry error, got {:?}", other.map(|data| data.len())),
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use flate2::read::GzDecoder;
+use rocket::http::ContentType;
+use xz2::read::XzDecoder;
+
+use super::{read_entry_data, ArchiveBackend, ArchiveEntry, ArchiveReader};
+use crate::models::error::ComicError;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+pub struct TarBackend;
+
+impl ArchiveBackend for TarBackend {
+    fn format(&self) -> &'static str {
+        "cbt"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["cbt", "tar"]
+    }
+
+    fn content_type(&self) -> ContentType {
+        ContentType::new("application", "x-cbt")
+    }
+
+    fn matches_magic(&self, header: &[u8]) -> bool {
+        // Only plain tar carries the ustar marker; compressed tarballs are
+        // picked by extension since gzip/xz magic alone says nothing about
+        // what is inside.
+        header.get(257..262) == Some(b"ustar".as_slice())
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn ArchiveReader>, ComicError> {
+        open_tar(path)?;
+        Ok(Box::new(TarReader { path: path.to_path_buf() }))
+    }
+}
+
+/// Tar streams cannot seek back, so every operation reopens the file.
+struct TarReader {
+    path: PathBuf,
+}
+
+impl ArchiveReader for TarReader {
+    fn entries(&mut self) -> Result<Vec<ArchiveEntry>, ComicError> {
+        let mut archive = open_tar(&self.path)?;
+        let mut entries = Vec::new();
+
+        for entry in archive.entries()? {
+            let entry = entry?;
+            entries.push(ArchiveEntry {
+                name: entry.path()?.to_string_lossy().into_owned(),
+                size: entry.size(),
+                is_dir: entry.header().entry_type().is_dir(),
+            });
+        }
+
+        Ok(entries)
+    }
+
+    fn read_entry(&mut self, name: &str) -> Result<Vec<u8>, ComicError> {
+        let mut archive = open_tar(&self.path)?;
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if entry.header().entry_type().is_file() && entry.path()?.to_string_lossy() == name {
+                let size = entry.size();
+                return Ok(read_entry_data(&mut entry, size)?);
+            }
+        }
+
+        Err(ComicError::EntryNotFound)
+    }
+}
+
+/// Opens a tar archive, transparently decompressing gzip and xz streams.
+fn open_tar(path: &Path) -> Result<tar::Archive<Box<dyn Read + Send>>, ComicError> {
+    let mut file = File::open(path)?;
+    let mut magic = [0u8; 6];
+    let read = file.read(&mut magic)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    let reader: Box<dyn Read + Send> = if magic[..read].starts_with(GZIP_MAGIC) {
+        Box::new(GzDecoder::new(file))
+    } else if magic[..read].starts_with(XZ_MAGIC) {
+        Box::new(XzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+
+    Ok(tar::Archive::new(reader))
+}
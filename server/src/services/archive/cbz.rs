@@ -0,0 +1,65 @@
+use std::fs::File;
+use std::path::Path;
+use rocket::http::ContentType;
+use zip::ZipArchive;
+
+use super::{read_entry_data, ArchiveBackend, ArchiveEntry, ArchiveReader};
+use crate::models::error::ComicError;
+
+pub struct ZipBackend;
+
+impl ArchiveBackend for ZipBackend {
+    fn format(&self) -> &'static str {
+        "cbz"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["cbz", "zip"]
+    }
+
+    fn content_type(&self) -> ContentType {
+        ContentType::ZIP
+    }
+
+    fn matches_magic(&self, header: &[u8]) -> bool {
+        header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06")
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn ArchiveReader>, ComicError> {
+        let archive = ZipArchive::new(File::open(path)?).map_err(ComicError::archive)?;
+        Ok(Box::new(ZipReader { archive }))
+    }
+}
+
+struct ZipReader {
+    archive: ZipArchive<File>,
+}
+
+impl ArchiveReader for ZipReader {
+    fn entries(&mut self) -> Result<Vec<ArchiveEntry>, ComicError> {
+        (0..self.archive.len())
+            .map(|i| {
+                let file = self.archive.by_index_raw(i).map_err(ComicError::archive)?;
+                Ok(ArchiveEntry {
+                    name: file.name().to_string(),
+                    size: file.size(),
+                    is_dir: file.is_dir(),
+                })
+            })
+            .collect()
+    }
+
+    fn read_entry(&mut self, name: &str) -> Result<Vec<u8>, ComicError> {
+        let mut file = self.archive.by_name(name).map_err(ComicError::archive)?;
+        let size = file.size();
+        Ok(read_entry_data(&mut file, size)?)
+    }
+
+    fn comment(&mut self) -> Result<Option<String>, ComicError> {
+        let comment = self.archive.comment();
+        if comment.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(String::from_utf8_lossy(comment).into_owned()))
+    }
+}
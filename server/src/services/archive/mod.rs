@@ -0,0 +1,167 @@
+mod cb7;
+mod cbr;
+mod cbt;
+mod cbz;
+
+use std::io::Read;
+use std::path::Path;
+use rocket::http::ContentType;
+
+use crate::models::error::ComicError;
+
+pub use cb7::SevenZBackend;
+pub use cbr::RarBackend;
+pub use cbt::TarBackend;
+pub use cbz::ZipBackend;
+
+/// A single file or directory stored inside a comic archive.
+#[derive(Debug, Clone)]
+pub struct ArchiveEntry {
+    pub name: String,
+    pub size: u64,
+    pub is_dir: bool,
+}
+
+/// Read access to an opened comic archive.
+pub trait ArchiveReader: Send {
+    /// Lists every entry in archive order.
+    fn entries(&mut self) -> Result<Vec<ArchiveEntry>, ComicError>;
+
+    /// Reads the full contents of the entry with the given name.
+    fn read_entry(&mut self, name: &str) -> Result<Vec<u8>, ComicError>;
+
+    /// Returns the archive-level comment, for formats that support one.
+    fn comment(&mut self) -> Result<Option<String>, ComicError> {
+        Ok(None)
+    }
+}
+
+/// A comic archive format that can be detected and opened.
+pub trait ArchiveBackend: Send + Sync {
+    /// Short format name reported on `Comic`, e.g. `cbz`.
+    fn format(&self) -> &'static str;
+
+    /// Lowercase file extensions handled by this backend.
+    fn extensions(&self) -> &'static [&'static str];
+
+    fn content_type(&self) -> ContentType;
+
+    /// Checks the first bytes of a file for this format's signature.
+    fn matches_magic(&self, header: &[u8]) -> bool;
+
+    fn open(&self, path: &Path) -> Result<Box<dyn ArchiveReader>, ComicError>;
+}
+
+/// Number of leading bytes read when sniffing an archive's format.
+const MAGIC_LEN: usize = 264;
+
+/// Largest entry read into memory. Sizes in archive headers are untrusted,
+/// so they never decide how much is allocated or read beyond this.
+const MAX_ENTRY_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Reads an entry's contents, refusing entries over `MAX_ENTRY_SIZE`
+/// whatever size their header declares.
+fn read_entry_data(reader: &mut dyn Read, declared_size: u64) -> std::io::Result<Vec<u8>> {
+    if declared_size > MAX_ENTRY_SIZE {
+        return Err(entry_too_large());
+    }
+
+    let mut data = Vec::with_capacity(declared_size as usize);
+    reader.take(MAX_ENTRY_SIZE + 1).read_to_end(&mut data)?;
+    if data.len() as u64 > MAX_ENTRY_SIZE {
+        return Err(entry_too_large());
+    }
+    Ok(data)
+}
+
+fn entry_too_large() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, "archive entry is too large")
+}
+
+pub struct ArchiveRegistry {
+    backends: Vec<Box<dyn ArchiveBackend>>,
+}
+
+impl ArchiveRegistry {
+    pub fn empty() -> Self {
+        ArchiveRegistry { backends: vec![] }
+    }
+
+    pub fn register(&mut self, backend: Box<dyn ArchiveBackend>) {
+        self.backends.push(backend);
+    }
+
+    /// Finds the backend registered for the path's extension.
+    pub fn backend_for_path(&self, path: &Path) -> Option<&dyn ArchiveBackend> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        self.backends.iter()
+            .find(|backend| backend.extensions().contains(&extension.as_str()))
+            .map(|backend| backend.as_ref())
+    }
+
+    pub fn backend_for_format(&self, format: &str) -> Option<&dyn ArchiveBackend> {
+        self.backends.iter()
+            .find(|backend| backend.format() == format)
+            .map(|backend| backend.as_ref())
+    }
+
+    /// Media type to serve a comic of this format with.
+    pub fn content_type(&self, format: &str) -> ContentType {
+        self.backend_for_format(format)
+            .map(|backend| backend.content_type())
+            .unwrap_or(ContentType::Binary)
+    }
+
+    /// Opens an archive, preferring the format its magic bytes identify
+    /// over the one its extension claims (a `.cbr` that is really a ZIP
+    /// is common in the wild).
+    pub fn open(&self, path: &Path) -> Result<Box<dyn ArchiveReader>, ComicError> {
+        let mut header = Vec::with_capacity(MAGIC_LEN);
+        std::fs::File::open(path)?
+            .take(MAGIC_LEN as u64)
+            .read_to_end(&mut header)?;
+
+        let backend = self.backends.iter()
+            .map(|backend| backend.as_ref())
+            .find(|backend| backend.matches_magic(&header))
+            .or_else(|| self.backend_for_path(path))
+            .ok_or(ComicError::UnsupportedFormat)?;
+
+        backend.open(path)
+    }
+}
+
+impl Default for ArchiveRegistry {
+    fn default() -> Self {
+        let mut registry = ArchiveRegistry::empty();
+        registry.register(Box::new(ZipBackend));
+        registry.register(Box::new(RarBackend));
+        registry.register(Box::new(SevenZBackend));
+        registry.register(Box::new(TarBackend));
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_entries_within_the_limit() {
+        let data = read_entry_data(&mut &b"page"[..], 4).unwrap();
+        assert_eq!(data, b"page");
+    }
+
+    #[test]
+    fn refuses_oversized_declared_sizes() {
+        let error = read_entry_data(&mut &b""[..], MAX_ENTRY_SIZE + 1).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn stops_reading_entries_that_lie_about_their_size() {
+        let mut endless = std::io::repeat(0);
+        let error = read_entry_data(&mut endless, 4).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
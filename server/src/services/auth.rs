@@ -1,12 +1,42 @@
+use std::io::{self, Write};
+use std::path::Path;
+use rocket::outcome::try_outcome;
 use rocket::request::{FromRequest, Outcome};
 use rocket::Request;
 use rocket::http::Status;
 use rocket::State;
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
 
 use crate::config::AppConfig;
+use crate::models::user::User;
+use crate::services::database::{run_blocking, unix_time};
+use crate::services::progress::ANONYMOUS_USER;
+use crate::services::users::UserService;
 
-pub struct AuthGuard;
+/// How long a signed URL token stays valid, in seconds.
+const URL_TOKEN_TTL: i64 = 24 * 60 * 60;
+
+/// Requires a bearer session token or HTTP Basic credentials once any
+/// account exists. An empty Basic username stands for the admin
+/// bootstrapped from `SERVER_PASSWORD`, which is what password-only
+/// clients send.
+pub struct AuthGuard {
+    /// The signed-in account; `None` while the server has no accounts.
+    pub user: Option<User>,
+    /// Set when the request authenticated with a session token.
+    pub session_id: Option<String>,
+}
+
+impl AuthGuard {
+    /// Key for per-user data such as reading progress.
+    pub fn user_id(&self) -> i64 {
+        self.user.as_ref().map_or(ANONYMOUS_USER, |user| user.id)
+    }
+}
 
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for AuthGuard {
@@ -14,35 +44,301 @@ impl<'r> FromRequest<'r> for AuthGuard {
 
     async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
         let config = request.guard::<&State<AppConfig>>().await.succeeded().unwrap();
+        let users = request.guard::<&State<UserService>>().await.succeeded().unwrap();
 
-        if config.server_password.is_none() {
-            return Outcome::Success(AuthGuard);
+        match run_blocking(users.inner(), |users| users.auth_required()).await {
+            Ok(false) => return Outcome::Success(AuthGuard { user: None, session_id: None }),
+            Ok(true) => (),
+            Err(e) => {
+                eprintln!("Error checking accounts: {}", e);
+                return Outcome::Error((Status::InternalServerError, ()));
+            }
         }
 
-        match request.headers().get_one("Authorization") {
-            None => Outcome::Error((Status::Unauthorized, ())),
-            Some(auth_header) if !auth_header.starts_with("Basic ") => {
-                Outcome::Error((Status::Unauthorized, ()))
-            }
-            Some(auth_header) => {
-                let credentials = &auth_header[6..];
-                match BASE64.decode(credentials) {
-                    Ok(decoded) => {
-                        match String::from_utf8(decoded) {
-                            Ok(credentials_str) => {
-                                let parts: Vec<&str> = credentials_str.split(':').collect();
-                                if parts.len() == 2 && parts[1] == config.server_password.as_ref().unwrap() {
-                                    Outcome::Success(AuthGuard)
-                                } else {
-                                    Outcome::Error((Status::Unauthorized, ()))
-                                }
-                            }
-                            Err(_) => Outcome::Error((Status::Unauthorized, ())),
-                        }
-                    }
-                    Err(_) => Outcome::Error((Status::Unauthorized, ())),
+        if let Some(token) = bearer_token(request) {
+            let token = token.to_string();
+            return match run_blocking(users.inner(), move |users| users.authenticate_token(&token)).await {
+                Ok(Some((user, session))) => Outcome::Success(AuthGuard {
+                    user: Some(user),
+                    session_id: Some(session.id),
+                }),
+                Ok(None) => Outcome::Error((Status::Unauthorized, ())),
+                Err(e) => {
+                    eprintln!("Error checking session: {}", e);
+                    Outcome::Error((Status::InternalServerError, ()))
                 }
+            };
+        }
+
+        let Some((username, password)) = basic_credentials(request) else {
+            return Outcome::Error((Status::Unauthorized, ()));
+        };
+        let username = if username.is_empty() {
+            config.admin_username.clone()
+        } else {
+            username
+        };
+
+        match run_blocking(users.inner(), move |users| users.authenticate(&username, &password)).await {
+            Ok(Some(user)) => Outcome::Success(AuthGuard { user: Some(user), session_id: None }),
+            Ok(None) => Outcome::Error((Status::Unauthorized, ())),
+            Err(e) => {
+                eprintln!("Error authenticating: {}", e);
+                Outcome::Error((Status::InternalServerError, ()))
+            }
+        }
+    }
+}
+
+/// Like `AuthGuard`, but the account must be an admin. While there are no
+/// accounts, requests from the server's own machine get through so the
+/// first admin can be created; everyone else needs `SERVER_PASSWORD` set.
+pub struct AdminGuard;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminGuard {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let auth = try_outcome!(request.guard::<AuthGuard>().await);
+        match auth.user {
+            Some(user) if user.is_admin => Outcome::Success(AdminGuard),
+            Some(_) => Outcome::Error((Status::Forbidden, ())),
+            // The socket address, as forwarding headers can be forged
+            None if request.remote().is_some_and(|remote| remote.ip().to_canonical().is_loopback()) => {
+                Outcome::Success(AdminGuard)
+            }
+            None => Outcome::Error((Status::Forbidden, ())),
+        }
+    }
+}
+
+/// Authenticates KOReader's kosync requests, which carry the username and
+/// the MD5 of the password in `x-auth-user` and `x-auth-key` headers.
+/// Like `AuthGuard`, it lets everyone through while no accounts exist.
+pub struct KosyncAuth {
+    pub user: Option<User>,
+}
+
+impl KosyncAuth {
+    pub fn user_id(&self) -> i64 {
+        self.user.as_ref().map_or(ANONYMOUS_USER, |user| user.id)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for KosyncAuth {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let users = request.guard::<&State<UserService>>().await.succeeded().unwrap();
+
+        match run_blocking(users.inner(), |users| users.auth_required()).await {
+            Ok(false) => return Outcome::Success(KosyncAuth { user: None }),
+            Ok(true) => (),
+            Err(e) => {
+                eprintln!("Error checking accounts: {}", e);
+                return Outcome::Error((Status::InternalServerError, ()));
+            }
+        }
+
+        let headers = request.headers();
+        let (Some(username), Some(key)) = (headers.get_one("x-auth-user"), headers.get_one("x-auth-key")) else {
+            return Outcome::Error((Status::Unauthorized, ()));
+        };
+        let (username, key) = (username.to_string(), key.to_string());
+
+        match run_blocking(users.inner(), move |users| users.authenticate_kosync(&username, &key)).await {
+            Ok(Some(user)) => Outcome::Success(KosyncAuth { user: Some(user) }),
+            Ok(None) => Outcome::Error((Status::Unauthorized, ())),
+            Err(e) => {
+                eprintln!("Error authenticating kosync client: {}", e);
+                Outcome::Error((Status::InternalServerError, ()))
+            }
+        }
+    }
+}
+
+fn bearer_token<'r>(request: &'r Request<'_>) -> Option<&'r str> {
+    request.headers().get_one("Authorization")?.strip_prefix("Bearer ")
+}
+
+/// Splits an `Authorization: Basic` header into username and password.
+fn basic_credentials(request: &Request<'_>) -> Option<(String, String)> {
+    let auth_header = request.headers().get_one("Authorization")?;
+    let credentials = auth_header.strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(BASE64.decode(credentials).ok()?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+    Some((username.to_string(), password.to_string()))
+}
+
+/// Grants access to one comic's download, cover and pages, either through
+/// the usual credentials or a signed `token` query parameter. A token is
+/// only good for one comic, so routes pass their id to `check`.
+pub struct ComicAccess<'r> {
+    /// Set when the request carried valid credentials of its own.
+    authenticated: bool,
+    token: Option<TokenCheck<'r>>,
+}
+
+/// A query token waiting for the route to name its comic.
+struct TokenCheck<'r> {
+    signer: &'r UrlSigner,
+    token: UrlToken,
+    /// Current generation of the token's user.
+    generation: i64,
+}
+
+impl ComicAccess<'_> {
+    pub fn check(&self, id: &str) -> Result<(), Status> {
+        if self.authenticated {
+            return Ok(());
+        }
+        match &self.token {
+            Some(check) if check.signer.verify(id, &check.token, check.generation) => Ok(()),
+            _ => Err(Status::Unauthorized),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ComicAccess<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let token = match request.query_value::<&str>("token") {
+            Some(Ok(token)) => UrlToken::parse(token),
+            _ => None,
+        };
+        let Some(token) = token else {
+            return request.guard::<AuthGuard>().await
+                .map(|_| ComicAccess { authenticated: true, token: None });
+        };
+
+        let signer = request.guard::<&State<UrlSigner>>().await.succeeded().unwrap();
+        let users = request.guard::<&State<UserService>>().await.succeeded().unwrap();
+
+        // Tokens die with their account and whenever it is signed out everywhere
+        let user_id = token.user_id;
+        let generation = match run_blocking(users.inner(), move |users| users.token_generation(user_id)).await {
+            Ok(generation) => generation,
+            Err(e) => {
+                eprintln!("Error checking URL token: {}", e);
+                return Outcome::Error((Status::InternalServerError, ()));
+            }
+        };
+        let authenticated = request.guard::<AuthGuard>().await.succeeded().is_some();
+
+        Outcome::Success(ComicAccess {
+            authenticated,
+            token: generation.map(|generation| TokenCheck { signer, token, generation }),
+        })
+    }
+}
+
+/// A URL token split into its parts: `<user>.<expiry>.<signature>`.
+pub struct UrlToken {
+    user_id: i64,
+    expires: i64,
+    signature: Vec<u8>,
+}
+
+impl UrlToken {
+    pub fn parse(token: &str) -> Option<Self> {
+        let mut parts = token.splitn(3, '.');
+        let user_id = parts.next()?.parse().ok()?;
+        let expires = parts.next()?.parse().ok()?;
+        let signature = BASE64_URL.decode(parts.next()?).ok()?;
+        Some(UrlToken { user_id, expires, signature })
+    }
+}
+
+/// Issues and checks expiring per-comic URL tokens, so `<img>` tags and
+/// plain links can reach protected resources without an `Authorization`
+/// header. Each token is bound to the user it was issued to and to that
+/// user's token generation.
+pub struct UrlSigner {
+    key: Vec<u8>,
+}
+
+impl UrlSigner {
+    /// Loads the signing key from `path`, creating a random one on first
+    /// run so tokens survive restarts.
+    pub fn load_or_create(path: &Path) -> io::Result<Self> {
+        if let Ok(key) = std::fs::read(path) {
+            if !key.is_empty() {
+                return Ok(UrlSigner { key });
             }
         }
+
+        let mut key = vec![0; 32];
+        rand::thread_rng().fill_bytes(&mut key);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        // Anyone who can read the key can mint tokens, so keep it private
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(path)?;
+        #[cfg(unix)]
+        file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+        file.write_all(&key)?;
+        Ok(UrlSigner { key })
+    }
+
+    pub fn sign(&self, id: &str, user_id: i64, generation: i64) -> String {
+        let expires = unix_time() + URL_TOKEN_TTL;
+        let signature = self.mac(id, user_id, generation, expires).finalize().into_bytes();
+        format!("{}.{}.{}", user_id, expires, BASE64_URL.encode(signature))
+    }
+
+    pub fn verify(&self, id: &str, token: &UrlToken, generation: i64) -> bool {
+        token.expires > unix_time()
+            && self.mac(id, token.user_id, generation, token.expires)
+                .verify_slice(&token.signature)
+                .is_ok()
+    }
+
+    fn mac(&self, id: &str, user_id: i64, generation: i64, expires: i64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key)
+            .expect("HMAC accepts keys of any length");
+        for field in [id, &user_id.to_string(), &generation.to_string()] {
+            mac.update(field.as_bytes());
+            mac.update(b"\n");
+        }
+        mac.update(expires.to_string().as_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn creates_a_private_signing_key_and_reuses_it() {
+        let dir = std::env::temp_dir().join(format!("url-signer-{}", std::process::id()));
+        let path = dir.join("keys/url_signing.key");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let signer = UrlSigner::load_or_create(&path).unwrap();
+        assert_eq!(signer.key.len(), 32);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let token = signer.sign("comic", 1, 0);
+        let reloaded = UrlSigner::load_or_create(&path).unwrap();
+        assert!(reloaded.verify("comic", &UrlToken::parse(&token).unwrap(), 0));
+        assert!(!reloaded.verify("other", &UrlToken::parse(&token).unwrap(), 0));
+        assert!(!reloaded.verify("comic", &UrlToken::parse(&token).unwrap(), 1));
+
+        let _ = std::fs::remove_dir_all(&dir);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,50 @@
+use std::collections::HashSet;
+
+use crate::models::comic::Comic;
+
+/// Comics per page of an OPDS acquisition feed, in either catalog version.
+pub const PAGE_SIZE: usize = 50;
+
+/// Link relation for downloading a comic.
+pub const REL_ACQUISITION: &str = "http://opds-spec.org/acquisition";
+
+/// Sorts comics by name in natural order, ignoring case.
+pub fn sort_by_name(comics: &mut [Comic]) {
+    comics.sort_by(|a, b| natord::compare_ignore_case(&a.name, &b.name));
+}
+
+/// Distinct series names in natural order. Names differing only in case
+/// count once, spelled as in the first comic that has them.
+pub fn series_names(comics: &[Comic]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut names: Vec<String> = comics.iter()
+        .filter_map(|comic| comic.series.as_ref())
+        .filter(|series| seen.insert(series.to_ascii_lowercase()))
+        .cloned()
+        .collect();
+    names.sort_by(|a, b| natord::compare_ignore_case(a, b));
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comic(series: Option<&str>) -> Comic {
+        let mut comic = Comic::new(String::from("issue.cbz"), vec![], "cbz");
+        comic.series = series.map(String::from);
+        comic
+    }
+
+    #[test]
+    fn lists_each_series_once_in_natural_order() {
+        let comics = [
+            comic(Some("Saga 10")),
+            comic(Some("saga 2")),
+            comic(None),
+            comic(Some("Saga 2")),
+            comic(Some("Batman")),
+        ];
+        assert_eq!(series_names(&comics), ["Batman", "saga 2", "Saga 10"]);
+    }
+}
@@ -1,45 +1,102 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::future::Future;
 use std::pin::Pin;
 use tokio::fs::{self, File};
-use tokio::io::AsyncReadExt;
-use tokio::sync::RwLock;
-use notify::{Watcher, RecursiveMode, Event};
-use zip::ZipArchive;
-use std::io::Cursor;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex, RwLock};
+use tokio::time;
+use notify::{Watcher, RecursiveMode, Event, EventKind};
+use sha2::{Digest, Sha256};
 
-use crate::models::comic::{Comic, CoverImage, Folder};
+use crate::models::comic::{Comic, CoverImage, Folder, Page};
 use crate::models::error::ComicError;
+use crate::models::metadata::ComicMetadata;
+use crate::services::archive::ArchiveRegistry;
+use crate::services::cover_cache::CoverCache;
+use crate::services::database::{unix_time, ComicRecord, Database, FolderRecord};
+use crate::services::{kosync, metadata, pages, thumbnails};
+use crate::services::thumbnails::ThumbnailSize;
+use crate::utils::paths::PathResolver;
 
+/// Length in hex digits of generated comic ids.
+const ID_LEN: usize = 16;
+
+/// Bytes read from each end of a file to fingerprint it.
+const FINGERPRINT_SPAN: u64 = 64 * 1024;
+
+/// Quiet period after the last filesystem event before a rescan runs.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(1500);
+
+/// Size and modification time of a comic's file, as last indexed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileStats {
+    pub size: u64,
+    /// Unix seconds.
+    pub modified: i64,
+}
+
+#[derive(Clone)]
 pub struct ComicService {
     comics_dir: PathBuf,
+    paths: PathResolver,
+    archives: Arc<ArchiveRegistry>,
+    database: Arc<Database>,
     comics_cache: Arc<RwLock<HashMap<String, Comic>>>,
-    covers_cache: Arc<RwLock<HashMap<String, CoverImage>>>,
+    cover_cache: Arc<CoverCache>,
+    /// Library records keyed by path relative to `comics_dir`
+    records: Arc<RwLock<HashMap<String, ComicRecord>>>,
     folder_structure: Arc<RwLock<Folder>>,
+    /// Held for the duration of a scan so overlapping rescans queue up
+    scan_lock: Arc<Mutex<()>>,
+    /// Bumped whenever the in-memory library changes
+    generation: Arc<AtomicU64>,
 }
 
 impl ComicService {
-    pub async fn new(comics_dir: PathBuf) -> Result<Self, ComicError> {
-        let comics_cache = Arc::new(RwLock::new(HashMap::new()));
-        let covers_cache = Arc::new(RwLock::new(HashMap::new()));
-        let folder_structure = Arc::new(RwLock::new(Folder {
-            name: "root".to_string(),
-            path: vec![],
-            comics: vec![],
-            subfolders: vec![],
-        }));
+    pub async fn new(
+        comics_dir: PathBuf,
+        cover_cache_dir: PathBuf,
+        database: Arc<Database>,
+    ) -> Result<Self, ComicError> {
+        let stored = database.load_comics()?;
 
         let service = ComicService {
+            paths: PathResolver::new([comics_dir.clone()])?,
             comics_dir,
-            comics_cache,
-            covers_cache,
-            folder_structure,
+            archives: Arc::new(ArchiveRegistry::default()),
+            database,
+            comics_cache: Arc::new(RwLock::new(HashMap::new())),
+            cover_cache: Arc::new(CoverCache::new(cover_cache_dir).await?),
+            records: Arc::new(RwLock::new(HashMap::new())),
+            folder_structure: Arc::new(RwLock::new(Folder::root())),
+            scan_lock: Arc::new(Mutex::new(())),
+            generation: Arc::new(AtomicU64::new(0)),
         };
 
-        // Initial scan
-        service.scan_directory().await?;
+        if stored.is_empty() {
+            // Nothing to serve yet, so wait for the first scan
+            service.scan_directory().await?;
+        } else {
+            let last_scan = service.database.scan_state("last_scan_completed")?;
+            println!("Loaded {} comics from the library database (last scan: {})",
+                     stored.len(), last_scan.as_deref().unwrap_or("never"));
+            service.install(stored.into_iter()
+                .map(|record| (record.relative_path.clone(), record))
+                .collect()).await;
+
+            // Pick up changes made while the server was down
+            let background = service.clone();
+            tokio::spawn(async move {
+                if let Err(e) = background.scan_directory().await {
+                    eprintln!("Error reconciling library: {}", e);
+                }
+                background.backfill_document_hashes().await;
+            });
+        }
 
         // Setup file watcher with recursive mode
         service.setup_watcher()?;
@@ -47,90 +104,413 @@ impl ComicService {
         Ok(service)
     }
 
+    /// Reconciles the library database with the comics directory. Archives
+    /// whose size and modification time are unchanged keep their stored
+    /// record; only new or modified files are opened.
     async fn scan_directory(&self) -> Result<(), ComicError> {
-        println!("\nStarting full directory scan");
-        let mut new_comics = HashMap::new();
-        let mut new_covers = HashMap::new();
-        let mut root_folder = Folder {
-            name: "root".to_string(),
-            path: vec![],
-            comics: vec![],
-            subfolders: vec![],
-        };
+        let _scan = self.scan_lock.lock().await;
+        println!("\nStarting library scan");
+        self.with_database(|database| database.set_scan_state("last_scan_started", &unix_time().to_string()))
+            .await?;
+
+        let mut files = Vec::new();
+        self.collect_comic_files(&self.comics_dir, &mut files).await?;
 
-        // Recursive directory scanning
-        self.scan_directory_recursive(&self.comics_dir, &mut new_comics, &mut new_covers, &mut root_folder).await?;
+        let known = self.records.read().await.clone();
+        let mut current = HashMap::new();
+        let mut changed = Vec::new();
+
+        for (path, format) in files {
+            let Some(relative_path) = self.relative_path(&path) else {
+                continue;
+            };
+            match self.refresh_comic(&path, format, known.get(&relative_path)).await {
+                Some(Refreshed::Unchanged(record)) => {
+                    current.insert(relative_path, record);
+                }
+                Some(Refreshed::Indexed(record)) => changed.push(record),
+                None => (),
+            }
+        }
+
+        let removed: Vec<String> = known.keys()
+            .filter(|relative_path| {
+                !current.contains_key(*relative_path)
+                    && !changed.iter().any(|record| &record.relative_path == *relative_path)
+            })
+            .cloned()
+            .collect();
+
+        let mut taken: HashSet<String> = current.values()
+            .chain(changed.iter())
+            .map(|record| record.comic.id.clone())
+            .collect();
+        let vanished = removed.iter().filter_map(|relative_path| known.get(relative_path)).collect();
+        assign_ids(&mut changed, vanished, &mut taken);
+        for record in &changed {
+            current.insert(record.relative_path.clone(), record.clone());
+        }
+
+        let root_folder = Folder::from_comics(current.values().map(|record| &record.comic));
+        let folders = folder_records(&root_folder);
+        let (indexed, removed_count) = (changed.len(), removed.len());
+        self.with_database(move |database| {
+            database.apply_scan(&changed, &removed, &folders)?;
+            database.set_scan_state("last_scan_completed", &unix_time().to_string())
+        }).await?;
 
         println!("\nScan complete:");
-        println!("Total comics found: {}", new_comics.len());
-        println!("Root comics: {}", root_folder.comics.len());
-        println!("Root subfolders: {}", root_folder.subfolders.len());
+        println!("Total comics found: {}", current.len());
+        println!("Indexed: {}, removed: {}", indexed, removed_count);
+
+        // Drop cached covers of comics that are gone or have changed
+        let live_keys: HashSet<String> = current.values()
+            .map(|record| record.cover_key.clone())
+            .collect();
+        match self.cover_cache.retain(&live_keys).await {
+            Ok(removed) => println!("Evicted {} stale cached covers", removed),
+            Err(e) => eprintln!("Error evicting cached covers: {}", e),
+        }
+
+        self.install(current).await;
+        Ok(())
+    }
+
+    /// Brings the library up to date for paths reported by the watcher,
+    /// opening only archives at or below them.
+    async fn rescan_paths(&self, paths: HashSet<PathBuf>) -> Result<(), ComicError> {
+        let _scan = self.scan_lock.lock().await;
+
+        let mut files = HashMap::new();
+        let mut prefixes = Vec::new();
+        for path in &paths {
+            let Some(relative_path) = self.relative_path(path) else {
+                continue;
+            };
+
+            let mut found = Vec::new();
+            if path.is_dir() {
+                if let Err(e) = self.collect_comic_files(path, &mut found).await {
+                    eprintln!("Error scanning {}: {}", path.display(), e);
+                }
+            } else if path.is_file() {
+                if let Some(backend) = self.archives.backend_for_path(path) {
+                    found.push((path.clone(), backend.format()));
+                }
+            }
+
+            for (path, format) in found {
+                if let Some(relative_path) = self.relative_path(&path) {
+                    files.insert(relative_path, (path, format));
+                }
+            }
+            prefixes.push(relative_path);
+        }
+
+        // Stored comics at or below a changed path may have been removed
+        let known: HashMap<String, ComicRecord> = self.records.read().await
+            .iter()
+            .filter(|(relative_path, _)| {
+                files.contains_key(*relative_path) || prefixes.iter().any(|prefix| {
+                    relative_path.strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+                })
+            })
+            .map(|(relative_path, record)| (relative_path.clone(), record.clone()))
+            .collect();
 
-        // Update caches
+        let mut changed = Vec::new();
+        let mut removed = Vec::new();
+        for (relative_path, (path, format)) in &files {
+            match self.refresh_comic(path, format, known.get(relative_path)).await {
+                Some(Refreshed::Indexed(record)) => changed.push(record),
+                Some(Refreshed::Unchanged(_)) => (),
+                None if known.contains_key(relative_path) => removed.push(relative_path.clone()),
+                None => (),
+            }
+        }
+        removed.extend(known.keys().filter(|relative_path| !files.contains_key(*relative_path)).cloned());
+
+        if changed.is_empty() && removed.is_empty() {
+            return Ok(());
+        }
+
+        let mut taken: HashSet<String> = self.records.read().await
+            .values()
+            .filter(|record| !removed.contains(&record.relative_path))
+            .map(|record| record.comic.id.clone())
+            .chain(changed.iter().map(|record| record.comic.id.clone()))
+            .collect();
+        let vanished = removed.iter().filter_map(|relative_path| known.get(relative_path)).collect();
+        assign_ids(&mut changed, vanished, &mut taken);
+
+        let (folders, live_keys) = {
+            let mut records = self.records.write().await;
+            let mut comics_cache = self.comics_cache.write().await;
+            let mut folder_structure = self.folder_structure.write().await;
+
+            for relative_path in &removed {
+                if let Some(old) = records.remove(relative_path) {
+                    comics_cache.remove(&old.comic.id);
+                    folder_structure.remove_comic(&old.comic);
+                }
+            }
+            for record in &changed {
+                if let Some(old) = records.insert(record.relative_path.clone(), record.clone()) {
+                    comics_cache.remove(&old.comic.id);
+                    folder_structure.remove_comic(&old.comic);
+                }
+                comics_cache.insert(record.comic.id.clone(), record.comic.clone());
+                folder_structure.add_comic(&record.comic);
+            }
+
+            self.generation.fetch_add(1, Ordering::Release);
+
+            let live_keys: HashSet<String> = records.values()
+                .map(|record| record.cover_key.clone())
+                .collect();
+            (folder_records(&folder_structure), live_keys)
+        };
+
+        let (indexed, removed_count) = (changed.len(), removed.len());
+        self.with_database(move |database| database.apply_scan(&changed, &removed, &folders)).await?;
+        println!("Rescanned {} changed paths: indexed {}, removed {}",
+                 paths.len(), indexed, removed_count);
+
+        if let Err(e) = self.cover_cache.retain(&live_keys).await {
+            eprintln!("Error evicting cached covers: {}", e);
+        }
+        Ok(())
+    }
+
+    /// Returns the stored record when the file is unchanged and its cover is
+    /// still cached, otherwise re-reads the archive. `None` means the file
+    /// could not be read.
+    async fn refresh_comic(
+        &self,
+        path: &Path,
+        format: &str,
+        known: Option<&ComicRecord>,
+    ) -> Option<Refreshed> {
+        let comic = Comic::from_path(&self.comics_dir, path, format)?;
+        let file_metadata = match fs::metadata(path).await {
+            Ok(file_metadata) => file_metadata,
+            Err(e) => {
+                eprintln!("Skipping {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        let modified = modified_nanos(&file_metadata);
+
+        if let Some(record) = known {
+            if record.is_current(file_metadata.len(), modified)
+                && self.cover_cache.contains(&record.cover_key).await
+            {
+                return Some(Refreshed::Unchanged(record.clone()));
+            }
+        }
+
+        let indexed = match self.index_comic(path, &file_metadata).await {
+            Ok(indexed) => indexed,
+            Err(e) => {
+                eprintln!("Skipping {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        let (fingerprint, document_hash) = {
+            let file_path = path.to_path_buf();
+            let hashes = run_blocking(move || {
+                Ok((fingerprint(&file_path)?, kosync::document_hash(&file_path)?))
+            });
+            match hashes.await {
+                Ok(hashes) => hashes,
+                Err(e) => {
+                    eprintln!("Skipping {}: {}", path.display(), e);
+                    return None;
+                }
+            }
+        };
+
+        // A comic rewritten in place keeps its id; new files and rows from
+        // before fingerprints existed get one from `assign_ids`
+        let (cover_key, metadata, page_count) = indexed;
+        let mut comic = comic.with_metadata(metadata);
+        comic.page_count = page_count;
+        if let Some(record) = known.filter(|record| !record.fingerprint.is_empty()) {
+            comic.set_id(record.comic.id.clone());
+        }
+
+        Some(Refreshed::Indexed(ComicRecord {
+            relative_path: comic.relative_path(),
+            comic,
+            file_size: file_metadata.len(),
+            modified,
+            cover_key,
+            fingerprint,
+            document_hash,
+        }))
+    }
+
+    /// Hashes comics indexed before KOReader sync, which only needs a few
+    /// small samples of each file rather than a full re-read.
+    async fn backfill_document_hashes(&self) {
+        let _scan = self.scan_lock.lock().await;
+        let missing: Vec<String> = self.records.read().await
+            .values()
+            .filter(|record| record.document_hash.is_empty())
+            .map(|record| record.relative_path.clone())
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+
+        let mut hashed = 0;
+        for relative_path in missing {
+            let path = match self.paths.resolve(&relative_path) {
+                Ok(path) => path,
+                Err(e) => {
+                    eprintln!("Error hashing {}: {}", relative_path, e);
+                    continue;
+                }
+            };
+            let database = self.database.clone();
+            let key = relative_path.clone();
+            let stored = run_blocking(move || {
+                let hash = kosync::document_hash(&path)?;
+                database.set_document_hash(&key, &hash)?;
+                Ok(hash)
+            }).await;
+
+            match stored {
+                Ok(hash) => {
+                    if let Some(record) = self.records.write().await.get_mut(&relative_path) {
+                        record.document_hash = hash;
+                    }
+                    hashed += 1;
+                }
+                Err(e) => eprintln!("Error hashing {}: {}", relative_path, e),
+            }
+        }
+        println!("Computed KOReader document hashes for {} comics", hashed);
+    }
+
+    /// Runs a database call off the async executor.
+    async fn with_database<T, F>(&self, f: F) -> Result<T, ComicError>
+    where
+        F: FnOnce(&Database) -> Result<T, ComicError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let database = self.database.clone();
+        run_blocking(move || f(&database)).await
+    }
+
+    /// Path relative to `comics_dir`, `/`-joined as stored in the database.
+    fn relative_path(&self, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(&self.comics_dir).ok()?;
+        let parts: Vec<String> = relative.components()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        Some(parts.join("/"))
+    }
+
+    /// Replaces the in-memory library with the given records.
+    async fn install(&self, records: HashMap<String, ComicRecord>) {
+        let comics: HashMap<String, Comic> = records.values()
+            .map(|record| (record.comic.id.clone(), record.comic.clone()))
+            .collect();
+        let root_folder = Folder::from_comics(records.values().map(|record| &record.comic));
+
+        let mut cached_records = self.records.write().await;
         let mut comics_cache = self.comics_cache.write().await;
-        let mut covers_cache = self.covers_cache.write().await;
         let mut folder_structure = self.folder_structure.write().await;
 
-        *comics_cache = new_comics;
-        *covers_cache = new_covers;
+        *cached_records = records;
+        *comics_cache = comics;
         *folder_structure = root_folder;
+        self.generation.fetch_add(1, Ordering::Release);
+    }
 
-        Ok(())
+    /// Stores the page count of a comic indexed before counts were kept.
+    async fn backfill_page_count(&self, comic: &Comic, page_count: usize) {
+        let relative_path = comic.relative_path();
+        let key = relative_path.clone();
+        let stored = self.with_database(move |database| database.set_page_count(&key, page_count)).await;
+        if let Err(e) = stored {
+            eprintln!("Error storing page count of {}: {}", relative_path, e);
+            return;
+        }
+
+        let mut records = self.records.write().await;
+        let mut comics_cache = self.comics_cache.write().await;
+        let mut folder_structure = self.folder_structure.write().await;
+
+        let Some(record) = records.get_mut(&relative_path).filter(|record| record.comic.id == comic.id) else {
+            return;
+        };
+        folder_structure.remove_comic(&record.comic);
+        record.comic.page_count = page_count;
+        folder_structure.add_comic(&record.comic);
+        comics_cache.insert(record.comic.id.clone(), record.comic.clone());
+        self.generation.fetch_add(1, Ordering::Release);
     }
 
-    fn scan_directory_recursive<'a>(
+    /// Finds every archive at or below `dir`. Only an unreadable `dir`
+    /// itself is an error; entries and folders below it that cannot be
+    /// read are logged and skipped.
+    async fn collect_comic_files(
+        &self,
+        dir: &Path,
+        files: &mut Vec<(PathBuf, &'static str)>,
+    ) -> Result<(), ComicError> {
+        self.walk_comic_files(dir, files, &mut HashSet::new()).await
+    }
+
+    /// Visits each directory once, by canonical path, so links back to an
+    /// ancestor cannot recurse forever.
+    fn walk_comic_files<'a>(
         &'a self,
         dir: &'a Path,
-        comics: &'a mut HashMap<String, Comic>,
-        covers: &'a mut HashMap<String, CoverImage>,
-        current_folder: &'a mut Folder,
+        files: &'a mut Vec<(PathBuf, &'static str)>,
+        visited: &'a mut HashSet<PathBuf>,
     ) -> Pin<Box<dyn Future<Output = Result<(), ComicError>> + Send + 'a>> {
         Box::pin(async move {
-
+            if !visited.insert(fs::canonicalize(dir).await?) {
+                return Ok(());
+            }
             let mut entries = fs::read_dir(dir).await?;
 
-            while let Some(entry) = entries.next_entry().await? {
+            loop {
+                let entry = match entries.next_entry().await {
+                    Ok(Some(entry)) => entry,
+                    Ok(None) => break,
+                    Err(e) => {
+                        eprintln!("Error reading {}: {}", dir.display(), e);
+                        break;
+                    }
+                };
                 let path = entry.path();
 
-                if path.is_dir() {
-                    let folder_name = path.file_name()
-                        .ok_or_else(|| ComicError::InvalidPath)?
-                        .to_string_lossy()
-                        .into_owned();
-
-                    let mut subfolder = Folder {
-                        name: folder_name.clone(),
-                        path: current_folder.path.iter()
-                            .cloned()
-                            .chain(std::iter::once(current_folder.name.clone()))
-                            .filter(|name| name != "root")
-                            .collect(),
-                        comics: vec![],
-                        subfolders: vec![],
-                    };
-
-                    self.scan_directory_recursive(&path, comics, covers, &mut subfolder).await?;
-
-                    if !subfolder.comics.is_empty() || !subfolder.subfolders.is_empty() {
-                        current_folder.subfolders.push(subfolder);
+                let file_type = match entry.file_type().await {
+                    Ok(file_type) => file_type,
+                    Err(e) => {
+                        eprintln!("Skipping {}: {}", path.display(), e);
+                        continue;
                     }
-                } else if let Some(extension) = path.extension() {
-                    if extension.to_string_lossy().to_lowercase() == "cbz" {
-                        if let Some(comic) = Comic::from_path(&self.comics_dir, &path) {
-                            if let Ok(cover) = self.extract_cover(&path).await {
-                                covers.insert(comic.id.clone(), cover);
-                                comics.insert(comic.id.clone(), comic.clone());
-                                current_folder.comics.push(comic);
-                            }
-                        }
+                };
+                if file_type.is_symlink() && self.paths.check(&path).is_err() {
+                    eprintln!("Skipping {}: links outside the library", path.display());
+                    continue;
+                }
+
+                if path.is_dir() {
+                    if let Err(e) = self.walk_comic_files(&path, files, visited).await {
+                        eprintln!("Skipping {}: {}", path.display(), e);
                     }
+                } else if let Some(backend) = self.archives.backend_for_path(&path) {
+                    files.push((path, backend.format()));
                 }
             }
 
-            println!("Finished scanning directory: {}", dir.display());
-            println!("Current folder {} now has {} comics and {} subfolders",
-                     current_folder.name, current_folder.comics.len(), current_folder.subfolders.len());
-
             Ok(())
         })
     }
@@ -143,113 +523,125 @@ impl ComicService {
             .collect()
     }
 
+    /// Every comic in a folder and the folders below it.
+    pub async fn comics_in_folder(&self, folder_path: &[String]) -> Vec<Comic> {
+        let comics_cache = self.comics_cache.read().await;
+        comics_cache.values()
+            .filter(|comic| comic.folder_path.starts_with(folder_path))
+            .cloned()
+            .collect()
+    }
+
+    pub async fn comics_in_series(&self, series: &str) -> Vec<Comic> {
+        let comics_cache = self.comics_cache.read().await;
+        comics_cache.values()
+            .filter(|comic| comic.series.as_deref().is_some_and(|s| s.eq_ignore_ascii_case(series)))
+            .cloned()
+            .collect()
+    }
+
+    /// Changes whenever the listed comics do, so views built from them can
+    /// be cached until then.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    pub fn archives(&self) -> &ArchiveRegistry {
+        &self.archives
+    }
+
     pub async fn get_folder_structure(&self) -> Folder {
         self.folder_structure.read().await.clone()
     }
 
-    async fn process_comic_file(&self, path: &Path) -> Result<Comic, ComicError> {
-        let file_name = path.file_name()
-            .ok_or_else(|| ComicError::InvalidPath)?
-            .to_string_lossy()
-            .into_owned();
-        let name = path.file_stem()
-            .ok_or_else(|| ComicError::InvalidPath)?
-            .to_string_lossy()
-            .into_owned();
-
-        // Calculate relative path from base_dir
+    /// Reads a comic's metadata and page count and makes sure its cover is
+    /// in the on-disk cache, returning the cover's cache key.
+    async fn index_comic(
+        &self,
+        path: &Path,
+        file_metadata: &std::fs::Metadata,
+    ) -> Result<(String, Option<ComicMetadata>, usize), ComicError> {
         let relative_path = path.strip_prefix(&self.comics_dir)
             .map_err(|_| ComicError::InvalidPath)?;
-        let parent_path = relative_path.parent();
-
-        // Create folder path
-        let folder_path: Vec<String> = if let Some(parent) = parent_path {
-            parent.components()
-                .map(|c| c.as_os_str().to_string_lossy().into_owned())
-                .collect()
-        } else {
-            vec![]
-        };
+        let cover_key = CoverCache::key_for(relative_path, file_metadata);
 
-        // Get series name (immediate parent folder)
-        let series = folder_path.last().cloned();
+        let cached = self.cover_cache.contains(&cover_key).await;
+        let (cover, metadata, page_count) = self.inspect_archive(path, !cached).await?;
 
-        // Create the encoded path
-        let encoded_path = format!("/comics/{}", urlencoding::encode(&file_name));
+        if let Some(cover) = cover {
+            self.cover_cache.put(&cover_key, &cover).await?;
+        }
 
-        Ok(Comic {
-            id: file_name.clone(),
-            name,
-            file_name,
-            path: encoded_path,
-            folder_path,
-            series,
-        })
+        Ok((cover_key, metadata, page_count))
     }
 
-    async fn extract_cover(&self, path: &Path) -> Result<CoverImage, ComicError> {
-        let mut file = File::open(path).await?;
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer).await?;
+    /// Opens an archive once to pull out its embedded metadata, page count
+    /// and, when asked for, its cover.
+    async fn inspect_archive(
+        &self,
+        path: &Path,
+        extract_cover: bool,
+    ) -> Result<(Option<CoverImage>, Option<ComicMetadata>, usize), ComicError> {
+        let archives = self.archives.clone();
+        let path = path.to_path_buf();
 
-        let mut archive = ZipArchive::new(Cursor::new(buffer))?;
-
-        for i in 0..archive.len() {
-            let mut file = archive.by_index(i)?;
-            let name = file.name().to_lowercase();
-
-            if name.ends_with(".jpg") || name.ends_with(".jpeg") ||
-                name.ends_with(".png") || name.ends_with(".gif") {
-                let mut data = Vec::new();
-                std::io::copy(&mut file, &mut data)?;
-                return Ok(CoverImage { data });
+        run_blocking(move || {
+            let mut archive = archives.open(&path)?;
+            let entries = archive.entries()?;
+            let metadata = metadata::read_metadata(archive.as_mut(), &entries);
+            let page_metadata = metadata.as_ref().map(|m| m.pages.as_slice()).unwrap_or_default();
+            let pages = pages::order_pages(&entries, page_metadata);
+            if !extract_cover {
+                return Ok((None, metadata, pages.len()));
             }
-        }
 
-        Err(ComicError::NoCoverFound)
+            let cover = pages::cover_page(&pages).ok_or(ComicError::NoCoverFound)?;
+            let cover = CoverImage {
+                data: archive.read_entry(&cover.name)?,
+                mime: cover.mime.clone(),
+            };
+            Ok((Some(cover), metadata, pages.len()))
+        }).await
     }
 
+    /// Watches the comics directory and rescans only the paths that
+    /// changed, once events have been quiet for `WATCH_DEBOUNCE`.
     fn setup_watcher(&self) -> Result<(), ComicError> {
-        let comics_dir = self.comics_dir.clone();
-        let comics_cache = self.comics_cache.clone();
-        let covers_cache = self.covers_cache.clone();
-        let folder_structure = self.folder_structure.clone();
+        let (events, mut receiver) = mpsc::unbounded_channel();
 
         let mut watcher = notify::recommended_watcher(move |res: Result<Event, notify::Error>| {
-            if let Ok(event) = res {
-                let comics_dir = comics_dir.clone();
-                let comics_cache = comics_cache.clone();
-                let covers_cache = covers_cache.clone();
-                let folder_structure = folder_structure.clone();
-
-                // Create a runtime for the async operations
-                let rt = tokio::runtime::Runtime::new().unwrap();
-                rt.spawn(async move {
-                    match event.kind {
-                        notify::EventKind::Create(_) |
-                        notify::EventKind::Modify(_) |
-                        notify::EventKind::Remove(_) => {
-                            let service = ComicService {
-                                comics_dir,
-                                comics_cache,
-                                covers_cache,
-                                folder_structure,
-                            };
-                            if let Err(e) = service.scan_directory().await {
-                                eprintln!("Error rescanning directory: {}", e);
-                            }
-                        },
-                        _ => (),
-                    }
-                });
+            match res {
+                Ok(event) => {
+                    // Only fails once the receiving task is gone
+                    let _ = events.send(event);
+                }
+                Err(e) => eprintln!("Watch error: {}", e),
             }
         })?;
 
         watcher.watch(&self.comics_dir, RecursiveMode::Recursive)?;
 
-        // Keep watcher alive by storing it in a thread-local or global state
-        std::thread::spawn(move || {
-            std::mem::forget(watcher);
+        let service = self.clone();
+        tokio::spawn(async move {
+            // Dropping the watcher stops it, so the task owns it
+            let _watcher = watcher;
+
+            while let Some(event) = receiver.recv().await {
+                let mut paths = HashSet::new();
+                collect_event_paths(event, &mut paths);
+
+                // Coalesce bursts such as a folder being copied in
+                while let Ok(Some(event)) = time::timeout(WATCH_DEBOUNCE, receiver.recv()).await {
+                    collect_event_paths(event, &mut paths);
+                }
+
+                if paths.is_empty() {
+                    continue;
+                }
+                if let Err(e) = service.rescan_paths(paths).await {
+                    eprintln!("Error rescanning changed paths: {}", e);
+                }
+            }
         });
 
         Ok(())
@@ -263,85 +655,392 @@ impl ComicService {
             .collect()
     }
 
-    pub async fn get_comic(&self, id: &str) -> Option<Comic> {        
-        let actual_id = if id.contains('/') {
-            let filename = id.split('/').last()?;
-            filename
-        } else {
-            id
-        };
-    
-        let cache = self.comics_cache.read().await;
-        
-        let result = cache.get(actual_id)
-            .or_else(|| {
-                urlencoding::decode(actual_id)
-                    .ok()
-                    .and_then(|decoded| cache.get(decoded.as_ref()))
-            })
-            .cloned();
-            
-        result
+    /// Looks a comic up by id. Older clients addressed comics by file name
+    /// or by `folder/file` path; those still resolve when they name exactly
+    /// one comic.
+    pub async fn get_comic(&self, id: &str) -> Option<Comic> {
+        if let Some(comic) = self.comics_cache.read().await.get(id) {
+            return Some(comic.clone());
+        }
+
+        // Rocket has already percent-decoded the id, and decoding again
+        // would mangle names containing `%`
+        let records = self.records.read().await;
+        if let Some(record) = records.get(id) {
+            return Some(record.comic.clone());
+        }
+
+        let mut matches = records.values()
+            .filter(|record| record.comic.file_name == id);
+        match (matches.next(), matches.next()) {
+            (Some(record), None) => Some(record.comic.clone()),
+            _ => None,
+        }
+    }
+
+    /// File stats of every indexed comic, keyed by comic id.
+    pub async fn file_stats(&self) -> HashMap<String, FileStats> {
+        self.records.read()
+            .await
+            .values()
+            .map(|record| (record.comic.id.clone(), FileStats {
+                size: record.file_size,
+                modified: record.modified / 1_000_000_000,
+            }))
+            .collect()
+    }
+
+    /// Finds the comic a KOReader document hash refers to, whether KOReader
+    /// hashed the file's contents or its name.
+    pub async fn find_by_document(&self, document: &str) -> Option<Comic> {
+        let records = self.records.read().await;
+        records.values()
+            .find(|record| record.document_hash == document)
+            .or_else(|| records.values()
+                .find(|record| kosync::file_name_hash(&record.comic.file_name) == document))
+            .map(|record| record.comic.clone())
     }
 
     pub async fn get_cover(&self, id: &str) -> Option<CoverImage> {
-        // Try both encoded and decoded versions of the ID
-        let cache = self.covers_cache.read().await;
-        cache.get(id)
-            .or_else(|| {
-                urlencoding::decode(id)
-                    .ok()
-                    .and_then(|decoded| cache.get(decoded.as_ref()))
-            })
-            .cloned()
+        let key = self.cover_key(id).await?;
+        self.cover_cache.get(&key).await
     }
 
-    pub async fn get_comic_data(&self, id: &str) -> Result<Vec<u8>, ComicError> {
-        // Extract the folder path and filename
-        let (folder_path, filename) = if id.contains('/') {
-            let parts: Vec<&str> = id.rsplitn(2, '/').collect();
-            if parts.len() == 2 {
-                (Some(parts[1]), parts[0])
-            } else {
-                (None, id)
-            }
-        } else {
-            (None, id)
-        };
-    
-        // Decode the filename to get the original filename
-        let decoded_filename = urlencoding::decode(filename)
-            .map_err(|_| ComicError::InvalidPath)?;
-        
-        let comic = self.get_comic(&decoded_filename).await
-            .ok_or(ComicError::ComicNotFound)?;
-        
-        // Construct the full path including any folders
-        let mut file_path = self.comics_dir.clone();
-        
-        // Add folder path if it exists either from the URL or from the comic metadata
-        if let Some(folder) = folder_path {
-            file_path.push(folder);
-        } else if !comic.folder_path.is_empty() {
-            for folder in &comic.folder_path {
-                file_path.push(folder);
-            }
+    async fn cover_key(&self, id: &str) -> Option<String> {
+        let comic = self.get_comic(id).await?;
+        self.records.read().await
+            .get(&comic.relative_path())
+            .map(|record| record.cover_key.clone())
+    }
+
+    /// Lists the readable pages of a comic in reading order.
+    pub async fn get_pages(&self, id: &str) -> Result<Vec<Page>, ComicError> {
+        let comic = self.get_comic(id).await.ok_or(ComicError::ComicNotFound)?;
+        let archives = self.archives.clone();
+        let path = self.comic_file_path(&comic)?;
+
+        let page_metadata = comic.page_metadata().to_vec();
+        let pages = run_blocking(move || {
+            let mut archive = archives.open(&path)?;
+            Ok(pages::order_pages(&archive.entries()?, &page_metadata))
+        }).await?;
+
+        if comic.page_count == 0 && !pages.is_empty() {
+            self.backfill_page_count(&comic, pages.len()).await;
+        }
+        Ok(pages)
+    }
+
+    /// Extracts a single page image from a comic's archive.
+    pub async fn get_page(&self, id: &str, index: usize) -> Result<(Page, Vec<u8>), ComicError> {
+        let comic = self.get_comic(id).await.ok_or(ComicError::ComicNotFound)?;
+        let archives = self.archives.clone();
+        let path = self.comic_file_path(&comic)?;
+
+        run_blocking(move || {
+            let mut archive = archives.open(&path)?;
+            let page = pages::order_pages(&archive.entries()?, comic.page_metadata())
+                .into_iter()
+                .nth(index)
+                .ok_or(ComicError::PageNotFound)?;
+            let data = archive.read_entry(&page.name)?;
+            Ok((page, data))
+        }).await
+    }
+
+    /// Extracts a page and scales it down to fit `size`, as JPEG.
+    pub async fn get_scaled_page(
+        &self,
+        id: &str,
+        index: usize,
+        size: ThumbnailSize,
+    ) -> Result<CoverImage, ComicError> {
+        let (_, data) = self.get_page(id, index).await?;
+        run_blocking(move || thumbnails::render(&data, size)).await
+    }
+
+    /// Locates a comic's archive on disk, refusing anything that resolves
+    /// outside the library.
+    fn comic_file_path(&self, comic: &Comic) -> Result<PathBuf, ComicError> {
+        self.paths.resolve(&comic.relative_path())
+    }
+
+    /// Returns a downscaled cover, rendering and caching it on first use.
+    pub async fn get_thumbnail(
+        &self,
+        id: &str,
+        size: ThumbnailSize,
+    ) -> Result<CoverImage, ComicError> {
+        let key = self.cover_key(id).await.ok_or(ComicError::ComicNotFound)?;
+
+        if let Some(thumbnail) = self.cover_cache.get_thumbnail(&key, size).await {
+            return Ok(thumbnail);
+        }
+
+        let cover = self.cover_cache.get(&key).await.ok_or(ComicError::NoCoverFound)?;
+        let thumbnail = run_blocking(move || thumbnails::render(&cover.data, size)).await?;
+
+        if let Err(e) = self.cover_cache.put_thumbnail(&key, size, &thumbnail).await {
+            eprintln!("Failed to cache thumbnail: {}", e);
         }
-        
-        // Add the actual file name
-        file_path.push(&comic.file_name);
-    
-        println!("Attempting to read comic from: {}", file_path.display());
-    
-        let mut file = File::open(&file_path).await
+        Ok(thumbnail)
+    }
+
+    /// Opens a comic's archive file for streaming to the client.
+    pub async fn get_comic_file(&self, id: &str) -> Result<File, ComicError> {
+        let comic = self.get_comic(id).await
+            .ok_or(ComicError::ComicNotFound)?;
+        let file_path = self.comic_file_path(&comic)?;
+
+        println!("Attempting to open comic at: {}", file_path.display());
+
+        File::open(&file_path).await
             .map_err(|e| {
                 println!("Failed to open file: {}", e);
                 ComicError::IoError(e)
-            })?;
-    
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer).await?;
-    
-        Ok(buffer)
-    }
-}
\ No newline at end of file
+            })
+    }
+}
+
+/// Runs synchronous archive work off the async executor.
+async fn run_blocking<T, F>(f: F) -> Result<T, ComicError>
+where
+    F: FnOnce() -> Result<T, ComicError> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| ComicError::IoError(std::io::Error::other(e)))?
+}
+
+/// Outcome of checking a single archive against its stored record.
+enum Refreshed {
+    Unchanged(ComicRecord),
+    Indexed(ComicRecord),
+}
+
+fn collect_event_paths(event: Event, paths: &mut HashSet<PathBuf>) {
+    if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)) {
+        paths.extend(event.paths);
+    }
+}
+
+/// Gives indexed comics that lack an id one: the id of a vanished comic
+/// with the same fingerprint when the file was moved or renamed, otherwise
+/// one derived from its relative path that no other comic uses.
+fn assign_ids(changed: &mut [ComicRecord], mut vanished: Vec<&ComicRecord>, taken: &mut HashSet<String>) {
+    for record in changed.iter_mut().filter(|record| record.comic.id.is_empty()) {
+        let moved = vanished.iter()
+            .position(|old| !old.fingerprint.is_empty() && old.fingerprint == record.fingerprint)
+            .map(|index| vanished.swap_remove(index));
+
+        let id = match moved {
+            Some(old) => old.comic.id.clone(),
+            None => (0u32..)
+                .map(|attempt| {
+                    let mut hasher = Sha256::new();
+                    hasher.update(record.relative_path.as_bytes());
+                    if attempt > 0 {
+                        hasher.update(attempt.to_le_bytes());
+                    }
+                    format!("{:x}", hasher.finalize())[..ID_LEN].to_string()
+                })
+                .find(|id| !taken.contains(id))
+                .expect("id space exhausted"),
+        };
+
+        taken.insert(id.clone());
+        record.comic.set_id(id);
+    }
+}
+
+/// Hashes a file's size with its first and last `FINGERPRINT_SPAN` bytes,
+/// cheap enough to run on every new file yet stable across moves.
+fn fingerprint(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let size = file.metadata()?.len();
+
+    let mut hasher = Sha256::new();
+    hasher.update(size.to_le_bytes());
+
+    let mut buffer = Vec::new();
+    (&mut file).take(FINGERPRINT_SPAN).read_to_end(&mut buffer)?;
+    hasher.update(&buffer);
+
+    if size > FINGERPRINT_SPAN {
+        buffer.clear();
+        file.seek(SeekFrom::Start(size.saturating_sub(FINGERPRINT_SPAN).max(FINGERPRINT_SPAN)))?;
+        file.take(FINGERPRINT_SPAN).read_to_end(&mut buffer)?;
+        hasher.update(&buffer);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Every folder below the root as a database row, keyed by its `/`-joined path.
+fn folder_records(root: &Folder) -> Vec<FolderRecord> {
+    let mut records = Vec::new();
+    for top in &root.subfolders {
+        top.walk(&mut |folder| {
+            let parent = folder.path.join("/");
+            let path = if parent.is_empty() {
+                folder.name.clone()
+            } else {
+                format!("{}/{}", parent, folder.name)
+            };
+            records.push(FolderRecord {
+                path,
+                name: folder.name.clone(),
+                parent: (!parent.is_empty()).then_some(parent),
+            });
+        });
+    }
+    records
+}
+
+fn modified_nanos(file_metadata: &std::fs::Metadata) -> i64 {
+    file_metadata.modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|elapsed| elapsed.as_nanos() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(relative_path: &str, id: &str, fingerprint: &str) -> ComicRecord {
+        let (folder, file_name) = relative_path.rsplit_once('/').unwrap_or(("", relative_path));
+        let folder_path = if folder.is_empty() { vec![] } else { vec![folder.to_string()] };
+        let mut comic = Comic::new(file_name.to_string(), folder_path, "cbz");
+        if !id.is_empty() {
+            comic.set_id(id.to_string());
+        }
+        ComicRecord {
+            comic,
+            relative_path: relative_path.to_string(),
+            file_size: 0,
+            modified: 0,
+            cover_key: String::new(),
+            fingerprint: fingerprint.to_string(),
+            document_hash: String::new(),
+        }
+    }
+
+    fn ids(records: &[ComicRecord]) -> Vec<&str> {
+        records.iter().map(|record| record.comic.id.as_str()).collect()
+    }
+
+    #[test]
+    fn moved_files_keep_their_id() {
+        let old = record("Saga/01.cbz", "0123456789abcdef", "same-bytes");
+        let mut changed = [record("Saga Vol 1/Chapter 01.cbz", "", "same-bytes")];
+        let mut taken = HashSet::new();
+
+        assign_ids(&mut changed, vec![&old], &mut taken);
+        assert_eq!(ids(&changed), ["0123456789abcdef"]);
+        assert!(taken.contains("0123456789abcdef"));
+    }
+
+    #[test]
+    fn new_files_get_stable_path_ids() {
+        let mut first = [record("Saga/01.cbz", "", "a")];
+        let mut second = [record("Saga/01.cbz", "", "b")];
+        assign_ids(&mut first, vec![], &mut HashSet::new());
+        assign_ids(&mut second, vec![], &mut HashSet::new());
+
+        assert_eq!(first[0].comic.id.len(), ID_LEN);
+        assert_eq!(ids(&first), ids(&second));
+        assert_eq!(first[0].comic.path, format!("/comics/{}", first[0].comic.id));
+    }
+
+    #[test]
+    fn skips_ids_already_in_use() {
+        let mut probe = [record("Saga/01.cbz", "", "a")];
+        assign_ids(&mut probe, vec![], &mut HashSet::new());
+        let path_id = probe[0].comic.id.clone();
+
+        let mut changed = [record("Saga/01.cbz", "", "a")];
+        let mut taken = HashSet::from([path_id.clone()]);
+        assign_ids(&mut changed, vec![], &mut taken);
+
+        assert_ne!(changed[0].comic.id, path_id);
+        assert_eq!(changed[0].comic.id.len(), ID_LEN);
+        assert_eq!(taken.len(), 2);
+    }
+
+    #[test]
+    fn identical_files_get_distinct_ids() {
+        let old = record("Saga/01.cbz", "0123456789abcdef", "same-bytes");
+        let mut changed = [
+            record("Saga/01 copy.cbz", "", "same-bytes"),
+            record("Saga/01 again.cbz", "", "same-bytes"),
+        ];
+        let mut taken = HashSet::new();
+
+        assign_ids(&mut changed, vec![&old], &mut taken);
+        assert_eq!(changed[0].comic.id, "0123456789abcdef");
+        assert_ne!(changed[1].comic.id, changed[0].comic.id);
+        assert_eq!(taken.len(), 2);
+    }
+
+    #[test]
+    fn keeps_existing_ids_and_ignores_unfingerprinted_records() {
+        let old = record("Saga/01.cbz", "0123456789abcdef", "");
+        let mut changed = [
+            record("Saga/02.cbz", "fedcba9876543210", ""),
+            record("Saga/03.cbz", "", ""),
+        ];
+
+        assign_ids(&mut changed, vec![&old], &mut HashSet::new());
+        assert_eq!(changed[0].comic.id, "fedcba9876543210");
+        assert_ne!(changed[1].comic.id, "0123456789abcdef");
+    }
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("comic-service-{}-{}", name, std::process::id()));
+            std::fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+
+        fn write(&self, name: &str, data: &[u8]) -> PathBuf {
+            let path = self.0.join(name);
+            std::fs::write(&path, data).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn fingerprints_follow_content_not_location() {
+        let dir = TempDir::new("fingerprint");
+        let large: Vec<u8> = (0..3 * FINGERPRINT_SPAN).map(|i| i as u8).collect();
+
+        let original = fingerprint(&dir.write("a.cbz", &large)).unwrap();
+        assert_eq!(fingerprint(&dir.write("renamed.cbz", &large)).unwrap(), original);
+
+        let mut tail_changed = large.clone();
+        *tail_changed.last_mut().unwrap() ^= 1;
+        assert_ne!(fingerprint(&dir.write("tail.cbz", &tail_changed)).unwrap(), original);
+
+        let mut head_changed = large.clone();
+        head_changed[0] ^= 1;
+        assert_ne!(fingerprint(&dir.write("head.cbz", &head_changed)).unwrap(), original);
+
+        let truncated = &large[..large.len() - 1];
+        assert_ne!(fingerprint(&dir.write("short.cbz", truncated)).unwrap(), original);
+
+        let small = fingerprint(&dir.write("small.cbz", b"tiny")).unwrap();
+        assert_eq!(fingerprint(&dir.write("small copy.cbz", b"tiny")).unwrap(), small);
+        assert_ne!(small, fingerprint(&dir.write("empty.cbz", b"")).unwrap());
+    }
+}
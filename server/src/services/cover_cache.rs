@@ -0,0 +1,128 @@
+use std::collections::HashSet;
+use std::fs::Metadata;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::UNIX_EPOCH;
+use sha2::{Digest, Sha256};
+use tokio::fs;
+
+use crate::models::comic::CoverImage;
+use crate::services::thumbnails::ThumbnailSize;
+
+const COVER_EXTENSION: &str = "img";
+const THUMBNAIL_EXTENSION: &str = "jpg";
+
+/// Covers and thumbnails persisted on disk so restarts do not have to
+/// reopen every archive.
+///
+/// Entries are keyed by file identity (relative path, size and mtime), so
+/// replacing a comic naturally invalidates its cover. Each key owns one
+/// `<key>.img` original plus any number of `<key>@<size>.jpg` thumbnails.
+pub struct CoverCache {
+    dir: PathBuf,
+}
+
+impl CoverCache {
+    pub async fn new(dir: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&dir).await?;
+        Ok(CoverCache { dir })
+    }
+
+    /// Derives the cache key for a comic file from its identity on disk.
+    pub fn key_for(relative_path: &Path, metadata: &Metadata) -> String {
+        let modified = metadata.modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|time| time.as_nanos())
+            .unwrap_or_default();
+
+        let mut hasher = Sha256::new();
+        hasher.update(relative_path.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update(metadata.len().to_le_bytes());
+        hasher.update(modified.to_le_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+
+    pub async fn contains(&self, key: &str) -> bool {
+        fs::try_exists(self.cover_path(key)).await.unwrap_or(false)
+    }
+
+    pub async fn get(&self, key: &str) -> Option<CoverImage> {
+        read_image(&self.cover_path(key)).await
+    }
+
+    pub async fn put(&self, key: &str, cover: &CoverImage) -> io::Result<()> {
+        write_atomic(&self.cover_path(key), &cover.data).await
+    }
+
+    pub async fn get_thumbnail(&self, key: &str, size: ThumbnailSize) -> Option<CoverImage> {
+        read_image(&self.thumbnail_path(key, size)).await
+    }
+
+    pub async fn put_thumbnail(
+        &self,
+        key: &str,
+        size: ThumbnailSize,
+        thumbnail: &CoverImage,
+    ) -> io::Result<()> {
+        write_atomic(&self.thumbnail_path(key, size), &thumbnail.data).await
+    }
+
+    /// Deletes every cached cover and thumbnail whose key is not in `live`.
+    pub async fn retain(&self, live: &HashSet<String>) -> io::Result<usize> {
+        let mut removed = 0;
+        let mut entries = fs::read_dir(&self.dir).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let key = name.split(['@', '.']).next().unwrap_or_default();
+
+            if !live.contains(key) {
+                fs::remove_file(entry.path()).await?;
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    fn cover_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", key, COVER_EXTENSION))
+    }
+
+    fn thumbnail_path(&self, key: &str, size: ThumbnailSize) -> PathBuf {
+        self.dir.join(format!("{}@{}.{}", key, size.key(), THUMBNAIL_EXTENSION))
+    }
+}
+
+async fn read_image(path: &Path) -> Option<CoverImage> {
+    let data = fs::read(path).await.ok()?;
+    let mime = image::guess_format(&data)
+        .map(|format| format.to_mime_type())
+        .unwrap_or("image/jpeg")
+        .to_string();
+    Some(CoverImage { data, mime })
+}
+
+/// Writes via a temporary file so readers never see a partial image. Each
+/// write gets its own temporary name, so concurrent writers of the same
+/// entry cannot interleave; the last rename wins with a whole image.
+async fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    static NEXT_TEMP: AtomicU64 = AtomicU64::new(0);
+
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(format!(
+        ".{}-{}.tmp",
+        std::process::id(),
+        NEXT_TEMP.fetch_add(1, Ordering::Relaxed),
+    ));
+    let temp = path.with_file_name(temp_name);
+
+    if let Err(e) = fs::write(&temp, data).await {
+        let _ = fs::remove_file(&temp).await;
+        return Err(e);
+    }
+    fs::rename(&temp, path).await
+}
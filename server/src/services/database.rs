@@ -0,0 +1,944 @@
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension, Row};
+
+use crate::models::comic::Comic;
+use crate::models::error::ComicError;
+use crate::models::metadata::ComicMetadata;
+use crate::models::progress::ReadProgress;
+use crate::models::user::{Session, User};
+
+/// Schema migrations, applied in order. `PRAGMA user_version` records how
+/// many have run; append new steps, never edit released ones.
+const MIGRATIONS: &[&str] = &[
+    // 1: library tables
+    "CREATE TABLE folders (
+        path TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        parent TEXT
+    );
+    CREATE TABLE comics (
+        relative_path TEXT PRIMARY KEY,
+        id TEXT NOT NULL,
+        name TEXT NOT NULL,
+        file_name TEXT NOT NULL,
+        folder_path TEXT NOT NULL,
+        format TEXT NOT NULL,
+        file_size INTEGER NOT NULL,
+        modified INTEGER NOT NULL,
+        cover_key TEXT NOT NULL,
+        metadata TEXT,
+        scanned_at INTEGER NOT NULL
+    );
+    CREATE INDEX comics_id ON comics (id);
+    CREATE TABLE scan_state (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );",
+    // 2: content fingerprints; rows from before this are re-read once and
+    // given path-derived ids in place of their bare file names
+    "ALTER TABLE comics ADD COLUMN fingerprint TEXT NOT NULL DEFAULT '';
+    CREATE INDEX comics_fingerprint ON comics (fingerprint);",
+    // 3: user accounts
+    "CREATE TABLE users (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        username TEXT NOT NULL UNIQUE COLLATE NOCASE,
+        password_hash TEXT NOT NULL,
+        is_admin INTEGER NOT NULL DEFAULT 0,
+        disabled INTEGER NOT NULL DEFAULT 0,
+        created_at INTEGER NOT NULL
+    );",
+    // 4: login sessions
+    "CREATE TABLE sessions (
+        id TEXT PRIMARY KEY,
+        user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        token_hash TEXT NOT NULL UNIQUE,
+        refresh_hash TEXT NOT NULL UNIQUE,
+        created_at INTEGER NOT NULL,
+        access_expires_at INTEGER NOT NULL,
+        expires_at INTEGER NOT NULL,
+        last_used_at INTEGER NOT NULL,
+        user_agent TEXT
+    );
+    CREATE INDEX sessions_user ON sessions (user_id);",
+    // 5: reading progress; user 0 is the anonymous reader of a server
+    // without accounts, so there is no foreign key
+    "CREATE TABLE progress (
+        user_id INTEGER NOT NULL,
+        comic_id TEXT NOT NULL,
+        page INTEGER NOT NULL,
+        completed INTEGER NOT NULL,
+        last_read_at INTEGER NOT NULL,
+        PRIMARY KEY (user_id, comic_id)
+    );",
+    // 6: page counts; rows from before this keep 0 until their pages are
+    // first listed
+    "ALTER TABLE comics ADD COLUMN page_count INTEGER NOT NULL DEFAULT 0;",
+    // 7: KOReader sync; existing comics are hashed in the background after
+    // startup, and accounts get a kosync key on their next sign-in
+    "ALTER TABLE comics ADD COLUMN document_hash TEXT NOT NULL DEFAULT '';
+    CREATE INDEX comics_document_hash ON comics (document_hash);
+    ALTER TABLE users ADD COLUMN kosync_key_hash TEXT;
+    ALTER TABLE progress ADD COLUMN device TEXT;
+    ALTER TABLE progress ADD COLUMN device_id TEXT;",
+    // 8: URL token generations; signed URLs carry their user's current
+    // one, so bumping it revokes them all
+    "ALTER TABLE users ADD COLUMN token_generation INTEGER NOT NULL DEFAULT 0;",
+];
+
+/// A comic as stored in the library database, with the file identity used
+/// to decide whether it must be re-read on the next scan.
+#[derive(Debug, Clone)]
+pub struct ComicRecord {
+    pub comic: Comic,
+    pub relative_path: String,
+    pub file_size: u64,
+    /// Modification time in nanoseconds since the Unix epoch.
+    pub modified: i64,
+    pub cover_key: String,
+    /// Hash of the file's size and leading and trailing bytes, used to
+    /// recognise a comic that was moved or renamed.
+    pub fingerprint: String,
+    /// KOReader's partial MD5 of the file, see `kosync::document_hash`.
+    pub document_hash: String,
+}
+
+/// What an account signs in with, as stored.
+pub struct Credentials {
+    pub user: User,
+    pub password_hash: String,
+    /// Argon2 hash of the MD5 key kosync clients send in place of the
+    /// password. Unset until the account signs in with its password once.
+    pub kosync_key_hash: Option<String>,
+}
+
+impl ComicRecord {
+    /// True when the file on disk still matches what was indexed.
+    pub fn is_current(&self, file_size: u64, modified: i64) -> bool {
+        !self.fingerprint.is_empty() && self.file_size == file_size && self.modified == modified
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FolderRecord {
+    pub path: String,
+    pub name: String,
+    pub parent: Option<String>,
+}
+
+/// Embedded SQLite store for the library.
+pub struct Database {
+    connection: Mutex<Connection>,
+}
+
+impl Database {
+    pub fn open(path: &Path) -> Result<Self, ComicError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut connection = Connection::open(path).map_err(ComicError::database)?;
+        connection.execute_batch("PRAGMA journal_mode = WAL; PRAGMA foreign_keys = ON;")
+            .map_err(ComicError::database)?;
+        migrate(&mut connection)?;
+
+        Ok(Database { connection: Mutex::new(connection) })
+    }
+
+    fn connection(&self) -> MutexGuard<'_, Connection> {
+        // A panic mid-statement leaves SQLite itself consistent, so a
+        // poisoned lock is safe to reuse
+        self.connection.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn load_comics(&self) -> Result<Vec<ComicRecord>, ComicError> {
+        let connection = self.connection();
+        let mut statement = connection.prepare(
+            "SELECT relative_path, id, name, file_name, folder_path, format,
+                    file_size, modified, cover_key, metadata, fingerprint, page_count,
+                    document_hash
+             FROM comics"
+        ).map_err(ComicError::database)?;
+
+        let records = statement.query_map([], read_comic)
+            .map_err(ComicError::database)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(ComicError::database)?;
+        Ok(records)
+    }
+
+    /// Applies the outcome of a scan in one transaction: upserts changed
+    /// comics, deletes vanished ones and replaces the folder list.
+    pub fn apply_scan(
+        &self,
+        changed: &[ComicRecord],
+        removed: &[String],
+        folders: &[FolderRecord],
+    ) -> Result<(), ComicError> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction().map_err(ComicError::database)?;
+        let now = unix_time();
+
+        {
+            let mut upsert = transaction.prepare(
+                "INSERT INTO comics (relative_path, id, name, file_name, folder_path, format,
+                                     file_size, modified, cover_key, metadata, scanned_at,
+                                     fingerprint, page_count, document_hash)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+                 ON CONFLICT (relative_path) DO UPDATE SET
+                    id = excluded.id, name = excluded.name, file_name = excluded.file_name,
+                    folder_path = excluded.folder_path, format = excluded.format,
+                    file_size = excluded.file_size, modified = excluded.modified,
+                    cover_key = excluded.cover_key, metadata = excluded.metadata,
+                    scanned_at = excluded.scanned_at, fingerprint = excluded.fingerprint,
+                    page_count = excluded.page_count, document_hash = excluded.document_hash"
+            ).map_err(ComicError::database)?;
+
+            for record in changed {
+                let comic = &record.comic;
+                upsert.execute(params![
+                    record.relative_path,
+                    comic.id,
+                    comic.name,
+                    comic.file_name,
+                    to_json(&comic.folder_path)?,
+                    comic.format,
+                    record.file_size as i64,
+                    record.modified,
+                    record.cover_key,
+                    comic.metadata.as_ref().map(to_json).transpose()?,
+                    now,
+                    record.fingerprint,
+                    comic.page_count as i64,
+                    record.document_hash,
+                ]).map_err(ComicError::database)?;
+            }
+
+            let mut delete = transaction.prepare("DELETE FROM comics WHERE relative_path = ?1")
+                .map_err(ComicError::database)?;
+            for relative_path in removed {
+                delete.execute([relative_path]).map_err(ComicError::database)?;
+            }
+
+            transaction.execute("DELETE FROM folders", []).map_err(ComicError::database)?;
+            let mut insert_folder = transaction.prepare(
+                "INSERT INTO folders (path, name, parent) VALUES (?1, ?2, ?3)"
+            ).map_err(ComicError::database)?;
+            for folder in folders {
+                insert_folder.execute(params![folder.path, folder.name, folder.parent])
+                    .map_err(ComicError::database)?;
+            }
+        }
+
+        transaction.commit().map_err(ComicError::database)
+    }
+
+    pub fn set_page_count(&self, relative_path: &str, page_count: usize) -> Result<(), ComicError> {
+        self.connection()
+            .execute(
+                "UPDATE comics SET page_count = ?2 WHERE relative_path = ?1",
+                params![relative_path, page_count as i64],
+            )
+            .map(|_| ())
+            .map_err(ComicError::database)
+    }
+
+    pub fn set_document_hash(&self, relative_path: &str, document_hash: &str) -> Result<(), ComicError> {
+        self.connection()
+            .execute(
+                "UPDATE comics SET document_hash = ?2 WHERE relative_path = ?1",
+                params![relative_path, document_hash],
+            )
+            .map(|_| ())
+            .map_err(ComicError::database)
+    }
+
+    pub fn scan_state(&self, key: &str) -> Result<Option<String>, ComicError> {
+        self.connection()
+            .query_row("SELECT value FROM scan_state WHERE key = ?1", [key], |row| row.get(0))
+            .optional()
+            .map_err(ComicError::database)
+    }
+
+    pub fn set_scan_state(&self, key: &str, value: &str) -> Result<(), ComicError> {
+        self.connection()
+            .execute(
+                "INSERT INTO scan_state (key, value) VALUES (?1, ?2)
+                 ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+                [key, value],
+            )
+            .map(|_| ())
+            .map_err(ComicError::database)
+    }
+}
+
+/// User queries. Password hashes never leave this module except through
+/// `user_credentials`, which authentication needs.
+impl Database {
+    pub fn count_users(&self) -> Result<u64, ComicError> {
+        self.connection()
+            .query_row("SELECT COUNT(*) FROM users", [], |row| row.get::<_, i64>(0))
+            .map(|count| count as u64)
+            .map_err(ComicError::database)
+    }
+
+    pub fn count_active_admins(&self) -> Result<u64, ComicError> {
+        self.connection()
+            .query_row(
+                "SELECT COUNT(*) FROM users WHERE is_admin = 1 AND disabled = 0",
+                [],
+                |row| row.get::<_, i64>(0),
+            )
+            .map(|count| count as u64)
+            .map_err(ComicError::database)
+    }
+
+    pub fn list_users(&self) -> Result<Vec<User>, ComicError> {
+        let connection = self.connection();
+        let mut statement = connection.prepare(
+            "SELECT id, username, is_admin, disabled, created_at FROM users ORDER BY username"
+        ).map_err(ComicError::database)?;
+
+        let users = statement.query_map([], read_user)
+            .map_err(ComicError::database)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(ComicError::database)?;
+        Ok(users)
+    }
+
+    pub fn get_user(&self, id: i64) -> Result<Option<User>, ComicError> {
+        self.connection()
+            .query_row(
+                "SELECT id, username, is_admin, disabled, created_at FROM users WHERE id = ?1",
+                [id],
+                read_user,
+            )
+            .optional()
+            .map_err(ComicError::database)
+    }
+
+    /// Looks a user up by name, case-insensitively, with their password hash.
+    pub fn user_credentials(&self, username: &str) -> Result<Option<Credentials>, ComicError> {
+        self.connection()
+            .query_row(
+                "SELECT id, username, is_admin, disabled, created_at, password_hash, kosync_key_hash
+                 FROM users WHERE username = ?1",
+                [username],
+                |row| Ok(Credentials {
+                    user: read_user(row)?,
+                    password_hash: row.get(5)?,
+                    kosync_key_hash: row.get(6)?,
+                }),
+            )
+            .optional()
+            .map_err(ComicError::database)
+    }
+
+    pub fn create_user(
+        &self,
+        username: &str,
+        password_hash: &str,
+        kosync_key_hash: &str,
+        is_admin: bool,
+    ) -> Result<User, ComicError> {
+        let created_at = unix_time();
+        let connection = self.connection();
+        // The first account must be able to manage the others. Checked
+        // under the connection lock, so two first accounts cannot race
+        let is_admin = is_admin || connection
+            .query_row("SELECT NOT EXISTS (SELECT 1 FROM users)", [], |row| row.get::<_, bool>(0))
+            .map_err(ComicError::database)?;
+        connection
+            .execute(
+                "INSERT INTO users (username, password_hash, kosync_key_hash, is_admin, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![username, password_hash, kosync_key_hash, is_admin, created_at],
+            )
+            .map_err(|e| match e.sqlite_error_code() {
+                Some(ErrorCode::ConstraintViolation) => ComicError::UserExists,
+                _ => ComicError::database(e),
+            })?;
+
+        Ok(User {
+            id: connection.last_insert_rowid(),
+            username: username.to_string(),
+            is_admin,
+            disabled: false,
+            created_at,
+        })
+    }
+
+    pub fn update_user(&self, user: &User) -> Result<(), ComicError> {
+        self.connection()
+            .execute(
+                "UPDATE users SET is_admin = ?2, disabled = ?3 WHERE id = ?1",
+                params![user.id, user.is_admin, user.disabled],
+            )
+            .map(|_| ())
+            .map_err(ComicError::database)
+    }
+
+    pub fn set_password_hash(
+        &self,
+        id: i64,
+        password_hash: &str,
+        kosync_key_hash: &str,
+    ) -> Result<(), ComicError> {
+        self.connection()
+            .execute(
+                "UPDATE users SET password_hash = ?2, kosync_key_hash = ?3 WHERE id = ?1",
+                params![id, password_hash, kosync_key_hash],
+            )
+            .map(|_| ())
+            .map_err(ComicError::database)
+    }
+
+    pub fn set_kosync_key_hash(&self, id: i64, kosync_key_hash: &str) -> Result<(), ComicError> {
+        self.connection()
+            .execute("UPDATE users SET kosync_key_hash = ?2 WHERE id = ?1", params![id, kosync_key_hash])
+            .map(|_| ())
+            .map_err(ComicError::database)
+    }
+
+    /// The generation signed into a user's URL tokens, or `None` when the
+    /// account is gone or disabled.
+    pub fn token_generation(&self, id: i64) -> Result<Option<i64>, ComicError> {
+        self.connection()
+            .query_row(
+                "SELECT token_generation FROM users WHERE id = ?1 AND disabled = 0",
+                [id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(ComicError::database)
+    }
+
+    pub fn delete_user(&self, id: i64) -> Result<(), ComicError> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction().map_err(ComicError::database)?;
+        transaction.execute("DELETE FROM progress WHERE user_id = ?1", [id])
+            .map_err(ComicError::database)?;
+        transaction.execute("DELETE FROM users WHERE id = ?1", [id])
+            .map_err(ComicError::database)?;
+        transaction.commit().map_err(ComicError::database)
+    }
+}
+
+/// Hashed credentials for one session, as written on login or refresh.
+pub struct SessionSecrets {
+    pub token_hash: String,
+    pub refresh_hash: String,
+    pub access_expires_at: i64,
+    pub expires_at: i64,
+}
+
+const SESSION_COLUMNS: &str =
+    "s.id, s.user_id, s.created_at, s.expires_at, s.last_used_at, s.user_agent";
+const SESSION_USER_COLUMNS: &str =
+    "u.id, u.username, u.is_admin, u.disabled, u.created_at";
+
+/// Session queries. Lookups by token only match unexpired sessions of
+/// enabled users.
+impl Database {
+    pub fn create_session(
+        &self,
+        id: &str,
+        user_id: i64,
+        secrets: &SessionSecrets,
+        user_agent: Option<&str>,
+    ) -> Result<Session, ComicError> {
+        let now = unix_time();
+        self.connection()
+            .execute(
+                "INSERT INTO sessions (id, user_id, token_hash, refresh_hash, created_at,
+                                       access_expires_at, expires_at, last_used_at, user_agent)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?5, ?8)",
+                params![
+                    id,
+                    user_id,
+                    secrets.token_hash,
+                    secrets.refresh_hash,
+                    now,
+                    secrets.access_expires_at,
+                    secrets.expires_at,
+                    user_agent,
+                ],
+            )
+            .map_err(ComicError::database)?;
+
+        Ok(Session {
+            id: id.to_string(),
+            user_id,
+            created_at: now,
+            expires_at: secrets.expires_at,
+            last_used_at: now,
+            user_agent: user_agent.map(str::to_string),
+        })
+    }
+
+    pub fn session_by_token(&self, token_hash: &str) -> Result<Option<(User, Session)>, ComicError> {
+        self.session_where("s.token_hash = ?1 AND s.access_expires_at > ?2", token_hash)
+    }
+
+    pub fn session_by_refresh(&self, refresh_hash: &str) -> Result<Option<(User, Session)>, ComicError> {
+        self.session_where("s.refresh_hash = ?1 AND s.expires_at > ?2", refresh_hash)
+    }
+
+    fn session_where(&self, condition: &str, hash: &str) -> Result<Option<(User, Session)>, ComicError> {
+        let sql = format!(
+            "SELECT {}, {} FROM sessions s JOIN users u ON u.id = s.user_id
+             WHERE {} AND u.disabled = 0",
+            SESSION_COLUMNS, SESSION_USER_COLUMNS, condition
+        );
+        self.connection()
+            .query_row(&sql, params![hash, unix_time()], |row| {
+                let session = read_session(row)?;
+                let user = User {
+                    id: row.get(6)?,
+                    username: row.get(7)?,
+                    is_admin: row.get(8)?,
+                    disabled: row.get(9)?,
+                    created_at: row.get(10)?,
+                };
+                Ok((user, session))
+            })
+            .optional()
+            .map_err(ComicError::database)
+    }
+
+    /// Replaces a session's tokens, invalidating the previous pair.
+    pub fn rotate_session(&self, id: &str, secrets: &SessionSecrets) -> Result<(), ComicError> {
+        self.connection()
+            .execute(
+                "UPDATE sessions SET token_hash = ?2, refresh_hash = ?3, access_expires_at = ?4,
+                                     expires_at = ?5, last_used_at = ?6
+                 WHERE id = ?1",
+                params![
+                    id,
+                    secrets.token_hash,
+                    secrets.refresh_hash,
+                    secrets.access_expires_at,
+                    secrets.expires_at,
+                    unix_time(),
+                ],
+            )
+            .map(|_| ())
+            .map_err(ComicError::database)
+    }
+
+    pub fn touch_session(&self, id: &str) -> Result<(), ComicError> {
+        self.connection()
+            .execute("UPDATE sessions SET last_used_at = ?2 WHERE id = ?1", params![id, unix_time()])
+            .map(|_| ())
+            .map_err(ComicError::database)
+    }
+
+    pub fn get_session(&self, id: &str) -> Result<Option<Session>, ComicError> {
+        let sql = format!("SELECT {} FROM sessions s WHERE s.id = ?1", SESSION_COLUMNS);
+        self.connection()
+            .query_row(&sql, [id], read_session)
+            .optional()
+            .map_err(ComicError::database)
+    }
+
+    pub fn list_sessions(&self, user_id: i64) -> Result<Vec<Session>, ComicError> {
+        let sql = format!(
+            "SELECT {} FROM sessions s WHERE s.user_id = ?1 AND s.expires_at > ?2
+             ORDER BY s.last_used_at DESC",
+            SESSION_COLUMNS
+        );
+        let connection = self.connection();
+        let mut statement = connection.prepare(&sql).map_err(ComicError::database)?;
+
+        let sessions = statement.query_map(params![user_id, unix_time()], read_session)
+            .map_err(ComicError::database)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(ComicError::database)?;
+        Ok(sessions)
+    }
+
+    pub fn delete_session(&self, id: &str) -> Result<(), ComicError> {
+        self.connection()
+            .execute("DELETE FROM sessions WHERE id = ?1", [id])
+            .map(|_| ())
+            .map_err(ComicError::database)
+    }
+
+    /// Signs a user out everywhere: drops their sessions and revokes the
+    /// signed URLs issued to them.
+    pub fn delete_user_sessions(&self, user_id: i64) -> Result<(), ComicError> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction().map_err(ComicError::database)?;
+        transaction.execute("DELETE FROM sessions WHERE user_id = ?1", [user_id])
+            .map_err(ComicError::database)?;
+        transaction.execute(
+            "UPDATE users SET token_generation = token_generation + 1 WHERE id = ?1",
+            [user_id],
+        ).map_err(ComicError::database)?;
+        transaction.commit().map_err(ComicError::database)
+    }
+
+    pub fn delete_expired_sessions(&self) -> Result<(), ComicError> {
+        self.connection()
+            .execute("DELETE FROM sessions WHERE expires_at <= ?1", [unix_time()])
+            .map(|_| ())
+            .map_err(ComicError::database)
+    }
+}
+
+/// Reading progress queries.
+impl Database {
+    pub fn progress(&self, user_id: i64, comic_id: &str) -> Result<Option<ReadProgress>, ComicError> {
+        self.connection()
+            .query_row(
+                "SELECT comic_id, page, completed, last_read_at, device, device_id FROM progress
+                 WHERE user_id = ?1 AND comic_id = ?2",
+                params![user_id, comic_id],
+                read_progress,
+            )
+            .optional()
+            .map_err(ComicError::database)
+    }
+
+    pub fn all_progress(&self, user_id: i64) -> Result<Vec<ReadProgress>, ComicError> {
+        let connection = self.connection();
+        let mut statement = connection.prepare(
+            "SELECT comic_id, page, completed, last_read_at, device, device_id FROM progress
+             WHERE user_id = ?1 ORDER BY last_read_at DESC"
+        ).map_err(ComicError::database)?;
+
+        let progress = statement.query_map([user_id], read_progress)
+            .map_err(ComicError::database)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(ComicError::database)?;
+        Ok(progress)
+    }
+
+    /// Stores progress unless a newer update is already recorded, and
+    /// returns whichever update won.
+    pub fn save_progress(&self, user_id: i64, progress: &ReadProgress) -> Result<ReadProgress, ComicError> {
+        let connection = self.connection();
+        connection
+            .execute(
+                "INSERT INTO progress (user_id, comic_id, page, completed, last_read_at,
+                                       device, device_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                 ON CONFLICT (user_id, comic_id) DO UPDATE SET
+                    page = excluded.page, completed = excluded.completed,
+                    last_read_at = excluded.last_read_at, device = excluded.device,
+                    device_id = excluded.device_id
+                 WHERE excluded.last_read_at >= progress.last_read_at",
+                params![
+                    user_id,
+                    progress.comic_id,
+                    progress.page as i64,
+                    progress.completed,
+                    progress.last_read_at,
+                    progress.device,
+                    progress.device_id,
+                ],
+            )
+            .map_err(ComicError::database)?;
+
+        connection
+            .query_row(
+                "SELECT comic_id, page, completed, last_read_at, device, device_id FROM progress
+                 WHERE user_id = ?1 AND comic_id = ?2",
+                params![user_id, progress.comic_id],
+                read_progress,
+            )
+            .map_err(ComicError::database)
+    }
+
+    /// Marks comics finished as of `last_read_at`, keeping the page each
+    /// was left on. Comics with a newer update are left alone.
+    pub fn mark_read(&self, user_id: i64, comic_ids: &[String], last_read_at: i64) -> Result<(), ComicError> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction().map_err(ComicError::database)?;
+        {
+            let mut statement = transaction.prepare(
+                "INSERT INTO progress (user_id, comic_id, page, completed, last_read_at)
+                 VALUES (?1, ?2, 0, 1, ?3)
+                 ON CONFLICT (user_id, comic_id) DO UPDATE SET
+                    completed = 1, last_read_at = excluded.last_read_at,
+                    device = NULL, device_id = NULL
+                 WHERE excluded.last_read_at >= progress.last_read_at"
+            ).map_err(ComicError::database)?;
+            for comic_id in comic_ids {
+                statement.execute(params![user_id, comic_id, last_read_at])
+                    .map_err(ComicError::database)?;
+            }
+        }
+        transaction.commit().map_err(ComicError::database)
+    }
+
+    pub fn delete_progress_for(&self, user_id: i64, comic_ids: &[String]) -> Result<(), ComicError> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction().map_err(ComicError::database)?;
+        {
+            let mut statement = transaction.prepare(
+                "DELETE FROM progress WHERE user_id = ?1 AND comic_id = ?2"
+            ).map_err(ComicError::database)?;
+            for comic_id in comic_ids {
+                statement.execute(params![user_id, comic_id])
+                    .map_err(ComicError::database)?;
+            }
+        }
+        transaction.commit().map_err(ComicError::database)
+    }
+
+    pub fn delete_progress(&self, user_id: i64, comic_id: &str) -> Result<(), ComicError> {
+        self.connection()
+            .execute(
+                "DELETE FROM progress WHERE user_id = ?1 AND comic_id = ?2",
+                params![user_id, comic_id],
+            )
+            .map(|_| ())
+            .map_err(ComicError::database)
+    }
+}
+
+fn migrate(connection: &mut Connection) -> Result<(), ComicError> {
+    let version: i64 = connection
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(ComicError::database)?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let transaction = connection.transaction().map_err(ComicError::database)?;
+        transaction.execute_batch(migration).map_err(ComicError::database)?;
+        transaction.pragma_update(None, "user_version", index as i64 + 1)
+            .map_err(ComicError::database)?;
+        transaction.commit().map_err(ComicError::database)?;
+        println!("Applied database migration {}", index + 1);
+    }
+
+    Ok(())
+}
+
+fn read_comic(row: &Row<'_>) -> rusqlite::Result<ComicRecord> {
+    let folder_path: String = row.get(4)?;
+    let metadata: Option<String> = row.get(9)?;
+
+    let folder_path = serde_json::from_str(&folder_path).unwrap_or_default();
+    let format: String = row.get(5)?;
+    let mut comic = Comic::new(row.get(3)?, folder_path, &format)
+        .with_metadata(metadata.and_then(|json| {
+            serde_json::from_str::<ComicMetadata>(&json).ok()
+        }));
+    comic.set_id(row.get(1)?);
+    comic.name = row.get(2)?;
+    comic.page_count = row.get::<_, i64>(11)? as usize;
+
+    Ok(ComicRecord {
+        comic,
+        relative_path: row.get(0)?,
+        file_size: row.get::<_, i64>(6)? as u64,
+        modified: row.get(7)?,
+        cover_key: row.get(8)?,
+        fingerprint: row.get(10)?,
+        document_hash: row.get(12)?,
+    })
+}
+
+fn read_user(row: &Row<'_>) -> rusqlite::Result<User> {
+    Ok(User {
+        id: row.get(0)?,
+        username: row.get(1)?,
+        is_admin: row.get(2)?,
+        disabled: row.get(3)?,
+        created_at: row.get(4)?,
+    })
+}
+
+fn read_session(row: &Row<'_>) -> rusqlite::Result<Session> {
+    Ok(Session {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        created_at: row.get(2)?,
+        expires_at: row.get(3)?,
+        last_used_at: row.get(4)?,
+        user_agent: row.get(5)?,
+    })
+}
+
+fn read_progress(row: &Row<'_>) -> rusqlite::Result<ReadProgress> {
+    Ok(ReadProgress {
+        comic_id: row.get(0)?,
+        page: row.get::<_, i64>(1)? as usize,
+        completed: row.get(2)?,
+        last_read_at: row.get(3)?,
+        device: row.get(4)?,
+        device_id: row.get(5)?,
+    })
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<String, ComicError> {
+    serde_json::to_string(value).map_err(|e| ComicError::DatabaseError(e.to_string()))
+}
+
+/// Runs a service's database work off the async executor, since SQLite
+/// calls block on the disk and on the shared connection.
+pub async fn run_blocking<S, T, F>(service: &S, f: F) -> Result<T, ComicError>
+where
+    S: Clone + Send + 'static,
+    F: FnOnce(&S) -> Result<T, ComicError> + Send + 'static,
+    T: Send + 'static,
+{
+    let service = service.clone();
+    tokio::task::spawn_blocking(move || f(&service))
+        .await
+        .map_err(|e| ComicError::IoError(std::io::Error::other(e)))?
+}
+
+pub fn unix_time() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_version(connection: &Connection) -> i64 {
+        connection.query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap()
+    }
+
+    fn in_memory() -> Database {
+        let mut connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch("PRAGMA foreign_keys = ON;").unwrap();
+        migrate(&mut connection).unwrap();
+        Database { connection: Mutex::new(connection) }
+    }
+
+    fn record(folder: &str, file_name: &str, id: &str, file_size: u64) -> ComicRecord {
+        let mut comic = Comic::new(file_name.to_string(), vec![folder.to_string()], "cbz");
+        comic.set_id(id.to_string());
+        comic.page_count = 20;
+        ComicRecord {
+            relative_path: comic.relative_path(),
+            comic,
+            file_size,
+            modified: 1_000,
+            cover_key: format!("cover-{}", id),
+            fingerprint: format!("fingerprint-{}", id),
+            document_hash: format!("hash-{}", id),
+        }
+    }
+
+    fn folder(path: &str) -> FolderRecord {
+        FolderRecord { path: path.to_string(), name: path.to_string(), parent: None }
+    }
+
+    fn folder_paths(database: &Database) -> Vec<String> {
+        let connection = database.connection();
+        let mut statement = connection.prepare("SELECT path FROM folders ORDER BY path").unwrap();
+        statement.query_map([], |row| row.get(0)).unwrap().collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn migrates_an_empty_database_to_the_latest_version() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        assert_eq!(user_version(&connection), 0);
+
+        migrate(&mut connection).unwrap();
+        assert_eq!(user_version(&connection), MIGRATIONS.len() as i64);
+        assert_eq!(MIGRATIONS.len(), 8);
+
+        // Columns from the last migrations are in place
+        connection.execute_batch(
+            "SELECT fingerprint, page_count, document_hash FROM comics;
+             SELECT kosync_key_hash, token_generation FROM users;
+             SELECT device, device_id FROM progress;
+             SELECT id FROM sessions;"
+        ).unwrap();
+
+        // Running again applies nothing
+        migrate(&mut connection).unwrap();
+        assert_eq!(user_version(&connection), MIGRATIONS.len() as i64);
+    }
+
+    #[test]
+    fn upgrades_rows_written_by_the_first_schema() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(MIGRATIONS[0]).unwrap();
+        connection.pragma_update(None, "user_version", 1).unwrap();
+        connection.execute(
+            "INSERT INTO comics (relative_path, id, name, file_name, folder_path, format,
+                                 file_size, modified, cover_key, metadata, scanned_at)
+             VALUES ('Saga/01.cbz', '01.cbz', '01', '01.cbz', '[\"Saga\"]', 'cbz', 10, 5, 'key', NULL, 0)",
+            [],
+        ).unwrap();
+
+        migrate(&mut connection).unwrap();
+        assert_eq!(user_version(&connection), MIGRATIONS.len() as i64);
+
+        let database = Database { connection: Mutex::new(connection) };
+        let records = database.load_comics().unwrap();
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record.comic.folder_path, ["Saga"]);
+        assert_eq!(record.comic.page_count, 0);
+        assert_eq!(record.fingerprint, "");
+        assert_eq!(record.document_hash, "");
+        // No fingerprint yet, so the next scan re-reads it
+        assert!(!record.is_current(10, 5));
+    }
+
+    #[test]
+    fn applies_scans_as_inserts_updates_and_deletes() {
+        let database = in_memory();
+        database.apply_scan(
+            &[record("Saga", "01.cbz", "a", 10), record("Saga", "02.cbz", "b", 20)],
+            &[],
+            &[folder("Saga")],
+        ).unwrap();
+
+        let mut records = database.load_comics().unwrap();
+        records.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].relative_path, "Saga/01.cbz");
+        assert_eq!(records[0].comic.id, "a");
+        assert_eq!(records[0].comic.page_count, 20);
+        assert_eq!(records[0].document_hash, "hash-a");
+        assert!(records[0].is_current(10, 1_000));
+
+        // 01 changed on disk, 02 is gone and 03 is new
+        database.apply_scan(
+            &[record("Saga", "01.cbz", "a", 11), record("Paper Girls", "03.cbz", "c", 30)],
+            &[String::from("Saga/02.cbz")],
+            &[folder("Paper Girls"), folder("Saga")],
+        ).unwrap();
+
+        let mut records = database.load_comics().unwrap();
+        records.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+        let paths: Vec<&str> = records.iter().map(|record| record.relative_path.as_str()).collect();
+        assert_eq!(paths, ["Paper Girls/03.cbz", "Saga/01.cbz"]);
+        assert_eq!(records[1].file_size, 11);
+        assert!(!records[1].is_current(10, 1_000));
+        assert_eq!(folder_paths(&database), ["Paper Girls", "Saga"]);
+
+        // Folders are replaced wholesale
+        database.apply_scan(&[], &[String::from("Saga/01.cbz")], &[folder("Paper Girls")]).unwrap();
+        assert_eq!(database.load_comics().unwrap().len(), 1);
+        assert_eq!(folder_paths(&database), ["Paper Girls"]);
+    }
+
+    #[test]
+    fn backfills_page_counts_and_hashes() {
+        let database = in_memory();
+        let mut stale = record("Saga", "01.cbz", "a", 10);
+        stale.comic.page_count = 0;
+        stale.document_hash = String::new();
+        database.apply_scan(&[stale], &[], &[]).unwrap();
+
+        database.set_page_count("Saga/01.cbz", 42).unwrap();
+        database.set_document_hash("Saga/01.cbz", "abc").unwrap();
+
+        let record = &database.load_comics().unwrap()[0];
+        assert_eq!(record.comic.page_count, 42);
+        assert_eq!(record.document_hash, "abc");
+    }
+}
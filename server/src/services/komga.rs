@@ -0,0 +1,269 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Page size Komga uses when a client does not ask for one.
+pub const DEFAULT_PAGE_SIZE: usize = 20;
+
+const SERIES_ID_LEN: usize = 16;
+
+/// Stable id for the series a folder stands for.
+pub fn series_id(folder_path: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(b"series:");
+    hasher.update(folder_path.join("/").as_bytes());
+    format!("{:x}", hasher.finalize())[..SERIES_ID_LEN].to_string()
+}
+
+/// One page of a listing, shaped like Spring's `Page` that Komga returns.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageWrapper<T> {
+    pub content: Vec<T>,
+    pub total_elements: usize,
+    pub total_pages: usize,
+    /// Zero-based index of this page.
+    pub number: usize,
+    pub size: usize,
+    pub number_of_elements: usize,
+    pub first: bool,
+    pub last: bool,
+    pub empty: bool,
+}
+
+impl<T> PageWrapper<T> {
+    /// Cuts zero-based `page` out of `items`, or returns them all when
+    /// `size` is `None`, as for Komga's `unpaged=true`.
+    pub fn paginate(items: Vec<T>, page: usize, size: Option<usize>) -> Self {
+        let total_elements = items.len();
+        let (page, size) = match size {
+            Some(size) => (page, size.max(1)),
+            None => (0, total_elements.max(1)),
+        };
+        let total_pages = total_elements.div_ceil(size);
+        // Pages far past the end must not overflow the offset
+        let content: Vec<T> = items.into_iter().skip(page.saturating_mul(size)).take(size).collect();
+
+        PageWrapper {
+            total_elements,
+            total_pages,
+            number: page,
+            size,
+            number_of_elements: content.len(),
+            first: page == 0,
+            last: page.saturating_add(1) >= total_pages,
+            empty: content.is_empty(),
+            content,
+        }
+    }
+}
+
+/// Spring's error body, which Komga sends with failed requests.
+#[derive(Debug, Serialize)]
+pub struct ErrorDto {
+    pub timestamp: String,
+    pub status: u16,
+    pub error: &'static str,
+    pub path: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryDto {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub root: String,
+    pub unavailable: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeriesDto {
+    pub id: String,
+    pub library_id: &'static str,
+    pub name: String,
+    pub url: String,
+    pub created: String,
+    pub last_modified: String,
+    pub file_last_modified: String,
+    pub books_count: usize,
+    pub books_read_count: usize,
+    pub books_unread_count: usize,
+    pub books_in_progress_count: usize,
+    pub metadata: SeriesMetadataDto,
+    pub books_metadata: BookMetadataAggregationDto,
+    pub deleted: bool,
+    pub oneshot: bool,
+}
+
+/// Series metadata. Every field is reported unlocked, as nothing here is
+/// edited by hand.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeriesMetadataDto {
+    /// `ONGOING` or `ENDED`.
+    pub status: &'static str,
+    pub status_lock: bool,
+    pub title: String,
+    pub title_lock: bool,
+    pub title_sort: String,
+    pub title_sort_lock: bool,
+    pub summary: String,
+    pub summary_lock: bool,
+    /// `LEFT_TO_RIGHT` or `RIGHT_TO_LEFT`.
+    pub reading_direction: &'static str,
+    pub reading_direction_lock: bool,
+    pub publisher: String,
+    pub publisher_lock: bool,
+    pub age_rating: Option<u32>,
+    pub age_rating_lock: bool,
+    pub language: String,
+    pub language_lock: bool,
+    pub genres: Vec<String>,
+    pub genres_lock: bool,
+    pub tags: Vec<String>,
+    pub tags_lock: bool,
+    pub total_book_count: Option<u32>,
+    pub total_book_count_lock: bool,
+    pub created: String,
+    pub last_modified: String,
+}
+
+/// What a series' books have in common, gathered from their metadata.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookMetadataAggregationDto {
+    pub authors: Vec<AuthorDto>,
+    pub tags: Vec<String>,
+    pub release_date: Option<String>,
+    pub summary: String,
+    pub summary_number: String,
+    pub created: String,
+    pub last_modified: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AuthorDto {
+    pub name: String,
+    pub role: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookDto {
+    pub id: String,
+    pub series_id: String,
+    pub series_title: String,
+    pub library_id: &'static str,
+    pub name: String,
+    pub url: String,
+    /// One-based position in the series.
+    pub number: usize,
+    pub created: String,
+    pub last_modified: String,
+    pub file_last_modified: String,
+    pub size_bytes: u64,
+    pub size: String,
+    pub media: MediaDto,
+    pub metadata: BookMetadataDto,
+    pub read_progress: Option<ReadProgressDto>,
+    pub deleted: bool,
+    pub oneshot: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaDto {
+    pub status: &'static str,
+    pub media_type: String,
+    pub media_profile: &'static str,
+    pub pages_count: usize,
+    pub comment: &'static str,
+    pub epub_divina_compatible: bool,
+    pub epub_is_kepub: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookMetadataDto {
+    pub title: String,
+    pub title_lock: bool,
+    pub summary: String,
+    pub summary_lock: bool,
+    pub number: String,
+    pub number_lock: bool,
+    pub number_sort: f32,
+    pub number_sort_lock: bool,
+    /// `YYYY-MM-DD`.
+    pub release_date: Option<String>,
+    pub release_date_lock: bool,
+    pub authors: Vec<AuthorDto>,
+    pub authors_lock: bool,
+    pub tags: Vec<String>,
+    pub tags_lock: bool,
+    pub isbn: String,
+    pub isbn_lock: bool,
+    pub created: String,
+    pub last_modified: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadProgressDto {
+    /// One-based page.
+    pub page: usize,
+    pub completed: bool,
+    pub read_date: String,
+    pub created: String,
+    pub last_modified: String,
+    pub device_id: String,
+    pub device_name: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageDto {
+    /// One-based page number.
+    pub number: usize,
+    pub file_name: String,
+    pub media_type: String,
+    pub size_bytes: u64,
+}
+
+/// Series progress as Mihon's Komga tracker reads it.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TachiyomiReadProgressDto {
+    pub books_count: usize,
+    pub books_read_count: usize,
+    pub books_unread_count: usize,
+    pub books_in_progress_count: usize,
+    /// `numberSort` of the last book read without gaps from the start.
+    pub last_read_continuous_number_sort: f32,
+    pub max_number_sort: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paginates_zero_based_pages() {
+        let page = PageWrapper::paginate((0..45).collect(), 2, Some(20));
+        assert_eq!(page.content, (40..45).collect::<Vec<_>>());
+        assert_eq!((page.total_pages, page.number_of_elements), (3, 5));
+        assert!(page.last && !page.first);
+    }
+
+    #[test]
+    fn returns_everything_when_unpaged() {
+        let page = PageWrapper::paginate((0..45).collect::<Vec<i32>>(), 3, None);
+        assert_eq!((page.number, page.size, page.number_of_elements), (0, 45, 45));
+        assert!(page.first && page.last);
+    }
+
+    #[test]
+    fn survives_huge_page_numbers() {
+        let page = PageWrapper::paginate((0..45).collect::<Vec<i32>>(), usize::MAX, Some(usize::MAX));
+        assert!(page.empty && page.last);
+    }
+}
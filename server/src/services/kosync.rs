@@ -0,0 +1,83 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use md5::{Digest, Md5};
+
+/// Bytes KOReader hashes at each sampled offset.
+const SAMPLE_SIZE: u64 = 1024;
+
+/// KOReader's partial MD5 of a document, which identifies a file to kosync
+/// regardless of its name. It hashes 1KiB samples from the start and at
+/// 1KiB, 4KiB, 16KiB and so on, stopping at the end of the file.
+pub fn document_hash(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Md5::new();
+    let mut buffer = Vec::with_capacity(SAMPLE_SIZE as usize);
+
+    for i in -1..=10 {
+        // KOReader shifts by -2 first, which LuaJIT wraps around to 0
+        let offset = if i < 0 { 0 } else { SAMPLE_SIZE << (2 * i) };
+        file.seek(SeekFrom::Start(offset))?;
+        buffer.clear();
+        (&mut file).take(SAMPLE_SIZE).read_to_end(&mut buffer)?;
+        if buffer.is_empty() {
+            break;
+        }
+        hasher.update(&buffer);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// The hash KOReader sends when set to match documents by file name.
+pub fn file_name_hash(file_name: &str) -> String {
+    md5_hex(file_name)
+}
+
+/// Kosync clients never send the password itself, only its MD5.
+pub fn key_for_password(password: &str) -> String {
+    md5_hex(password)
+}
+
+fn md5_hex(text: &str) -> String {
+    format!("{:x}", Md5::digest(text.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Writes `len` bytes counting up modulo 251, so every sample differs.
+    fn sample_file(name: &str, len: usize) -> std::path::PathBuf {
+        let path = std::env::temp_dir()
+            .join(format!("kosync-{}-{}", name, std::process::id()));
+        let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn hashes_samples_at_growing_offsets() {
+        // Samples at 0, 1KiB, 4KiB and 16KiB; 64KiB is past the end
+        let path = sample_file("large", 20_000);
+        let hash = document_hash(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(hash.unwrap(), "04a4d10f5e5583d7173cce0782769cb1");
+    }
+
+    #[test]
+    fn hashes_short_final_samples() {
+        let path = sample_file("short", 1_500);
+        let hash = document_hash(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(hash.unwrap(), "a5fcf6debbdcc01aaa4dc9dfb5c8ae5a");
+    }
+
+    #[test]
+    fn hashes_names_and_passwords_with_plain_md5() {
+        assert_eq!(file_name_hash("Batman 001.cbz"), "11c9c0c98bdadf6b80abc9bbf4b7cdb6");
+        assert_eq!(key_for_password("password"), "5f4dcc3b5aa765d61d8327deb882cf99");
+    }
+}
@@ -0,0 +1,148 @@
+use serde::Deserialize;
+
+use super::{non_empty, parse_number};
+use crate::models::error::ComicError;
+use crate::models::metadata::ComicMetadata;
+
+/// Raw CoMet document (http://www.denvog.com/comet/). Creator and genre
+/// elements repeat once per value instead of being comma separated.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct CoMet {
+    title: Option<String>,
+    description: Option<String>,
+    series: Option<String>,
+    issue: Option<String>,
+    volume: Option<String>,
+    publisher: Option<String>,
+    date: Option<String>,
+    genre: Vec<String>,
+    character: Vec<String>,
+    language: Option<String>,
+    rating: Option<String>,
+    pages: Option<String>,
+    writer: Vec<String>,
+    penciller: Vec<String>,
+    inker: Vec<String>,
+    colorist: Vec<String>,
+    letterer: Vec<String>,
+    cover_designer: Vec<String>,
+    editor: Vec<String>,
+    reading_direction: Option<String>,
+}
+
+/// Cheap check used to pick CoMet files out of arbitrary `.xml` entries.
+pub fn is_comet(xml: &[u8]) -> bool {
+    String::from_utf8_lossy(xml).contains("<comet")
+}
+
+pub fn parse_comet(xml: &[u8]) -> Result<ComicMetadata, ComicError> {
+    let xml = String::from_utf8_lossy(xml);
+    let comet: CoMet = quick_xml::de::from_str(xml.trim_start_matches('\u{feff}'))
+        .map_err(|e| ComicError::InvalidMetadata(e.to_string()))?;
+
+    // Dates are ISO 8601, possibly truncated to year-month or year
+    let date = non_empty(comet.date).unwrap_or_default();
+    let mut date_parts = date.split('-').map(|part| Some(part.to_string()));
+
+    Ok(ComicMetadata {
+        title: non_empty(comet.title),
+        series: non_empty(comet.series),
+        number: non_empty(comet.issue),
+        volume: parse_number(comet.volume),
+        summary: non_empty(comet.description),
+        year: date_parts.next().and_then(parse_number),
+        month: date_parts.next().and_then(parse_number).filter(|month| (1..=12).contains(month)),
+        day: date_parts.next().and_then(parse_number).filter(|day| (1..=31).contains(day)),
+        writers: clean_list(comet.writer),
+        pencillers: clean_list(comet.penciller),
+        inkers: clean_list(comet.inker),
+        colorists: clean_list(comet.colorist),
+        letterers: clean_list(comet.letterer),
+        cover_artists: clean_list(comet.cover_designer),
+        editors: clean_list(comet.editor),
+        publisher: non_empty(comet.publisher),
+        genres: clean_list(comet.genre),
+        characters: clean_list(comet.character),
+        page_count: parse_number(comet.pages),
+        language: non_empty(comet.language),
+        right_to_left: match non_empty(comet.reading_direction).as_deref() {
+            Some("rtl") => Some(true),
+            Some("ltr") => Some(false),
+            _ => None,
+        },
+        age_rating: non_empty(comet.rating),
+        ..Default::default()
+    })
+}
+
+fn clean_list(values: Vec<String>) -> Vec<String> {
+    values.into_iter()
+        .filter_map(|value| non_empty(Some(value)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_comet_document() {
+        let xml = br#"<?xml version="1.0"?>
+<comet xmlns="http://www.denvog.com/comet/" xsi:schemaLocation="http://www.denvog.com/comet/ comet.xsd">
+  <title>Chapter One</title>
+  <series>Saga</series>
+  <issue>1</issue>
+  <volume>1</volume>
+  <description>Two soldiers fall in love.</description>
+  <publisher>Image</publisher>
+  <date>2012-03</date>
+  <genre>Science Fiction</genre>
+  <genre>Fantasy</genre>
+  <character>Alana</character>
+  <character>Marko</character>
+  <language>en</language>
+  <rating>Mature</rating>
+  <pages>44</pages>
+  <writer>Brian K. Vaughan</writer>
+  <penciller>Fiona Staples</penciller>
+  <coverDesigner>Fiona Staples</coverDesigner>
+  <editor> </editor>
+  <readingDirection>ltr</readingDirection>
+</comet>"#;
+
+        assert!(is_comet(xml));
+        let metadata = parse_comet(xml).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Chapter One"));
+        assert_eq!(metadata.series.as_deref(), Some("Saga"));
+        assert_eq!(metadata.number.as_deref(), Some("1"));
+        assert_eq!(metadata.volume, Some(1));
+        assert_eq!(metadata.summary.as_deref(), Some("Two soldiers fall in love."));
+        assert_eq!(metadata.publisher.as_deref(), Some("Image"));
+        assert_eq!((metadata.year, metadata.month, metadata.day), (Some(2012), Some(3), None));
+        assert_eq!(metadata.genres, ["Science Fiction", "Fantasy"]);
+        assert_eq!(metadata.characters, ["Alana", "Marko"]);
+        assert_eq!(metadata.language.as_deref(), Some("en"));
+        assert_eq!(metadata.age_rating.as_deref(), Some("Mature"));
+        assert_eq!(metadata.page_count, Some(44));
+        assert_eq!(metadata.writers, ["Brian K. Vaughan"]);
+        assert_eq!(metadata.pencillers, ["Fiona Staples"]);
+        assert_eq!(metadata.cover_artists, ["Fiona Staples"]);
+        assert!(metadata.editors.is_empty());
+        assert_eq!(metadata.right_to_left, Some(false));
+    }
+
+    #[test]
+    fn drops_invalid_dates() {
+        let metadata = parse_comet(b"<comet><date>2012-13-40</date></comet>").unwrap();
+        assert_eq!((metadata.year, metadata.month, metadata.day), (Some(2012), None, None));
+
+        let metadata = parse_comet(b"<comet><date>soon</date></comet>").unwrap();
+        assert_eq!(metadata.year, None);
+    }
+
+    #[test]
+    fn ignores_other_xml_files() {
+        assert!(!is_comet(b"<ComicInfo><Title>Saga</Title></ComicInfo>"));
+    }
+}
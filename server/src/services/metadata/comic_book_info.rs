@@ -0,0 +1,151 @@
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{non_empty, parse_number};
+use crate::models::error::ComicError;
+use crate::models::metadata::ComicMetadata;
+
+const COMIC_BOOK_INFO_KEY: &str = "ComicBookInfo/1.0";
+
+/// ComicBookInfo payload as written by ComicBookLover and ComicTagger.
+/// Numeric fields are kept as raw JSON values because taggers disagree on
+/// whether they are numbers or strings.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct ComicBookInfo {
+    series: Option<String>,
+    title: Option<String>,
+    publisher: Option<String>,
+    publication_month: Option<Value>,
+    publication_year: Option<Value>,
+    issue: Option<Value>,
+    number_of_issues: Option<Value>,
+    volume: Option<Value>,
+    genre: Option<String>,
+    language: Option<String>,
+    credits: Vec<Credit>,
+    tags: Vec<String>,
+    comments: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Credit {
+    person: String,
+    role: String,
+}
+
+/// Cheap check used to tell ComicBookInfo apart from other ZIP comments.
+pub fn is_comic_book_info(comment: &str) -> bool {
+    comment.contains(COMIC_BOOK_INFO_KEY)
+}
+
+pub fn parse_comic_book_info(comment: &str) -> Result<ComicMetadata, ComicError> {
+    let mut document: Value = serde_json::from_str(comment)
+        .map_err(|e| ComicError::InvalidMetadata(e.to_string()))?;
+    let payload = document.get_mut(COMIC_BOOK_INFO_KEY)
+        .map(Value::take)
+        .ok_or_else(|| ComicError::InvalidMetadata(format!("missing {}", COMIC_BOOK_INFO_KEY)))?;
+    let info: ComicBookInfo = serde_json::from_value(payload)
+        .map_err(|e| ComicError::InvalidMetadata(e.to_string()))?;
+
+    let mut metadata = ComicMetadata {
+        title: non_empty(info.title),
+        series: non_empty(info.series),
+        number: non_empty(value_to_string(info.issue)),
+        count: parse_number(value_to_string(info.number_of_issues)),
+        volume: parse_number(value_to_string(info.volume)),
+        summary: non_empty(info.comments),
+        year: parse_number(value_to_string(info.publication_year)),
+        month: parse_number(value_to_string(info.publication_month))
+            .filter(|month| (1..=12).contains(month)),
+        publisher: non_empty(info.publisher),
+        genres: non_empty(info.genre).into_iter().collect(),
+        tags: info.tags.into_iter().filter_map(|tag| non_empty(Some(tag))).collect(),
+        language: non_empty(info.language),
+        ..Default::default()
+    };
+
+    for credit in info.credits {
+        let Some(person) = non_empty(Some(credit.person)) else { continue };
+        let credits = match credit.role.trim().to_lowercase().as_str() {
+            "writer" | "scripter" | "plotter" => &mut metadata.writers,
+            "penciller" | "penciler" | "artist" | "pencils" => &mut metadata.pencillers,
+            "inker" | "inks" => &mut metadata.inkers,
+            "colorist" | "colourist" | "colors" => &mut metadata.colorists,
+            "letterer" | "letters" => &mut metadata.letterers,
+            "cover" | "cover artist" | "covers" => &mut metadata.cover_artists,
+            "editor" => &mut metadata.editors,
+            "translator" => &mut metadata.translators,
+            _ => continue,
+        };
+        credits.push(person);
+    }
+
+    Ok(metadata)
+}
+
+fn value_to_string(value: Option<Value>) -> Option<String> {
+    match value? {
+        Value::String(value) => Some(value),
+        Value::Number(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_comic_book_info_comment() {
+        let comment = r#"{
+  "appID": "ComicTagger/1.0",
+  "lastModified": "2012-06-01 12:00:00",
+  "ComicBookInfo/1.0": {
+    "series": "Saga",
+    "title": "Chapter One",
+    "publisher": "Image",
+    "publicationMonth": 3,
+    "publicationYear": "2012",
+    "issue": 1,
+    "numberOfIssues": "54",
+    "volume": -1,
+    "genre": "Science Fiction",
+    "language": "en",
+    "credits": [
+      {"person": "Brian K. Vaughan", "role": "Writer"},
+      {"person": "Fiona Staples", "role": "Artist", "primary": true},
+      {"person": "Fonografiks", "role": "Letterer"},
+      {"person": "Someone", "role": "Catering"}
+    ],
+    "tags": ["space", " "],
+    "comments": "Two soldiers fall in love."
+  }
+}"#;
+
+        assert!(is_comic_book_info(comment));
+        let metadata = parse_comic_book_info(comment).unwrap();
+        assert_eq!(metadata.series.as_deref(), Some("Saga"));
+        assert_eq!(metadata.title.as_deref(), Some("Chapter One"));
+        assert_eq!(metadata.publisher.as_deref(), Some("Image"));
+        assert_eq!((metadata.year, metadata.month), (Some(2012), Some(3)));
+        assert_eq!(metadata.number.as_deref(), Some("1"));
+        assert_eq!(metadata.count, Some(54));
+        assert_eq!(metadata.volume, None);
+        assert_eq!(metadata.genres, ["Science Fiction"]);
+        assert_eq!(metadata.language.as_deref(), Some("en"));
+        assert_eq!(metadata.writers, ["Brian K. Vaughan"]);
+        assert_eq!(metadata.pencillers, ["Fiona Staples"]);
+        assert_eq!(metadata.letterers, ["Fonografiks"]);
+        assert_eq!(metadata.tags, ["space"]);
+        assert_eq!(metadata.summary.as_deref(), Some("Two soldiers fall in love."));
+    }
+
+    #[test]
+    fn rejects_comments_without_the_payload() {
+        assert!(!is_comic_book_info("Created by some zip tool"));
+        let result = parse_comic_book_info(r#"{"appID": "ComicTagger/1.0"}"#);
+        assert!(matches!(result, Err(ComicError::InvalidMetadata(_))));
+    }
+}
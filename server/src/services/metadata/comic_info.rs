@@ -0,0 +1,255 @@
+use serde::Deserialize;
+
+use super::{non_empty, parse_number, split_list};
+use crate::models::error::ComicError;
+use crate::models::metadata::{ComicMetadata, PageMetadata};
+
+/// Raw `ComicInfo.xml` document as written by ComicRack and compatible
+/// taggers (Anansi Project schema v2.0). Every field is read as text so a
+/// single malformed value does not reject the whole file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+struct ComicInfo {
+    title: Option<String>,
+    series: Option<String>,
+    number: Option<String>,
+    count: Option<String>,
+    volume: Option<String>,
+    summary: Option<String>,
+    year: Option<String>,
+    month: Option<String>,
+    day: Option<String>,
+    writer: Option<String>,
+    penciller: Option<String>,
+    inker: Option<String>,
+    colorist: Option<String>,
+    letterer: Option<String>,
+    cover_artist: Option<String>,
+    editor: Option<String>,
+    translator: Option<String>,
+    publisher: Option<String>,
+    imprint: Option<String>,
+    genre: Option<String>,
+    tags: Option<String>,
+    characters: Option<String>,
+    story_arc: Option<String>,
+    page_count: Option<String>,
+    #[serde(rename = "LanguageISO")]
+    language_iso: Option<String>,
+    manga: Option<String>,
+    age_rating: Option<String>,
+    web: Option<String>,
+    pages: Option<Pages>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Pages {
+    #[serde(rename = "Page")]
+    page: Vec<PageInfo>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct PageInfo {
+    #[serde(rename = "@Image")]
+    image: Option<String>,
+    #[serde(rename = "@Type")]
+    page_type: Option<String>,
+}
+
+pub fn parse_comic_info(xml: &[u8]) -> Result<ComicMetadata, ComicError> {
+    let xml = String::from_utf8_lossy(xml);
+    let info: ComicInfo = quick_xml::de::from_str(xml.trim_start_matches('\u{feff}'))
+        .map_err(|e| ComicError::InvalidMetadata(e.to_string()))?;
+
+    let manga = non_empty(info.manga);
+
+    Ok(ComicMetadata {
+        title: non_empty(info.title),
+        series: non_empty(info.series),
+        number: non_empty(info.number),
+        count: parse_number(info.count),
+        volume: parse_number(info.volume),
+        summary: non_empty(info.summary),
+        year: parse_number(info.year),
+        month: parse_number(info.month).filter(|month| (1..=12).contains(month)),
+        day: parse_number(info.day).filter(|day| (1..=31).contains(day)),
+        writers: split_list(info.writer),
+        pencillers: split_list(info.penciller),
+        inkers: split_list(info.inker),
+        colorists: split_list(info.colorist),
+        letterers: split_list(info.letterer),
+        cover_artists: split_list(info.cover_artist),
+        editors: split_list(info.editor),
+        translators: split_list(info.translator),
+        publisher: non_empty(info.publisher),
+        imprint: non_empty(info.imprint),
+        genres: split_list(info.genre),
+        tags: split_list(info.tags),
+        characters: split_list(info.characters),
+        story_arc: non_empty(info.story_arc),
+        page_count: parse_number(info.page_count),
+        language: non_empty(info.language_iso),
+        manga: match manga.as_deref() {
+            Some("Yes") | Some("YesAndRightToLeft") => Some(true),
+            Some("No") => Some(false),
+            _ => None,
+        },
+        right_to_left: match manga.as_deref() {
+            Some("YesAndRightToLeft") => Some(true),
+            Some("Yes") | Some("No") => Some(false),
+            _ => None,
+        },
+        age_rating: non_empty(info.age_rating).filter(|rating| rating != "Unknown"),
+        web: non_empty(info.web),
+        pages: info.pages.map(|pages| pages.page).unwrap_or_default()
+            .into_iter()
+            .filter_map(|page| Some(PageMetadata {
+                image: parse_number(page.image)?,
+                page_type: non_empty(page.page_type),
+            }))
+            .collect(),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_full_document() {
+        let xml = br#"<?xml version="1.0" encoding="utf-8"?>
+<ComicInfo xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+  <Title>The Court of Owls</Title>
+  <Series>Batman</Series>
+  <Number>1</Number>
+  <Count>52</Count>
+  <Volume>2011</Volume>
+  <Summary>Gotham has a secret.</Summary>
+  <Year>2011</Year>
+  <Month>9</Month>
+  <Day>21</Day>
+  <Writer>Scott Snyder</Writer>
+  <Penciller>Greg Capullo</Penciller>
+  <Inker>Jonathan Glapion</Inker>
+  <Colorist>FCO Plascencia</Colorist>
+  <Letterer>Richard Starkings, Jimmy Betancourt</Letterer>
+  <CoverArtist>Greg Capullo</CoverArtist>
+  <Editor>Mike Marts</Editor>
+  <Publisher>DC Comics</Publisher>
+  <Imprint>DC</Imprint>
+  <Genre>Superhero, Crime</Genre>
+  <Tags>new 52</Tags>
+  <Characters>Batman, Dick Grayson</Characters>
+  <StoryArc>Night of the Owls</StoryArc>
+  <PageCount>24</PageCount>
+  <LanguageISO>en</LanguageISO>
+  <Manga>No</Manga>
+  <AgeRating>Teen</AgeRating>
+  <Web>https://example.com/batman-1</Web>
+</ComicInfo>"#;
+
+        let metadata = parse_comic_info(xml).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("The Court of Owls"));
+        assert_eq!(metadata.series.as_deref(), Some("Batman"));
+        assert_eq!(metadata.number.as_deref(), Some("1"));
+        assert_eq!(metadata.count, Some(52));
+        assert_eq!(metadata.volume, Some(2011));
+        assert_eq!(metadata.summary.as_deref(), Some("Gotham has a secret."));
+        assert_eq!((metadata.year, metadata.month, metadata.day), (Some(2011), Some(9), Some(21)));
+        assert_eq!(metadata.writers, ["Scott Snyder"]);
+        assert_eq!(metadata.pencillers, ["Greg Capullo"]);
+        assert_eq!(metadata.inkers, ["Jonathan Glapion"]);
+        assert_eq!(metadata.colorists, ["FCO Plascencia"]);
+        assert_eq!(metadata.letterers, ["Richard Starkings", "Jimmy Betancourt"]);
+        assert_eq!(metadata.cover_artists, ["Greg Capullo"]);
+        assert_eq!(metadata.editors, ["Mike Marts"]);
+        assert_eq!(metadata.publisher.as_deref(), Some("DC Comics"));
+        assert_eq!(metadata.imprint.as_deref(), Some("DC"));
+        assert_eq!(metadata.genres, ["Superhero", "Crime"]);
+        assert_eq!(metadata.tags, ["new 52"]);
+        assert_eq!(metadata.characters, ["Batman", "Dick Grayson"]);
+        assert_eq!(metadata.story_arc.as_deref(), Some("Night of the Owls"));
+        assert_eq!(metadata.page_count, Some(24));
+        assert_eq!(metadata.language.as_deref(), Some("en"));
+        assert_eq!((metadata.manga, metadata.right_to_left), (Some(false), Some(false)));
+        assert_eq!(metadata.age_rating.as_deref(), Some("Teen"));
+        assert_eq!(metadata.web.as_deref(), Some("https://example.com/batman-1"));
+        assert!(metadata.pages.is_empty());
+    }
+
+    #[test]
+    fn treats_missing_and_blank_fields_as_unset() {
+        let xml = b"\xef\xbb\xbf<ComicInfo>
+  <Title>   </Title>
+  <Series>Saga</Series>
+  <Writer> , Brian K. Vaughan,, </Writer>
+  <Penciller/>
+  <AgeRating>Unknown</AgeRating>
+  <Manga></Manga>
+</ComicInfo>";
+
+        let metadata = parse_comic_info(xml).unwrap();
+        assert_eq!(metadata.title, None);
+        assert_eq!(metadata.series.as_deref(), Some("Saga"));
+        assert_eq!(metadata.writers, ["Brian K. Vaughan"]);
+        assert!(metadata.pencillers.is_empty());
+        assert_eq!(metadata.age_rating, None);
+        assert_eq!((metadata.manga, metadata.right_to_left), (None, None));
+        assert_eq!((metadata.number, metadata.year, metadata.page_count), (None, None, None));
+    }
+
+    #[test]
+    fn drops_invalid_numbers_without_rejecting_the_file() {
+        let xml = b"<ComicInfo>
+  <Series>Saga</Series>
+  <Count>-1</Count>
+  <Volume>one</Volume>
+  <Year>2012</Year>
+  <Month>13</Month>
+  <Day>0</Day>
+  <PageCount>lots</PageCount>
+</ComicInfo>";
+
+        let metadata = parse_comic_info(xml).unwrap();
+        assert_eq!(metadata.series.as_deref(), Some("Saga"));
+        assert_eq!(metadata.count, None);
+        assert_eq!(metadata.volume, None);
+        assert_eq!((metadata.year, metadata.month, metadata.day), (Some(2012), None, None));
+        assert_eq!(metadata.page_count, None);
+    }
+
+    #[test]
+    fn reads_manga_reading_direction() {
+        let metadata = parse_comic_info(b"<ComicInfo><Manga>YesAndRightToLeft</Manga></ComicInfo>").unwrap();
+        assert_eq!((metadata.manga, metadata.right_to_left), (Some(true), Some(true)));
+
+        let metadata = parse_comic_info(b"<ComicInfo><Manga>Yes</Manga></ComicInfo>").unwrap();
+        assert_eq!((metadata.manga, metadata.right_to_left), (Some(true), Some(false)));
+    }
+
+    #[test]
+    fn reads_page_entries() {
+        let xml = br#"<ComicInfo>
+  <Pages>
+    <Page Image="0" Type="InnerCover" />
+    <Page Image="1" />
+    <Page Image="2" Type="FrontCover" ImageSize="123456" />
+    <Page Image="x" Type="Story" />
+    <Page Type="BackCover" />
+  </Pages>
+</ComicInfo>"#;
+
+        let pages = parse_comic_info(xml).unwrap().pages;
+        let pages: Vec<_> = pages.iter().map(|page| (page.image, page.page_type.as_deref())).collect();
+        assert_eq!(pages, [(0, Some("InnerCover")), (1, None), (2, Some("FrontCover"))]);
+    }
+
+    #[test]
+    fn rejects_malformed_xml() {
+        let result = parse_comic_info(b"<ComicInfo><Title>Batman</Series></ComicInfo>");
+        assert!(matches!(result, Err(ComicError::InvalidMetadata(_))));
+    }
+}
@@ -0,0 +1,263 @@
+mod comet;
+mod comic_book_info;
+mod comic_info;
+
+use std::path::Path;
+
+use crate::models::error::ComicError;
+use crate::models::metadata::{ComicMetadata, MetadataSource};
+use crate::services::archive::{ArchiveEntry, ArchiveReader};
+
+pub use comet::parse_comet;
+pub use comic_book_info::parse_comic_book_info;
+pub use comic_info::parse_comic_info;
+
+const COMIC_INFO_FILE: &str = "comicinfo.xml";
+
+/// Reads every metadata format the archive carries and merges them, with
+/// ComicInfo.xml taking precedence over CoMet, and CoMet over
+/// ComicBookInfo. Unreadable or malformed metadata is logged and treated as
+/// absent rather than failing the scan.
+pub fn read_metadata(
+    archive: &mut dyn ArchiveReader,
+    entries: &[ArchiveEntry],
+) -> Option<ComicMetadata> {
+    let mut found = Vec::new();
+
+    let files = entries.iter().filter(|entry| !entry.is_dir);
+    let comic_info = files.clone()
+        .find(|entry| file_name(&entry.name).eq_ignore_ascii_case(COMIC_INFO_FILE));
+    if let Some(entry) = comic_info {
+        if let Some(metadata) = log_failure(&entry.name, archive.read_entry(&entry.name)
+            .and_then(|data| parse_comic_info(&data))) {
+            found.push((MetadataSource::ComicInfo, metadata));
+        }
+    }
+
+    let xml_files = files
+        .filter(|entry| entry.name.to_lowercase().ends_with(".xml"))
+        .filter(|entry| !file_name(&entry.name).eq_ignore_ascii_case(COMIC_INFO_FILE));
+    for entry in xml_files {
+        let data = match archive.read_entry(&entry.name) {
+            Ok(data) if comet::is_comet(&data) => data,
+            _ => continue,
+        };
+        if let Some(metadata) = log_failure(&entry.name, parse_comet(&data)) {
+            found.push((MetadataSource::CoMet, metadata));
+            break;
+        }
+    }
+
+    if let Ok(Some(comment)) = archive.comment() {
+        if comic_book_info::is_comic_book_info(&comment) {
+            if let Some(metadata) = log_failure("archive comment", parse_comic_book_info(&comment)) {
+                found.push((MetadataSource::ComicBookInfo, metadata));
+            }
+        }
+    }
+
+    if found.is_empty() {
+        return None;
+    }
+    Some(merge(found))
+}
+
+macro_rules! merge_fields {
+    ($merged:ident, $candidate:ident, $source:expr, $($field:ident),+ $(,)?) => {
+        $(
+            if !$merged.$field.is_set() && $candidate.$field.is_set() {
+                $merged.$field = $candidate.$field;
+                $merged.sources.insert(stringify!($field).to_string(), $source);
+            }
+        )+
+    };
+}
+
+/// Field-by-field merge: each field is taken from the first source, in the
+/// given order, that sets it.
+fn merge(sources: Vec<(MetadataSource, ComicMetadata)>) -> ComicMetadata {
+    let mut merged = ComicMetadata::default();
+
+    for (source, candidate) in sources {
+        merge_fields!(merged, candidate, source,
+            title, series, number, count, volume, summary, year, month, day,
+            writers, pencillers, inkers, colorists, letterers, cover_artists,
+            editors, translators, publisher, imprint, genres, tags, characters,
+            story_arc, page_count, language, manga, right_to_left, age_rating, web,
+            pages,
+        );
+    }
+
+    merged
+}
+
+trait IsSet {
+    fn is_set(&self) -> bool;
+}
+
+impl<T> IsSet for Option<T> {
+    fn is_set(&self) -> bool {
+        self.is_some()
+    }
+}
+
+impl<T> IsSet for Vec<T> {
+    fn is_set(&self) -> bool {
+        !self.is_empty()
+    }
+}
+
+fn log_failure(name: &str, result: Result<ComicMetadata, ComicError>) -> Option<ComicMetadata> {
+    match result {
+        Ok(metadata) => Some(metadata),
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", name, e);
+            None
+        }
+    }
+}
+
+fn file_name(entry_name: &str) -> &str {
+    Path::new(entry_name)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(entry_name)
+}
+
+/// Splits a comma separated credit or tag list, dropping empty items.
+fn split_list(value: Option<String>) -> Vec<String> {
+    value.map(|value| {
+        value.split(',')
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect()
+    })
+        .unwrap_or_default()
+}
+
+/// Trims a text field, treating blank values as missing.
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Parses a numeric field. Taggers use `-1` and blanks for "unset", so
+/// anything negative or unparseable is dropped.
+fn parse_number<T: std::str::FromStr + Default + PartialOrd>(value: Option<String>) -> Option<T> {
+    non_empty(value)?
+        .parse::<T>()
+        .ok()
+        .filter(|number| *number >= T::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// In-memory archive holding named entries and an optional comment.
+    struct FakeArchive {
+        files: Vec<(&'static str, &'static str)>,
+        comment: Option<&'static str>,
+    }
+
+    impl FakeArchive {
+        fn entries(&self) -> Vec<ArchiveEntry> {
+            self.files.iter()
+                .map(|(name, data)| ArchiveEntry { name: name.to_string(), size: data.len() as u64, is_dir: false })
+                .collect()
+        }
+    }
+
+    impl ArchiveReader for FakeArchive {
+        fn entries(&mut self) -> Result<Vec<ArchiveEntry>, ComicError> {
+            Ok(FakeArchive::entries(self))
+        }
+
+        fn read_entry(&mut self, name: &str) -> Result<Vec<u8>, ComicError> {
+            self.files.iter()
+                .find(|(entry, _)| *entry == name)
+                .map(|(_, data)| data.as_bytes().to_vec())
+                .ok_or(ComicError::EntryNotFound)
+        }
+
+        fn comment(&mut self) -> Result<Option<String>, ComicError> {
+            Ok(self.comment.map(String::from))
+        }
+    }
+
+    const COMIC_INFO: &str = "<ComicInfo>
+  <Title>From ComicInfo</Title>
+  <Writer>Brian K. Vaughan</Writer>
+</ComicInfo>";
+
+    const COMET: &str = "<comet>
+  <title>From CoMet</title>
+  <series>Saga</series>
+  <writer>Somebody Else</writer>
+  <date>2012-03</date>
+</comet>";
+
+    const COMIC_BOOK_INFO: &str = r#"{"ComicBookInfo/1.0": {
+  "title": "From ComicBookInfo",
+  "series": "Not Saga",
+  "publicationYear": 1999,
+  "publisher": "Image"
+}}"#;
+
+    fn read(mut archive: FakeArchive) -> Option<ComicMetadata> {
+        let entries = archive.entries();
+        read_metadata(&mut archive, &entries)
+    }
+
+    #[test]
+    fn prefers_comic_info_field_by_field() {
+        let metadata = read(FakeArchive {
+            files: vec![("page1.png", ""), ("comet.xml", COMET), ("Extras/ComicInfo.XML", COMIC_INFO)],
+            comment: Some(COMIC_BOOK_INFO),
+        }).unwrap();
+
+        assert_eq!(metadata.title.as_deref(), Some("From ComicInfo"));
+        assert_eq!(metadata.writers, ["Brian K. Vaughan"]);
+        assert_eq!(metadata.series.as_deref(), Some("Saga"));
+        assert_eq!((metadata.year, metadata.month), (Some(2012), Some(3)));
+        assert_eq!(metadata.publisher.as_deref(), Some("Image"));
+
+        assert_eq!(metadata.sources["title"], MetadataSource::ComicInfo);
+        assert_eq!(metadata.sources["writers"], MetadataSource::ComicInfo);
+        assert_eq!(metadata.sources["series"], MetadataSource::CoMet);
+        assert_eq!(metadata.sources["year"], MetadataSource::CoMet);
+        assert_eq!(metadata.sources["publisher"], MetadataSource::ComicBookInfo);
+        assert!(!metadata.sources.contains_key("summary"));
+    }
+
+    #[test]
+    fn fills_gaps_from_partial_sources() {
+        let metadata = read(FakeArchive {
+            files: vec![("notes.xml", "<notes/>"), ("comet.xml", COMET)],
+            comment: Some(COMIC_BOOK_INFO),
+        }).unwrap();
+
+        assert_eq!(metadata.title.as_deref(), Some("From CoMet"));
+        assert_eq!(metadata.writers, ["Somebody Else"]);
+        assert_eq!(metadata.publisher.as_deref(), Some("Image"));
+        assert_eq!(metadata.sources["title"], MetadataSource::CoMet);
+        assert_eq!(metadata.sources["publisher"], MetadataSource::ComicBookInfo);
+    }
+
+    #[test]
+    fn skips_malformed_sources() {
+        let metadata = read(FakeArchive {
+            files: vec![("ComicInfo.xml", "<ComicInfo><Title>broken</Series>")],
+            comment: Some(COMIC_BOOK_INFO),
+        }).unwrap();
+
+        assert_eq!(metadata.title.as_deref(), Some("From ComicBookInfo"));
+        assert_eq!(metadata.sources["title"], MetadataSource::ComicBookInfo);
+    }
+
+    #[test]
+    fn returns_nothing_without_metadata() {
+        let archive = FakeArchive { files: vec![("page1.png", "")], comment: Some("just a zip comment") };
+        assert!(read(archive).is_none());
+    }
+}
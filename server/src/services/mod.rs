@@ -1,2 +1,15 @@
+pub mod archive;
 pub mod auth;
-pub mod comic_service;
\ No newline at end of file
+pub mod catalog;
+pub mod comic_service;
+pub mod cover_cache;
+pub mod database;
+pub mod komga;
+pub mod kosync;
+pub mod metadata;
+pub mod opds;
+pub mod opds2;
+pub mod pages;
+pub mod progress;
+pub mod thumbnails;
+pub mod users;
\ No newline at end of file
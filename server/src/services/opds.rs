@@ -0,0 +1,287 @@
+use std::fmt::Write;
+
+use quick_xml::escape::escape;
+
+pub const NAVIGATION_TYPE: &str = "application/atom+xml;profile=opds-catalog;kind=navigation";
+pub const ACQUISITION_TYPE: &str = "application/atom+xml;profile=opds-catalog;kind=acquisition";
+pub const OPENSEARCH_TYPE: &str = "application/opensearchdescription+xml";
+
+pub const REL_IMAGE: &str = "http://opds-spec.org/image";
+pub const REL_THUMBNAIL: &str = "http://opds-spec.org/image/thumbnail";
+pub const REL_SUBSECTION: &str = "subsection";
+/// OPDS Page Streaming Extension link to single pages.
+pub const REL_PSE_STREAM: &str = "http://vaemendis.net/opds-pse/stream";
+
+const CATALOG_TITLE: &str = "Comic Reader";
+
+/// An Atom link. `kind` is the link's media type.
+pub struct Link {
+    pub rel: &'static str,
+    pub href: String,
+    pub kind: String,
+    pub title: Option<String>,
+    /// Extension attributes such as `pse:count`.
+    pub attributes: Vec<(&'static str, String)>,
+}
+
+impl Link {
+    pub fn new(rel: &'static str, href: impl Into<String>, kind: impl Into<String>) -> Self {
+        Link { rel, href: href.into(), kind: kind.into(), title: None, attributes: vec![] }
+    }
+
+    pub fn titled(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn with_attribute(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.attributes.push((name, value.into()));
+        self
+    }
+}
+
+/// A navigation or catalog entry.
+#[derive(Default)]
+pub struct Entry {
+    pub id: String,
+    pub title: String,
+    pub updated: i64,
+    pub authors: Vec<String>,
+    pub summary: Option<String>,
+    /// Shown by clients under navigation entries.
+    pub content: Option<String>,
+    pub publisher: Option<String>,
+    /// Publication date, `YYYY`, `YYYY-MM` or `YYYY-MM-DD`.
+    pub issued: Option<String>,
+    pub language: Option<String>,
+    pub links: Vec<Link>,
+}
+
+/// Where a page of results sits in the whole result set.
+pub struct Pagination {
+    pub total: usize,
+    pub per_page: usize,
+    /// One-based index of the first entry on this page.
+    pub start_index: usize,
+}
+
+/// An OPDS 1.2 catalog feed.
+pub struct Feed {
+    pub id: String,
+    pub title: String,
+    pub updated: i64,
+    pub links: Vec<Link>,
+    pub entries: Vec<Entry>,
+    pub pagination: Option<Pagination>,
+}
+
+impl Feed {
+    pub fn new(id: impl Into<String>, title: impl Into<String>, updated: i64) -> Self {
+        Feed {
+            id: id.into(),
+            title: title.into(),
+            updated,
+            links: vec![],
+            entries: vec![],
+            pagination: None,
+        }
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::new();
+        xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(
+            "<feed xmlns=\"http://www.w3.org/2005/Atom\" \
+             xmlns:dc=\"http://purl.org/dc/terms/\" \
+             xmlns:opds=\"http://opds-spec.org/2010/catalog\" \
+             xmlns:opensearch=\"http://a9.com/-/spec/opensearch/1.1/\" \
+             xmlns:pse=\"http://vaemendis.net/opds-pse/ns\">\n",
+        );
+        write_element(&mut xml, "id", &self.id);
+        write_element(&mut xml, "title", &self.title);
+        write_element(&mut xml, "updated", &format_timestamp(self.updated));
+        let _ = writeln!(xml, "<author><name>{}</name></author>", CATALOG_TITLE);
+
+        if let Some(pagination) = &self.pagination {
+            write_element(&mut xml, "opensearch:totalResults", &pagination.total.to_string());
+            write_element(&mut xml, "opensearch:itemsPerPage", &pagination.per_page.to_string());
+            write_element(&mut xml, "opensearch:startIndex", &pagination.start_index.to_string());
+        }
+
+        for link in &self.links {
+            write_link(&mut xml, link);
+        }
+        for entry in &self.entries {
+            write_entry(&mut xml, entry);
+        }
+        xml.push_str("</feed>\n");
+        xml
+    }
+}
+
+/// The OpenSearch description pointing clients at the search feed.
+pub fn opensearch_description(search_template: &str) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<OpenSearchDescription xmlns=\"http://a9.com/-/spec/opensearch/1.1/\">\n");
+    write_element(&mut xml, "ShortName", CATALOG_TITLE);
+    write_element(&mut xml, "Description", "Search comics by title, series or folder");
+    write_element(&mut xml, "InputEncoding", "UTF-8");
+    write_element(&mut xml, "OutputEncoding", "UTF-8");
+    let _ = writeln!(
+        xml,
+        "<Url type=\"{}\" template=\"{}\"/>",
+        escape(ACQUISITION_TYPE),
+        escape(search_template),
+    );
+    xml.push_str("</OpenSearchDescription>\n");
+    xml
+}
+
+fn write_entry(xml: &mut String, entry: &Entry) {
+    xml.push_str("<entry>\n");
+    write_element(xml, "title", &entry.title);
+    write_element(xml, "id", &entry.id);
+    write_element(xml, "updated", &format_timestamp(entry.updated));
+    for author in &entry.authors {
+        let _ = writeln!(xml, "<author><name>{}</name></author>", escape(author.as_str()));
+    }
+    if let Some(publisher) = &entry.publisher {
+        write_element(xml, "dc:publisher", publisher);
+    }
+    if let Some(issued) = &entry.issued {
+        write_element(xml, "dc:issued", issued);
+    }
+    if let Some(language) = &entry.language {
+        write_element(xml, "dc:language", language);
+    }
+    if let Some(summary) = &entry.summary {
+        write_element(xml, "summary", summary);
+    }
+    if let Some(content) = &entry.content {
+        let _ = writeln!(xml, "<content type=\"text\">{}</content>", escape(content.as_str()));
+    }
+    for link in &entry.links {
+        write_link(xml, link);
+    }
+    xml.push_str("</entry>\n");
+}
+
+fn write_link(xml: &mut String, link: &Link) {
+    let _ = write!(
+        xml,
+        "<link rel=\"{}\" href=\"{}\" type=\"{}\"",
+        escape(link.rel),
+        escape(link.href.as_str()),
+        escape(link.kind.as_str()),
+    );
+    if let Some(title) = &link.title {
+        let _ = write!(xml, " title=\"{}\"", escape(title.as_str()));
+    }
+    for (name, value) in &link.attributes {
+        let _ = write!(xml, " {}=\"{}\"", name, escape(value.as_str()));
+    }
+    xml.push_str("/>\n");
+}
+
+fn write_element(xml: &mut String, name: &str, text: &str) {
+    let _ = writeln!(xml, "<{name}>{}</{name}>", escape(text));
+}
+
+/// Formats unix seconds as an RFC 3339 UTC timestamp.
+pub fn format_timestamp(timestamp: i64) -> String {
+    let days = timestamp.div_euclid(86_400);
+    let seconds = timestamp.rem_euclid(86_400);
+
+    // Civil-from-days, after Howard Hinnant's date algorithms
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day,
+        seconds / 3600, seconds % 3600 / 60, seconds % 60,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quick_xml::events::Event;
+    use quick_xml::Reader;
+
+    /// Unescaped text of every element with the given name, failing on
+    /// malformed XML.
+    fn texts(xml: &str, name: &str) -> Vec<String> {
+        let mut reader = Reader::from_str(xml);
+        let mut texts = Vec::new();
+        let mut text: Option<String> = None;
+        loop {
+            match reader.read_event().unwrap() {
+                Event::Start(start) if start.name().0 == name => text = Some(String::new()),
+                Event::Text(raw) => if let Some(text) = &mut text {
+                    text.push_str(&raw);
+                },
+                // Entities arrive as their own events
+                Event::GeneralRef(entity) => if let Some(text) = &mut text {
+                    text.push_str(&format!("&{};", &*entity));
+                },
+                Event::End(_) => if let Some(text) = text.take() {
+                    texts.push(quick_xml::escape::unescape(&text).unwrap().into_owned());
+                },
+                Event::Eof => return texts,
+                _ => (),
+            }
+        }
+    }
+
+    #[test]
+    fn escapes_text_and_attributes() {
+        let mut feed = Feed::new("urn:feed", "Tom & Jerry <Special>", 0);
+        feed.links.push(Link::new("self", "/opds?q=a&b=\"c\"", ACQUISITION_TYPE).titled("Say \"hi\" & <bye>"));
+        feed.entries.push(Entry {
+            id: String::from("urn:comic:1"),
+            title: String::from("Batman & Robin: \"Heroes\" <1>"),
+            authors: vec![String::from("Simon & Schuster")],
+            summary: Some(String::from("1 < 2 & 3 > 2")),
+            links: vec![Link::new(REL_PSE_STREAM, "/pages?i={pageNumber}&w={maxWidth}", "image/jpeg")
+                .with_attribute("pse:count", "\"3\"")],
+            ..Entry::default()
+        });
+
+        let xml = feed.to_xml();
+        assert!(xml.contains("<title>Tom &amp; Jerry &lt;Special&gt;</title>"));
+        assert!(xml.contains("href=\"/opds?q=a&amp;b=&quot;c&quot;\""));
+        assert!(xml.contains("title=\"Say &quot;hi&quot; &amp; &lt;bye&gt;\""));
+        assert!(xml.contains("pse:count=\"&quot;3&quot;\""));
+        assert!(!xml.contains("Robin: \"Heroes\""));
+
+        assert_eq!(texts(&xml, "title"), ["Tom & Jerry <Special>", "Batman & Robin: \"Heroes\" <1>"]);
+        assert_eq!(texts(&xml, "name")[1], "Simon & Schuster");
+        assert_eq!(texts(&xml, "summary"), ["1 < 2 & 3 > 2"]);
+    }
+
+    #[test]
+    fn escapes_the_search_template() {
+        let xml = opensearch_description("/opds/search?q={searchTerms}&page=1");
+        assert!(xml.contains("template=\"/opds/search?q={searchTerms}&amp;page=1\""));
+        assert_eq!(texts(&xml, "ShortName"), [CATALOG_TITLE]);
+    }
+
+    #[test]
+    fn formats_timestamps_as_rfc_3339() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(-1), "1969-12-31T23:59:59Z");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_timestamp(1_700_000_000), "2023-11-14T22:13:20Z");
+        assert_eq!(format_timestamp(1_709_208_000), "2024-02-29T12:00:00Z");
+        assert_eq!(format_timestamp(4_102_444_799), "2099-12-31T23:59:59Z");
+    }
+}
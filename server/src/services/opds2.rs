@@ -0,0 +1,159 @@
+use serde::Serialize;
+
+pub const FEED_TYPE: &str = "application/opds+json";
+
+/// An OPDS 2.0 catalog feed or group.
+#[derive(Debug, Default, Serialize)]
+pub struct Feed {
+    pub metadata: Metadata,
+    pub links: Vec<Link>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub facets: Vec<FacetGroup>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub navigation: Vec<Link>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<Group>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub publications: Vec<Publication>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Metadata {
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub number_of_items: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub items_per_page: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_page: Option<usize>,
+}
+
+impl Metadata {
+    pub fn titled(title: impl Into<String>) -> Self {
+        Metadata { title: title.into(), ..Metadata::default() }
+    }
+}
+
+/// A section of a feed, such as a folder or series preview.
+#[derive(Debug, Default, Serialize)]
+pub struct Group {
+    pub metadata: Metadata,
+    pub links: Vec<Link>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub navigation: Vec<Link>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub publications: Vec<Publication>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FacetGroup {
+    pub metadata: Metadata,
+    pub links: Vec<Link>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Link {
+    pub href: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rel: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub templated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub properties: Option<LinkProperties>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkProperties {
+    pub number_of_items: usize,
+}
+
+impl Link {
+    pub fn new(href: impl Into<String>, kind: impl Into<String>) -> Self {
+        Link {
+            href: href.into(),
+            kind: kind.into(),
+            rel: None,
+            title: None,
+            templated: false,
+            properties: None,
+        }
+    }
+
+    pub fn rel(mut self, rel: &'static str) -> Self {
+        self.rel = Some(rel);
+        self
+    }
+
+    pub fn titled(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn templated(mut self) -> Self {
+        self.templated = true;
+        self
+    }
+
+    pub fn count(mut self, number_of_items: usize) -> Self {
+        self.properties = Some(LinkProperties { number_of_items });
+        self
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Publication {
+    pub metadata: PublicationMetadata,
+    pub links: Vec<Link>,
+    pub images: Vec<Image>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicationMetadata {
+    #[serde(rename = "@type")]
+    pub kind: &'static str,
+    pub identifier: String,
+    pub title: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub author: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub publisher: Option<String>,
+    /// `YYYY`, `YYYY-MM` or `YYYY-MM-DD`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub number_of_pages: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub belongs_to: Option<BelongsTo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BelongsTo {
+    pub series: Vec<SeriesMembership>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SeriesMembership {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Image {
+    pub href: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+}
@@ -0,0 +1,162 @@
+use std::cmp::Ordering;
+use std::path::{Component, Path};
+
+use crate::models::comic::Page;
+use crate::models::metadata::PageMetadata;
+use crate::services::archive::ArchiveEntry;
+
+/// ComicInfo page type marking the intended cover.
+const FRONT_COVER: &str = "FrontCover";
+
+/// Files that archivers and operating systems leave behind.
+const IGNORED_FILES: &[&str] = &["thumbs.db", "desktop.ini"];
+const IGNORED_DIRS: &[&str] = &["__macosx"];
+
+/// Turns an archive listing into the comic's pages in reading order.
+///
+/// Image entries are natural-sorted by path ("page2" before "page10"),
+/// after dropping OS junk such as `__MACOSX` resource forks, dotfiles and
+/// `Thumbs.db`. ComicInfo page types are attached by image index.
+pub fn order_pages(entries: &[ArchiveEntry], page_metadata: &[PageMetadata]) -> Vec<Page> {
+    let mut images: Vec<(&ArchiveEntry, &'static str)> = entries.iter()
+        .filter(|entry| !entry.is_dir && entry.size > 0 && !is_ignored(&entry.name))
+        .filter_map(|entry| Some((entry, image_mime(&entry.name)?)))
+        .collect();
+
+    images.sort_by(|(a, _), (b, _)| compare_paths(&a.name, &b.name));
+
+    images.into_iter()
+        .enumerate()
+        .map(|(index, (entry, mime))| Page {
+            index,
+            name: entry.name.clone(),
+            size: entry.size,
+            mime: mime.to_string(),
+            page_type: page_metadata.iter()
+                .find(|page| page.image == index)
+                .and_then(|page| page.page_type.clone()),
+        })
+        .collect()
+}
+
+/// The page to use as a cover: the one tagged `FrontCover`, otherwise the
+/// first page.
+pub fn cover_page(pages: &[Page]) -> Option<&Page> {
+    pages.iter()
+        .find(|page| page.page_type.as_deref() == Some(FRONT_COVER))
+        .or_else(|| pages.first())
+}
+
+pub fn image_mime(name: &str) -> Option<&'static str> {
+    let extension = Path::new(name).extension()?.to_string_lossy().to_lowercase();
+    match extension.as_str() {
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "png" => Some("image/png"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+fn is_ignored(name: &str) -> bool {
+    let path = Path::new(name);
+    let ignored_component = path.components().any(|component| match component {
+        Component::Normal(part) => {
+            let part = part.to_string_lossy().to_lowercase();
+            part.starts_with('.') || IGNORED_DIRS.contains(&part.as_str())
+        }
+        _ => false,
+    });
+
+    ignored_component || path.file_name()
+        .map(|file| IGNORED_FILES.contains(&file.to_string_lossy().to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Natural, case-insensitive ordering compared segment by segment so that
+/// directory boundaries sort before file name characters.
+fn compare_paths(a: &str, b: &str) -> Ordering {
+    let mut a_parts = a.split(['/', '\\']);
+    let mut b_parts = b.split(['/', '\\']);
+
+    loop {
+        match (a_parts.next(), b_parts.next()) {
+            (Some(a), Some(b)) => match natord::compare_ignore_case(a, b) {
+                Ordering::Equal => continue,
+                other => return other,
+            },
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (None, None) => return Ordering::Equal,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(names: &[&str]) -> Vec<ArchiveEntry> {
+        names.iter()
+            .map(|name| ArchiveEntry { name: name.to_string(), size: 100, is_dir: false })
+            .collect()
+    }
+
+    fn names(pages: &[Page]) -> Vec<&str> {
+        pages.iter().map(|page| page.name.as_str()).collect()
+    }
+
+    #[test]
+    fn orders_pages_naturally() {
+        let pages = order_pages(&entries(&["page10.jpg", "Page2.png", "page1.jpg", "page2b.jpg"]), &[]);
+        assert_eq!(names(&pages), ["page1.jpg", "Page2.png", "page2b.jpg", "page10.jpg"]);
+        assert_eq!(pages.iter().map(|page| page.index).collect::<Vec<_>>(), [0, 1, 2, 3]);
+        assert_eq!(pages[1].mime, "image/png");
+    }
+
+    #[test]
+    fn sorts_directories_before_their_neighbours() {
+        let pages = order_pages(&entries(&["ch10/01.jpg", "ch2/10.jpg", "ch2/2.jpg", "ch2 extra.jpg"]), &[]);
+        assert_eq!(names(&pages), ["ch2/2.jpg", "ch2/10.jpg", "ch2 extra.jpg", "ch10/01.jpg"]);
+    }
+
+    #[test]
+    fn skips_junk_entries() {
+        let mut listing = entries(&[
+            "__MACOSX/._page1.jpg",
+            "comic/__MACOSX/page1.jpg",
+            ".cover.jpg",
+            ".hidden/page1.jpg",
+            "Thumbs.db",
+            "ComicInfo.xml",
+            "notes.txt",
+            "page1.jpg",
+            "empty.jpg",
+            "folder.jpg",
+        ]);
+        listing.iter_mut().find(|entry| entry.name == "empty.jpg").unwrap().size = 0;
+        listing.iter_mut().find(|entry| entry.name == "folder.jpg").unwrap().is_dir = true;
+
+        assert_eq!(names(&order_pages(&listing, &[])), ["page1.jpg"]);
+    }
+
+    #[test]
+    fn attaches_page_types_and_uses_the_front_cover() {
+        let page_metadata = [
+            PageMetadata { image: 0, page_type: Some(String::from("InnerCover")) },
+            PageMetadata { image: 2, page_type: Some(String::from(FRONT_COVER)) },
+        ];
+        let pages = order_pages(&entries(&["3.jpg", "1.jpg", "2.jpg"]), &page_metadata);
+
+        assert_eq!(pages[0].page_type.as_deref(), Some("InnerCover"));
+        assert_eq!(pages[1].page_type, None);
+        assert_eq!(cover_page(&pages).unwrap().name, "3.jpg");
+    }
+
+    #[test]
+    fn falls_back_to_the_first_page_as_cover() {
+        let pages = order_pages(&entries(&["2.jpg", "1.jpg"]), &[]);
+        assert_eq!(cover_page(&pages).unwrap().name, "1.jpg");
+        assert!(cover_page(&[]).is_none());
+    }
+}
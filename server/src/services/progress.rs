@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::models::error::ComicError;
+use crate::models::progress::{ReadCounts, ReadProgress};
+use crate::services::database::{unix_time, Database};
+
+/// User id under which a server without accounts keeps progress.
+pub const ANONYMOUS_USER: i64 = 0;
+
+/// Per-user reading positions, synced between devices.
+#[derive(Clone)]
+pub struct ProgressService {
+    database: Arc<Database>,
+}
+
+impl ProgressService {
+    pub fn new(database: Arc<Database>) -> Self {
+        ProgressService { database }
+    }
+
+    pub fn get(&self, user_id: i64, comic_id: &str) -> Result<Option<ReadProgress>, ComicError> {
+        self.database.progress(user_id, comic_id)
+    }
+
+    pub fn all(&self, user_id: i64) -> Result<Vec<ReadProgress>, ComicError> {
+        self.database.all_progress(user_id)
+    }
+
+    /// All of a user's progress keyed by comic id, for annotating listings.
+    pub fn by_comic(&self, user_id: i64) -> Result<HashMap<String, ReadProgress>, ComicError> {
+        Ok(self.all(user_id)?
+            .into_iter()
+            .map(|progress| (progress.comic_id.clone(), progress))
+            .collect())
+    }
+
+    /// Records an update, returning the stored progress. An update older
+    /// than what is stored loses, and the stored one comes back instead.
+    pub fn save(&self, user_id: i64, progress: &ReadProgress) -> Result<ReadProgress, ComicError> {
+        self.database.save_progress(user_id, progress)
+    }
+
+    pub fn clear(&self, user_id: i64, comic_id: &str) -> Result<(), ComicError> {
+        self.database.delete_progress(user_id, comic_id)
+    }
+
+    /// Marks a batch of comics, such as a folder or series, read or unread.
+    /// Marking unread forgets the progress entirely.
+    pub fn mark(&self, user_id: i64, comic_ids: &[String], read: bool) -> Result<(), ComicError> {
+        if read {
+            self.database.mark_read(user_id, comic_ids, unix_time())
+        } else {
+            self.database.delete_progress_for(user_id, comic_ids)
+        }
+    }
+
+    pub fn counts(&self, user_id: i64, comic_ids: &[String]) -> Result<ReadCounts, ComicError> {
+        let progress = self.by_comic(user_id)?;
+        let mut counts = ReadCounts::default();
+        for comic_id in comic_ids {
+            counts.add(progress.get(comic_id));
+        }
+        Ok(counts)
+    }
+}
@@ -0,0 +1,87 @@
+use std::io::Cursor;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+
+use crate::models::comic::CoverImage;
+use crate::models::error::ComicError;
+
+/// Upper bound for requested dimensions, so a single request cannot make the
+/// server allocate arbitrarily large images.
+const MAX_DIMENSION: u32 = 2000;
+const JPEG_QUALITY: u8 = 85;
+
+/// Bounding box a cover is scaled down to fit. Aspect ratio is preserved
+/// and images are never scaled up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ThumbnailSize {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl ThumbnailSize {
+    /// Builds a size from the `size`, `width` and `height` query parameters.
+    /// Returns `None` when the original image was asked for.
+    pub fn from_query(
+        size: Option<&str>,
+        width: Option<u32>,
+        height: Option<u32>,
+    ) -> Result<Option<Self>, ComicError> {
+        let named = match size {
+            None | Some("original") => None,
+            Some("small") => Some(ThumbnailSize { width: Some(150), height: None }),
+            Some("medium") => Some(ThumbnailSize { width: Some(300), height: None }),
+            Some("large") => Some(ThumbnailSize { width: Some(600), height: None }),
+            Some(_) => return Err(ComicError::InvalidThumbnailSize),
+        };
+
+        let explicit = (width.is_some() || height.is_some())
+            .then_some(ThumbnailSize { width, height });
+
+        match explicit.or(named) {
+            Some(size) if size.is_valid() => Ok(Some(size)),
+            Some(_) => Err(ComicError::InvalidThumbnailSize),
+            None => Ok(None),
+        }
+    }
+
+    /// Box for a page shown on a screen `width` pixels wide. Readers send
+    /// their real screen size, so oversized widths are clamped rather than
+    /// refused.
+    pub fn fit_width(width: u32) -> Self {
+        ThumbnailSize { width: Some(width.clamp(1, MAX_DIMENSION)), height: None }
+    }
+
+    fn is_valid(&self) -> bool {
+        let valid = |dimension: Option<u32>| {
+            dimension.is_none_or(|dimension| (1..=MAX_DIMENSION).contains(&dimension))
+        };
+        valid(self.width) && valid(self.height)
+    }
+
+    /// Short cache key such as `300x` or `300x450`.
+    pub fn key(&self) -> String {
+        let dimension = |d: Option<u32>| d.map(|d| d.to_string()).unwrap_or_default();
+        format!("{}x{}", dimension(self.width), dimension(self.height))
+    }
+}
+
+/// Scales an image down into the requested box and re-encodes it as JPEG.
+pub fn render(source: &[u8], size: ThumbnailSize) -> Result<CoverImage, ComicError> {
+    let image = image::load_from_memory(source).map_err(ComicError::image)?;
+
+    let max_width = size.width.unwrap_or(u32::MAX).min(image.width());
+    let max_height = size.height.unwrap_or(u32::MAX).min(image.height());
+    let image = if max_width < image.width() || max_height < image.height() {
+        image.resize(max_width, max_height, FilterType::Lanczos3)
+    } else {
+        image
+    };
+
+    let mut data = Vec::new();
+    let encoder = JpegEncoder::new_with_quality(Cursor::new(&mut data), JPEG_QUALITY);
+    image.to_rgb8()
+        .write_with_encoder(encoder)
+        .map_err(ComicError::image)?;
+
+    Ok(CoverImage { data, mime: "image/jpeg".to_string() })
+}
@@ -0,0 +1,352 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use argon2::password_hash::SaltString;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::Engine as _;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use sha2::{Digest, Sha256};
+
+use crate::models::error::ComicError;
+use crate::models::user::{Session, SessionTokens, User};
+use crate::services::database::{unix_time, Database, SessionSecrets};
+use crate::services::kosync;
+
+const MIN_PASSWORD_LEN: usize = 8;
+const MAX_USERNAME_LEN: usize = 64;
+
+/// Lifetime of an access token, in seconds.
+const ACCESS_TOKEN_TTL: i64 = 60 * 60;
+/// Lifetime of a refresh token, and so of an idle session, in seconds.
+const REFRESH_TOKEN_TTL: i64 = 30 * 24 * 60 * 60;
+/// `last_used_at` is only rewritten when older than this, in seconds.
+const SESSION_TOUCH_INTERVAL: i64 = 60;
+
+/// Account management and credential checks.
+///
+/// Argon2 is deliberately slow, so credentials that verified once are
+/// remembered by digest until any account changes.
+#[derive(Clone)]
+pub struct UserService {
+    database: Arc<Database>,
+    verified: Arc<RwLock<HashMap<[u8; 32], User>>>,
+}
+
+impl UserService {
+    pub fn new(database: Arc<Database>) -> Self {
+        UserService {
+            database,
+            verified: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Creates the first admin from the legacy shared password, so existing
+    /// installs keep working after upgrading to accounts.
+    pub fn bootstrap_admin(&self, username: &str, password: &str) -> Result<(), ComicError> {
+        if self.database.count_users()? > 0 {
+            return Ok(());
+        }
+
+        self.database.create_user(
+            username,
+            &hash_password(password)?,
+            &hash_password(&kosync::key_for_password(password))?,
+            true,
+        )?;
+        println!("Created admin user '{}' from SERVER_PASSWORD", username);
+        Ok(())
+    }
+
+    /// Authentication is only enforced once at least one account exists.
+    pub fn auth_required(&self) -> Result<bool, ComicError> {
+        Ok(self.database.count_users()? > 0)
+    }
+
+    /// Checks a username and password, returning the account when they
+    /// match an enabled user. Blocking; run it off the async executor.
+    pub fn authenticate(&self, username: &str, password: &str) -> Result<Option<User>, ComicError> {
+        let digest = credentials_digest(username, password);
+        if let Some(user) = self.verified.read().unwrap_or_else(|e| e.into_inner()).get(&digest) {
+            return Ok(Some(user.clone()));
+        }
+
+        let Some(credentials) = self.database.user_credentials(username)? else {
+            return Ok(None);
+        };
+        let user = credentials.user;
+        if user.disabled || !verify_password(password, &credentials.password_hash) {
+            return Ok(None);
+        }
+
+        // Accounts from before kosync support get their key the first time
+        // the password is at hand
+        if credentials.kosync_key_hash.is_none() {
+            let key_hash = hash_password(&kosync::key_for_password(password))?;
+            self.database.set_kosync_key_hash(user.id, &key_hash)?;
+        }
+
+        self.verified.write().unwrap_or_else(|e| e.into_inner()).insert(digest, user.clone());
+        Ok(Some(user))
+    }
+
+    /// Checks the username and MD5 key a kosync client sends. Blocking,
+    /// like `authenticate`.
+    pub fn authenticate_kosync(&self, username: &str, key: &str) -> Result<Option<User>, ComicError> {
+        let digest = kosync_digest(username, key);
+        if let Some(user) = self.verified.read().unwrap_or_else(|e| e.into_inner()).get(&digest) {
+            return Ok(Some(user.clone()));
+        }
+
+        let Some(credentials) = self.database.user_credentials(username)? else {
+            return Ok(None);
+        };
+        let Some(key_hash) = credentials.kosync_key_hash else {
+            return Ok(None);
+        };
+        let user = credentials.user;
+        if user.disabled || !verify_password(&key.to_ascii_lowercase(), &key_hash) {
+            return Ok(None);
+        }
+
+        self.verified.write().unwrap_or_else(|e| e.into_inner()).insert(digest, user.clone());
+        Ok(Some(user))
+    }
+
+    /// Whether an account with this username exists.
+    pub fn exists(&self, username: &str) -> Result<bool, ComicError> {
+        Ok(self.database.user_credentials(username)?.is_some())
+    }
+
+    pub fn list(&self) -> Result<Vec<User>, ComicError> {
+        self.database.list_users()
+    }
+
+    pub fn get(&self, id: i64) -> Result<User, ComicError> {
+        self.database.get_user(id)?.ok_or(ComicError::UserNotFound)
+    }
+
+    /// The generation signed URLs for this user must carry, or `None` when
+    /// the account is gone or disabled.
+    pub fn token_generation(&self, id: i64) -> Result<Option<i64>, ComicError> {
+        self.database.token_generation(id)
+    }
+
+    /// Creates an account. The first one is always an admin, whatever
+    /// `is_admin` says.
+    pub fn create(&self, username: &str, password: &str, is_admin: bool) -> Result<User, ComicError> {
+        let username = validate_username(username)?;
+        validate_password(password)?;
+        self.database.create_user(
+            username,
+            &hash_password(password)?,
+            &hash_password(&kosync::key_for_password(password))?,
+            is_admin,
+        )
+    }
+
+    /// Changes a user's role or enabled state, refusing to leave the server
+    /// without an active admin.
+    pub fn update(
+        &self,
+        id: i64,
+        is_admin: Option<bool>,
+        disabled: Option<bool>,
+    ) -> Result<User, ComicError> {
+        let mut user = self.get(id)?;
+        let was_active_admin = user.is_active_admin();
+
+        user.is_admin = is_admin.unwrap_or(user.is_admin);
+        user.disabled = disabled.unwrap_or(user.disabled);
+        if was_active_admin && !user.is_active_admin() {
+            self.ensure_other_admin()?;
+        }
+
+        self.database.update_user(&user)?;
+        if user.disabled {
+            self.database.delete_user_sessions(id)?;
+        }
+        self.forget_verified();
+        Ok(user)
+    }
+
+    /// Changes a password and signs the user out everywhere.
+    pub fn set_password(&self, id: i64, password: &str) -> Result<(), ComicError> {
+        validate_password(password)?;
+        self.get(id)?;
+        self.database.set_password_hash(
+            id,
+            &hash_password(password)?,
+            &hash_password(&kosync::key_for_password(password))?,
+        )?;
+        self.database.delete_user_sessions(id)?;
+        self.forget_verified();
+        Ok(())
+    }
+
+    pub fn delete(&self, id: i64) -> Result<(), ComicError> {
+        let user = self.get(id)?;
+        if user.is_active_admin() {
+            self.ensure_other_admin()?;
+        }
+
+        self.database.delete_user(id)?;
+        self.forget_verified();
+        Ok(())
+    }
+
+    /// Checks credentials and opens a session. Blocking, like
+    /// `authenticate`.
+    pub fn login(
+        &self,
+        username: &str,
+        password: &str,
+        user_agent: Option<&str>,
+    ) -> Result<Option<SessionTokens>, ComicError> {
+        let Some(user) = self.authenticate(username, password)? else {
+            return Ok(None);
+        };
+
+        self.database.delete_expired_sessions()?;
+        let (access_token, refresh_token) = (random_token(), random_token());
+        let session = self.database.create_session(
+            &random_token(),
+            user.id,
+            &session_secrets(&access_token, &refresh_token),
+            user_agent,
+        )?;
+
+        Ok(Some(session_tokens(session.id, access_token, refresh_token, user)))
+    }
+
+    /// Trades a refresh token for a new token pair on the same session.
+    pub fn refresh(&self, refresh_token: &str) -> Result<Option<SessionTokens>, ComicError> {
+        let Some((user, session)) = self.database.session_by_refresh(&token_hash(refresh_token))? else {
+            return Ok(None);
+        };
+
+        let (access_token, refresh_token) = (random_token(), random_token());
+        self.database.rotate_session(&session.id, &session_secrets(&access_token, &refresh_token))?;
+        Ok(Some(session_tokens(session.id, access_token, refresh_token, user)))
+    }
+
+    /// Resolves a bearer access token to its user and session.
+    pub fn authenticate_token(&self, access_token: &str) -> Result<Option<(User, Session)>, ComicError> {
+        let found = self.database.session_by_token(&token_hash(access_token))?;
+        if let Some((_, session)) = &found {
+            if session.last_used_at + SESSION_TOUCH_INTERVAL < unix_time() {
+                self.database.touch_session(&session.id)?;
+            }
+        }
+        Ok(found)
+    }
+
+    pub fn sessions(&self, user_id: i64) -> Result<Vec<Session>, ComicError> {
+        self.database.list_sessions(user_id)
+    }
+
+    pub fn session(&self, id: &str) -> Result<Session, ComicError> {
+        self.database.get_session(id)?.ok_or(ComicError::SessionNotFound)
+    }
+
+    pub fn revoke_session(&self, id: &str) -> Result<(), ComicError> {
+        self.database.delete_session(id)
+    }
+
+    fn ensure_other_admin(&self) -> Result<(), ComicError> {
+        if self.database.count_active_admins()? <= 1 {
+            return Err(ComicError::LastAdmin);
+        }
+        Ok(())
+    }
+
+    fn forget_verified(&self) {
+        self.verified.write().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
+
+pub fn hash_password(password: &str) -> Result<String, ComicError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| ComicError::InvalidUser(e.to_string()))
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash)
+        .map(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+        .unwrap_or(false)
+}
+
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    BASE64_URL.encode(bytes)
+}
+
+/// Tokens are stored as digests, so a leaked database cannot be replayed.
+fn token_hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn session_secrets(access_token: &str, refresh_token: &str) -> SessionSecrets {
+    let now = unix_time();
+    SessionSecrets {
+        token_hash: token_hash(access_token),
+        refresh_hash: token_hash(refresh_token),
+        access_expires_at: now + ACCESS_TOKEN_TTL,
+        expires_at: now + REFRESH_TOKEN_TTL,
+    }
+}
+
+fn session_tokens(session_id: String, access_token: String, refresh_token: String, user: User) -> SessionTokens {
+    SessionTokens {
+        session_id,
+        access_token,
+        token_type: "Bearer",
+        expires_in: ACCESS_TOKEN_TTL,
+        refresh_token,
+        refresh_expires_in: REFRESH_TOKEN_TTL,
+        user,
+    }
+}
+
+fn credentials_digest(username: &str, password: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(username.to_ascii_lowercase().as_bytes());
+    hasher.update([0]);
+    hasher.update(password.as_bytes());
+    hasher.finalize().into()
+}
+
+/// Cache key for kosync credentials, kept apart from password digests.
+fn kosync_digest(username: &str, key: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(username.to_ascii_lowercase().as_bytes());
+    hasher.update([1]);
+    hasher.update(key.to_ascii_lowercase().as_bytes());
+    hasher.finalize().into()
+}
+
+fn validate_username(username: &str) -> Result<&str, ComicError> {
+    let username = username.trim();
+    if username.is_empty() || username.len() > MAX_USERNAME_LEN {
+        return Err(ComicError::InvalidUser(format!(
+            "username must be 1 to {} characters", MAX_USERNAME_LEN
+        )));
+    }
+    // HTTP Basic splits on the first colon
+    if username.contains(':') || username.chars().any(char::is_control) {
+        return Err(ComicError::InvalidUser("username contains invalid characters".to_string()));
+    }
+    Ok(username)
+}
+
+fn validate_password(password: &str) -> Result<(), ComicError> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(ComicError::InvalidUser(format!(
+            "password must be at least {} characters", MIN_PASSWORD_LEN
+        )));
+    }
+    Ok(())
+}
@@ -4,6 +4,7 @@ use rocket::{Request, Response};
 use rocket::http::Method;
 use rocket::http::Status;
 
+#[allow(clippy::upper_case_acronyms)]
 pub struct CORS;
 
 #[rocket::async_trait]
@@ -18,10 +19,14 @@ impl Fairing for CORS {
     async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
         // Add CORS headers to all responses
         response.set_header(Header::new("Access-Control-Allow-Origin", "*"));
-        response.set_header(Header::new("Access-Control-Allow-Methods", "GET, POST, OPTIONS"));
+        response.set_header(Header::new("Access-Control-Allow-Methods", "GET, POST, PUT, PATCH, DELETE, OPTIONS"));
         response.set_header(Header::new(
             "Access-Control-Allow-Headers",
-            "Content-Type, Authorization"
+            "Content-Type, Authorization, Range, If-Range"
+        ));
+        response.set_header(Header::new(
+            "Access-Control-Expose-Headers",
+            "Accept-Ranges, Content-Range, Content-Length, Content-Disposition, ETag"
         ));
         response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
 
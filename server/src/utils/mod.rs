@@ -1,2 +1,4 @@
 pub mod response;
-pub mod cors;
\ No newline at end of file
+pub mod cors;
+pub mod range;
+pub mod paths;
\ No newline at end of file
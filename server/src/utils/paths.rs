@@ -0,0 +1,136 @@
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::models::error::ComicError;
+
+/// Resolves library-relative paths to files on disk, refusing anything that
+/// would land outside the configured roots.
+///
+/// Every path is canonicalized before the check, so `..` segments and
+/// symlinks pointing elsewhere are caught alike. Callers hand in paths that
+/// are already URL-decoded; the resolver never decodes, so escapes that are
+/// still percent-encoded stay literal file names.
+#[derive(Debug, Clone)]
+pub struct PathResolver {
+    roots: Vec<PathBuf>,
+}
+
+impl PathResolver {
+    pub fn new(roots: impl IntoIterator<Item = PathBuf>) -> io::Result<Self> {
+        let roots = roots.into_iter()
+            .map(|root| root.canonicalize())
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(PathResolver { roots })
+    }
+
+    /// Resolves a `/`-separated path relative to the first root.
+    pub fn resolve(&self, relative: &str) -> Result<PathBuf, ComicError> {
+        let root = self.roots.first().ok_or(ComicError::InvalidPath)?;
+
+        let mut path = root.clone();
+        for part in relative.split('/').filter(|part| !part.is_empty()) {
+            if part.contains('\0') || !matches!(Path::new(part).components().next(), Some(Component::Normal(_))) {
+                return Err(ComicError::InvalidPath);
+            }
+            path.push(part);
+        }
+
+        self.check(&path)
+    }
+
+    /// Canonicalizes an absolute path and checks it lies under a root.
+    pub fn check(&self, path: &Path) -> Result<PathBuf, ComicError> {
+        let canonical = path.canonicalize().map_err(|_| ComicError::InvalidPath)?;
+        if self.roots.iter().any(|root| canonical.starts_with(root)) {
+            Ok(canonical)
+        } else {
+            Err(ComicError::InvalidPath)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// A library root with one comic inside and a secret file beside it.
+    struct Fixture {
+        dir: PathBuf,
+        resolver: PathResolver,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir()
+                .join(format!("comic-paths-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(dir.join("library/Batman")).unwrap();
+            fs::write(dir.join("library/Batman/001.cbz"), b"comic").unwrap();
+            fs::write(dir.join("secret.txt"), b"secret").unwrap();
+
+            let resolver = PathResolver::new([dir.join("library")]).unwrap();
+            Fixture { dir, resolver }
+        }
+
+        fn resolve_decoded(&self, id: &str) -> Result<PathBuf, ComicError> {
+            let decoded = urlencoding::decode(id).unwrap();
+            self.resolver.resolve(&decoded)
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn is_invalid(result: Result<PathBuf, ComicError>) -> bool {
+        matches!(result, Err(ComicError::InvalidPath))
+    }
+
+    #[test]
+    fn resolves_paths_inside_the_library() {
+        let fixture = Fixture::new("inside");
+        let path = fixture.resolver.resolve("Batman/001.cbz").unwrap();
+        assert!(path.ends_with("library/Batman/001.cbz"));
+    }
+
+    #[test]
+    fn rejects_plain_traversal() {
+        let fixture = Fixture::new("plain");
+        assert!(is_invalid(fixture.resolver.resolve("../secret.txt")));
+        assert!(is_invalid(fixture.resolver.resolve("Batman/../../secret.txt")));
+        assert!(is_invalid(fixture.resolver.resolve("/etc/passwd")));
+    }
+
+    #[test]
+    fn rejects_encoded_traversal() {
+        let fixture = Fixture::new("encoded");
+        assert!(is_invalid(fixture.resolve_decoded("..%2Fsecret.txt")));
+        assert!(is_invalid(fixture.resolve_decoded("%2e%2e%2fsecret.txt")));
+        assert!(is_invalid(fixture.resolve_decoded("Batman%2F%2E%2E%2F%2E%2E%2Fsecret.txt")));
+    }
+
+    #[test]
+    fn double_encoded_traversal_stays_literal() {
+        let fixture = Fixture::new("double");
+        // One decode leaves `%2e%2e%2f`, which must not be decoded again
+        assert!(is_invalid(fixture.resolve_decoded("%252e%252e%252fsecret.txt")));
+        assert!(is_invalid(fixture.resolve_decoded("..%252Fsecret.txt")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlink_escapes() {
+        let fixture = Fixture::new("symlink");
+        std::os::unix::fs::symlink(
+            fixture.dir.join("secret.txt"),
+            fixture.dir.join("library/escape.cbz"),
+        ).unwrap();
+        std::os::unix::fs::symlink(fixture.dir.clone(), fixture.dir.join("library/outside")).unwrap();
+
+        assert!(is_invalid(fixture.resolver.resolve("escape.cbz")));
+        assert!(is_invalid(fixture.resolver.resolve("outside/secret.txt")));
+    }
+}
@@ -0,0 +1,357 @@
+use std::collections::VecDeque;
+use std::io::{self, SeekFrom};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
+
+/// More ranges than this in one request are treated as abuse and answered
+/// with the full body instead.
+const MAX_RANGES: usize = 16;
+
+/// An inclusive byte range already clamped to the resource size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    pub fn content_range(&self, size: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, size)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// No usable `Range` header; send the whole body.
+    Full,
+    Partial(Vec<ByteRange>),
+    /// Syntactically valid but no range overlaps the resource.
+    Unsatisfiable,
+}
+
+/// Parses a `Range` header (RFC 9110 §14.1.2) against a body of `size`
+/// bytes. Malformed headers and units other than `bytes` are ignored, as
+/// the RFC allows.
+pub fn parse_range(header: &str, size: u64) -> RangeRequest {
+    let Some(specs) = header.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+
+    let mut ranges = Vec::new();
+    for spec in specs.split(',') {
+        let Some((start, end)) = spec.trim().split_once('-') else {
+            return RangeRequest::Full;
+        };
+
+        let range = match (start.trim(), end.trim()) {
+            ("", "") => return RangeRequest::Full,
+            // Suffix range: the last N bytes
+            ("", suffix) => match suffix.parse::<u64>() {
+                Ok(0) => None,
+                Ok(suffix) if size > 0 => Some(ByteRange {
+                    start: size.saturating_sub(suffix),
+                    end: size - 1,
+                }),
+                Ok(_) => None,
+                Err(_) => return RangeRequest::Full,
+            },
+            (start, end) => {
+                let Ok(start) = start.parse::<u64>() else {
+                    return RangeRequest::Full;
+                };
+                let end = match end {
+                    "" => u64::MAX,
+                    end => match end.parse::<u64>() {
+                        Ok(end) if end >= start => end,
+                        _ => return RangeRequest::Full,
+                    },
+                };
+                (start < size).then(|| ByteRange { start, end: end.min(size - 1) })
+            }
+        };
+
+        ranges.extend(range);
+    }
+
+    if ranges.is_empty() {
+        RangeRequest::Unsatisfiable
+    } else if ranges.len() > MAX_RANGES {
+        RangeRequest::Full
+    } else {
+        RangeRequest::Partial(ranges)
+    }
+}
+
+/// A piece of a ranged response body.
+pub enum Part {
+    Bytes(Vec<u8>),
+    /// A byte range read from the underlying file.
+    File(ByteRange),
+}
+
+enum State {
+    Idle,
+    Seeking,
+    Reading { remaining: u64 },
+    Bytes { data: Vec<u8>, position: usize },
+}
+
+/// Streams a sequence of literal byte chunks and file ranges, seeking the
+/// file as needed, so partial and multipart responses never hold more than
+/// one read buffer in memory.
+pub struct RangeReader {
+    file: Option<File>,
+    parts: VecDeque<Part>,
+    state: State,
+}
+
+impl RangeReader {
+    pub fn new(file: Option<File>, parts: Vec<Part>) -> Self {
+        RangeReader { file, parts: parts.into(), state: State::Idle }
+    }
+
+    fn file(&mut self) -> io::Result<Pin<&mut File>> {
+        self.file.as_mut()
+            .map(Pin::new)
+            .ok_or_else(|| io::Error::other("file range without a file"))
+    }
+}
+
+impl AsyncRead for RangeReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            match &mut this.state {
+                State::Idle => match this.parts.pop_front() {
+                    None => return Poll::Ready(Ok(())),
+                    Some(Part::Bytes(data)) => this.state = State::Bytes { data, position: 0 },
+                    Some(Part::File(range)) => {
+                        this.file()?.start_seek(SeekFrom::Start(range.start))?;
+                        this.parts.push_front(Part::File(range));
+                        this.state = State::Seeking;
+                    }
+                },
+                State::Seeking => {
+                    ready!(this.file()?.poll_complete(cx))?;
+                    let Some(Part::File(range)) = this.parts.pop_front() else {
+                        unreachable!("seeking without a pending file range");
+                    };
+                    this.state = State::Reading { remaining: range.len() };
+                }
+                State::Bytes { data, position } => {
+                    if *position == data.len() {
+                        this.state = State::Idle;
+                        continue;
+                    }
+                    let count = buf.remaining().min(data.len() - *position);
+                    buf.put_slice(&data[*position..*position + count]);
+                    *position += count;
+                    return Poll::Ready(Ok(()));
+                }
+                State::Reading { remaining } => {
+                    if *remaining == 0 {
+                        this.state = State::Idle;
+                        continue;
+                    }
+                    let remaining = *remaining;
+                    let limit = buf.remaining().min(usize::try_from(remaining).unwrap_or(usize::MAX));
+                    let mut limited = ReadBuf::new(buf.initialize_unfilled_to(limit));
+                    ready!(this.file()?.poll_read(cx, &mut limited))?;
+
+                    let read = limited.filled().len();
+                    if read == 0 {
+                        return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                    }
+                    buf.advance(read);
+                    this.state = State::Reading { remaining: remaining - read as u64 };
+                    return Poll::Ready(Ok(()));
+                }
+            }
+        }
+    }
+}
+
+/// Rocket only requires seeking to measure bodies of unknown size. Ranged
+/// bodies are always sent with an explicit length, so only the no-op
+/// "where am I" seek is supported.
+impl AsyncSeek for RangeReader {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        match position {
+            SeekFrom::Current(0) => Ok(()),
+            _ => Err(io::ErrorKind::Unsupported.into()),
+        }
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(Ok(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    const SIZE: u64 = 1000;
+
+    fn range(start: u64, end: u64) -> ByteRange {
+        ByteRange { start, end }
+    }
+
+    fn partial(ranges: &[(u64, u64)]) -> RangeRequest {
+        RangeRequest::Partial(ranges.iter().map(|&(start, end)| range(start, end)).collect())
+    }
+
+    #[test]
+    fn parses_closed_ranges() {
+        assert_eq!(parse_range("bytes=0-499", SIZE), partial(&[(0, 499)]));
+        assert_eq!(parse_range(" bytes= 10 - 19 ", SIZE), partial(&[(10, 19)]));
+        // An end past the body is clamped to the last byte
+        assert_eq!(parse_range("bytes=900-5000", SIZE), partial(&[(900, 999)]));
+    }
+
+    #[test]
+    fn parses_suffix_ranges() {
+        assert_eq!(parse_range("bytes=-500", SIZE), partial(&[(500, 999)]));
+        // Asking for more than the body returns all of it
+        assert_eq!(parse_range("bytes=-5000", SIZE), partial(&[(0, 999)]));
+        assert_eq!(parse_range("bytes=-0", SIZE), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-10", 0), RangeRequest::Unsatisfiable);
+    }
+
+    #[test]
+    fn parses_open_ended_ranges() {
+        assert_eq!(parse_range("bytes=100-", SIZE), partial(&[(100, 999)]));
+        assert_eq!(parse_range("bytes=999-", SIZE), partial(&[(999, 999)]));
+    }
+
+    #[test]
+    fn parses_multiple_ranges_in_order() {
+        assert_eq!(
+            parse_range("bytes=0-9, 500-, -5", SIZE),
+            partial(&[(0, 9), (500, 999), (995, 999)]),
+        );
+    }
+
+    #[test]
+    fn keeps_overlapping_ranges_as_sent() {
+        assert_eq!(
+            parse_range("bytes=0-99,50-149,0-99", SIZE),
+            partial(&[(0, 99), (50, 149), (0, 99)]),
+        );
+    }
+
+    #[test]
+    fn drops_ranges_outside_the_body() {
+        assert_eq!(parse_range("bytes=1000-", SIZE), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=1000-2000,5000-", SIZE), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-0", 0), RangeRequest::Unsatisfiable);
+        // Satisfiable ranges survive next to unsatisfiable ones
+        assert_eq!(parse_range("bytes=2000-,0-0", SIZE), partial(&[(0, 0)]));
+    }
+
+    #[test]
+    fn ignores_malformed_headers() {
+        for header in [
+            "",
+            "bytes",
+            "bytes=",
+            "bytes=-",
+            "bytes=abc",
+            "bytes=5",
+            "bytes=a-10",
+            "bytes=10-a",
+            "bytes=-a",
+            "bytes=20-10",
+            "bytes=0-1,,2-3",
+            "items=0-10",
+            "0-10",
+        ] {
+            assert_eq!(parse_range(header, SIZE), RangeRequest::Full, "{:?}", header);
+        }
+    }
+
+    #[test]
+    fn too_many_ranges_get_the_full_body() {
+        let specs = |count: u64| (0..count)
+            .map(|i| format!("{}-{}", i * 10, i * 10 + 1))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        match parse_range(&format!("bytes={}", specs(MAX_RANGES as u64)), SIZE) {
+            RangeRequest::Partial(ranges) => assert_eq!(ranges.len(), MAX_RANGES),
+            other => panic!("expected {} ranges, got {:?}", MAX_RANGES, other),
+        }
+        assert_eq!(
+            parse_range(&format!("bytes={}", specs(MAX_RANGES as u64 + 1)), SIZE),
+            RangeRequest::Full,
+        );
+    }
+
+    struct TempFile(std::path::PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, contents: &[u8]) -> Self {
+            let path = std::env::temp_dir()
+                .join(format!("comic-range-{}-{}", name, std::process::id()));
+            std::fs::write(&path, contents).unwrap();
+            TempFile(path)
+        }
+
+        async fn open(&self) -> File {
+            File::open(&self.0).await.unwrap()
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    async fn read_all(mut reader: RangeReader) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await?;
+        Ok(data)
+    }
+
+    #[rocket::async_test]
+    async fn reader_interleaves_bytes_and_file_ranges() {
+        let file = TempFile::new("interleave", b"0123456789");
+        let reader = RangeReader::new(Some(file.open().await), vec![
+            Part::Bytes(b"--a\n".to_vec()),
+            Part::File(range(2, 4)),
+            Part::Bytes(b"\n".to_vec()),
+            Part::File(range(8, 9)),
+            Part::File(range(0, 0)),
+        ]);
+
+        assert_eq!(read_all(reader).await.unwrap(), b"--a\n234\n890");
+    }
+
+    #[rocket::async_test]
+    async fn reader_fails_on_ranges_past_the_file() {
+        let file = TempFile::new("short", b"0123");
+        let reader = RangeReader::new(Some(file.open().await), vec![Part::File(range(2, 9))]);
+
+        let error = read_all(reader).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[rocket::async_test]
+    async fn reader_needs_a_file_for_file_ranges() {
+        let reader = RangeReader::new(None, vec![Part::Bytes(b"x".to_vec()), Part::File(range(0, 1))]);
+        assert!(read_all(reader).await.is_err());
+    }
+}
@@ -1,27 +1,304 @@
-use rocket::http::{ContentType, Header};
+use rocket::http::{ContentType, Header, Status};
 use rocket::response::{self, Response, Responder};
 use rocket::Request;
 use std::io::Cursor;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::File;
 
+use crate::utils::range::{parse_range, ByteRange, Part, RangeReader, RangeRequest};
+
+/// Payload of a `BinaryResponse`. Files are streamed from disk in chunks
+/// rather than loaded into memory.
+pub enum Body {
+    Bytes(Vec<u8>),
+    File { file: File, size: u64, modified: Option<SystemTime> },
+}
+
+impl Body {
+    pub async fn from_file(file: File) -> std::io::Result<Self> {
+        let metadata = file.metadata().await?;
+        Ok(Body::File {
+            file,
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+
+    fn size(&self) -> u64 {
+        match self {
+            Body::Bytes(data) => data.len() as u64,
+            Body::File { size, .. } => *size,
+        }
+    }
+
+    /// Strong validator for files, built from size and modification time,
+    /// used to make `If-Range` resumption safe against the file being
+    /// replaced between requests. Bytes bodies carry no validator.
+    fn etag(&self) -> Option<String> {
+        match self {
+            Body::Bytes(_) => None,
+            Body::File { size, modified, .. } => {
+                let modified = (*modified)?.duration_since(UNIX_EPOCH).ok()?;
+                Some(format!("\"{:x}-{:x}\"", size, modified.as_secs()))
+            }
+        }
+    }
+
+    /// Splits the body into the readers for the given parts.
+    fn into_reader(self, parts: Vec<Part>) -> RangeReader {
+        match self {
+            Body::File { file, .. } => RangeReader::new(Some(file), parts),
+            Body::Bytes(data) => {
+                let parts = parts.into_iter()
+                    .map(|part| match part {
+                        Part::File(range) => Part::Bytes(slice(&data, range).to_vec()),
+                        bytes => bytes,
+                    })
+                    .collect();
+                RangeReader::new(None, parts)
+            }
+        }
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(data: Vec<u8>) -> Self {
+        Body::Bytes(data)
+    }
+}
+
+/// Binary payload with support for single and multiple byte ranges.
 pub struct BinaryResponse {
-    pub data: Vec<u8>,
+    pub body: Body,
     pub content_type: ContentType,
     pub filename: Option<String>,
 }
 
 impl<'r> Responder<'r, 'static> for BinaryResponse {
-    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
         let mut response = Response::build();
-        response.header(self.content_type);
+        response.header(Header::new("Accept-Ranges", "bytes"));
 
-        if let Some(filename) = self.filename {
-            response.header(Header::new(
-                "Content-Disposition",
-                format!("attachment; filename=\"{}\"", filename)
-            ));
+        if let Some(filename) = &self.filename {
+            response.header(Header::new("Content-Disposition", content_disposition(filename)));
+        }
+
+        let size = self.body.size();
+        let etag = self.body.etag();
+        if let Some(etag) = &etag {
+            response.header(Header::new("ETag", etag.clone()));
+        }
+
+        // A stale If-Range means the client's partial copy is outdated, so
+        // the whole body is sent instead of the requested ranges. If-Range
+        // needs a strong match, so weak tags and dates never match ours
+        let if_range = request.headers().get_one("If-Range");
+        let range = request.headers().get_one("Range")
+            .filter(|_| if_range.is_none() || if_range == etag.as_deref())
+            .map(|header| parse_range(header, size))
+            .unwrap_or(RangeRequest::Full);
+
+        match range {
+            RangeRequest::Full => {
+                response.header(self.content_type);
+                match self.body {
+                    Body::Bytes(data) => {
+                        response.sized_body(data.len(), Cursor::new(data));
+                    }
+                    Body::File { file, size, .. } => {
+                        response.sized_body(usize::try_from(size).ok(), file);
+                    }
+                }
+            }
+            RangeRequest::Unsatisfiable => {
+                response.status(Status::RangeNotSatisfiable);
+                response.header(Header::new("Content-Range", format!("bytes */{}", size)));
+            }
+            RangeRequest::Partial(ranges) if ranges.len() == 1 => {
+                let range = ranges[0];
+                response.status(Status::PartialContent);
+                response.header(self.content_type);
+                response.header(Header::new("Content-Range", range.content_range(size)));
+
+                let length = usize::try_from(range.len()).ok();
+                response.sized_body(length, self.body.into_reader(vec![Part::File(range)]));
+            }
+            RangeRequest::Partial(ranges) => {
+                let boundary = boundary(size);
+                let parts = multipart_parts(&ranges, size, &self.content_type, &boundary);
+                let length = parts.iter()
+                    .map(|part| match part {
+                        Part::Bytes(data) => data.len() as u64,
+                        Part::File(range) => range.len(),
+                    })
+                    .sum::<u64>();
+
+                response.status(Status::PartialContent);
+                response.header(ContentType::new("multipart", "byteranges")
+                    .with_params(("boundary", boundary)));
+                response.sized_body(usize::try_from(length).ok(), self.body.into_reader(parts));
+            }
         }
 
-        response.sized_body(self.data.len(), Cursor::new(self.data));
         Ok(response.finalize())
     }
-}
\ No newline at end of file
+}
+
+/// `attachment` disposition with a quoted ASCII fallback for old clients
+/// and the exact UTF-8 name in `filename*` (RFC 6266).
+fn content_disposition(filename: &str) -> String {
+    let fallback: String = filename.chars()
+        .map(|c| if c.is_ascii() && !c.is_ascii_control() && c != '"' && c != '\\' { c } else { '_' })
+        .collect();
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        fallback, urlencoding::encode(filename)
+    )
+}
+
+/// Lays out a `multipart/byteranges` body (RFC 9110 §14.6).
+fn multipart_parts(
+    ranges: &[ByteRange],
+    size: u64,
+    content_type: &ContentType,
+    boundary: &str,
+) -> Vec<Part> {
+    let mut parts = Vec::with_capacity(ranges.len() * 2 + 1);
+    for range in ranges {
+        let header = format!(
+            "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+            boundary, content_type, range.content_range(size)
+        );
+        parts.push(Part::Bytes(header.into_bytes()));
+        parts.push(Part::File(*range));
+    }
+    parts.push(Part::Bytes(format!("\r\n--{}--\r\n", boundary).into_bytes()));
+    parts
+}
+
+/// A boundary unlikely to occur in the payload.
+fn boundary(size: u64) -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or_default();
+    format!("comic-reader-{:016x}{:08x}", nanos, size as u32)
+}
+
+fn slice(data: &[u8], range: ByteRange) -> &[u8] {
+    &data[range.start as usize..=range.end as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::local::blocking::Client;
+
+    #[rocket::get("/body")]
+    fn body() -> BinaryResponse {
+        BinaryResponse {
+            body: b"0123456789".to_vec().into(),
+            content_type: ContentType::Plain,
+            filename: None,
+        }
+    }
+
+    fn file_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("response-file-{}", std::process::id()))
+    }
+
+    #[rocket::get("/file")]
+    async fn file() -> BinaryResponse {
+        BinaryResponse {
+            body: Body::from_file(File::open(file_path()).await.unwrap()).await.unwrap(),
+            content_type: ContentType::Plain,
+            filename: Some(String::from("Batman \"Année\" 1.cbz")),
+        }
+    }
+
+    fn client() -> Client {
+        Client::tracked(rocket::build().mount("/", rocket::routes![body, file])).unwrap()
+    }
+
+    #[test]
+    fn serves_a_single_range() {
+        let client = client();
+        let response = client.get("/body").header(Header::new("Range", "bytes=-3")).dispatch();
+
+        assert_eq!(response.status(), Status::PartialContent);
+        assert_eq!(response.headers().get_one("Content-Range"), Some("bytes 7-9/10"));
+        assert_eq!(response.into_bytes().unwrap(), b"789");
+    }
+
+    #[test]
+    fn serves_multiple_ranges_as_multipart() {
+        let client = client();
+        let response = client.get("/body").header(Header::new("Range", "bytes=0-1,5-")).dispatch();
+
+        assert_eq!(response.status(), Status::PartialContent);
+        let content_type = response.content_type().unwrap();
+        assert_eq!((content_type.top().as_str(), content_type.sub().as_str()), ("multipart", "byteranges"));
+
+        let body = response.into_string().unwrap();
+        assert!(body.contains("Content-Range: bytes 0-1/10\r\n\r\n01\r\n"));
+        assert!(body.contains("Content-Range: bytes 5-9/10\r\n\r\n56789\r\n"));
+    }
+
+    #[test]
+    fn answers_unsatisfiable_ranges_with_416() {
+        let client = client();
+        let response = client.get("/body").header(Header::new("Range", "bytes=10-")).dispatch();
+
+        assert_eq!(response.status(), Status::RangeNotSatisfiable);
+        assert_eq!(response.headers().get_one("Content-Range"), Some("bytes */10"));
+    }
+
+    #[test]
+    fn malformed_ranges_get_the_full_body() {
+        let client = client();
+        let response = client.get("/body").header(Header::new("Range", "bytes=9-1")).dispatch();
+
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_bytes().unwrap(), b"0123456789");
+    }
+
+    #[test]
+    fn escapes_the_download_filename() {
+        assert_eq!(
+            content_disposition("Batman \"Année\" 1\\2.cbz"),
+            "attachment; filename=\"Batman _Ann_e_ 1_2.cbz\"; filename*=UTF-8''Batman%20%22Ann%C3%A9e%22%201%5C2.cbz"
+        );
+    }
+
+    #[test]
+    fn resumes_files_only_on_a_strong_if_range_match() {
+        std::fs::write(file_path(), b"0123456789").unwrap();
+        let client = client();
+
+        let response = client.get("/file").dispatch();
+        let etag = response.headers().get_one("ETag").unwrap().to_string();
+        assert!(etag.starts_with('"'));
+        assert_eq!(
+            response.headers().get_one("Content-Disposition"),
+            Some("attachment; filename=\"Batman _Ann_e_ 1.cbz\"; filename*=UTF-8''Batman%20%22Ann%C3%A9e%22%201.cbz")
+        );
+
+        let response = client.get("/file")
+            .header(Header::new("Range", "bytes=8-"))
+            .header(Header::new("If-Range", etag.clone()))
+            .dispatch();
+        assert_eq!(response.status(), Status::PartialContent);
+        assert_eq!(response.into_bytes().unwrap(), b"89");
+
+        for stale in [format!("W/{}", etag), String::from("\"0-0\""), String::from("Wed, 21 Oct 2015 07:28:00 GMT")] {
+            let response = client.get("/file")
+                .header(Header::new("Range", "bytes=8-"))
+                .header(Header::new("If-Range", stale))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            assert_eq!(response.into_bytes().unwrap(), b"0123456789");
+        }
+
+        let _ = std::fs::remove_file(file_path());
+    }
+}